@@ -0,0 +1,13 @@
+precision FRAGMENT_PRECISION float;
+
+varying FRAGMENT_PRECISION vec2 v_uv;
+
+uniform sampler2D u_source;
+uniform sampler2D u_bloom;
+uniform float u_intensity;
+
+void main() {
+    vec4 color = texture2D(u_source, v_uv);
+    vec3 bloom = texture2D(u_bloom, v_uv).rgb;
+    gl_FragColor = vec4(color.rgb + bloom * u_intensity, color.a);
+}
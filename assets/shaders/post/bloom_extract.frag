@@ -0,0 +1,13 @@
+precision FRAGMENT_PRECISION float;
+
+varying FRAGMENT_PRECISION vec2 v_uv;
+
+uniform sampler2D u_source;
+uniform float u_threshold;
+
+void main() {
+    vec3 color = texture2D(u_source, v_uv).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    float contribution = max(brightness - u_threshold, 0.0) / max(brightness, 0.0001);
+    gl_FragColor = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,18 @@
+precision FRAGMENT_PRECISION float;
+
+varying FRAGMENT_PRECISION vec2 v_uv;
+
+uniform sampler2D u_source;
+uniform vec2 u_resolution;
+// the blur axis scaled by how many texels apart the taps are
+uniform vec2 u_direction;
+
+void main() {
+    vec2 tap_offset = u_direction / u_resolution;
+    vec3 color = texture2D(u_source, v_uv).rgb * 0.227027;
+    color += texture2D(u_source, v_uv + tap_offset * 1.384615).rgb * 0.316216;
+    color += texture2D(u_source, v_uv - tap_offset * 1.384615).rgb * 0.316216;
+    color += texture2D(u_source, v_uv + tap_offset * 3.230769).rgb * 0.070270;
+    color += texture2D(u_source, v_uv - tap_offset * 3.230769).rgb * 0.070270;
+    gl_FragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,11 @@
+precision FRAGMENT_PRECISION float;
+
+varying FRAGMENT_PRECISION vec2 v_uv;
+
+uniform sampler2D u_source;
+uniform float u_gamma;
+
+void main() {
+    vec4 color = texture2D(u_source, v_uv);
+    gl_FragColor = vec4(pow(max(color.rgb, 0.0), vec3(1.0 / u_gamma)), color.a);
+}
@@ -0,0 +1,16 @@
+precision FRAGMENT_PRECISION float;
+
+varying FRAGMENT_PRECISION vec2 v_uv;
+
+uniform sampler2D u_source;
+uniform float u_exposure;
+
+// narkowicz's fit of the aces filmic curve
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec4 color = texture2D(u_source, v_uv);
+    gl_FragColor = vec4(aces(color.rgb * u_exposure), color.a);
+}
@@ -0,0 +1,15 @@
+precision FRAGMENT_PRECISION float;
+
+varying FRAGMENT_PRECISION vec2 v_uv;
+
+uniform sampler2D u_source;
+uniform float u_strength;
+// distance from the center, where the corners are 1, at which darkening starts
+uniform float u_radius;
+
+void main() {
+    vec4 color = texture2D(u_source, v_uv);
+    float dist = length(v_uv - 0.5) * 1.41421356;
+    float vignette = 1.0 - smoothstep(u_radius, 1.0, dist) * u_strength;
+    gl_FragColor = vec4(color.rgb * vignette, color.a);
+}
@@ -0,0 +1,27 @@
+precision FRAGMENT_PRECISION float;
+
+varying FRAGMENT_PRECISION vec2 v_tex_coord;
+
+uniform sampler2D u_sampler;
+uniform vec4 u_base_color;
+// fragments with less alpha than this cast no shadow, 0 for materials that aren't masked
+uniform float u_alpha_cutoff;
+
+// without WEBGL_depth_texture the depth is packed into the rgba channels of a color texture
+#ifdef PACK_DEPTH
+vec4 pack_depth(float depth) {
+    vec4 encoded = fract(vec4(1.0, 255.0, 65025.0, 16581375.0) * depth);
+    return encoded - encoded.yzww * vec4(1.0 / 255.0, 1.0 / 255.0, 1.0 / 255.0, 0.0);
+}
+#endif
+
+void main() {
+    if ((texture2D(u_sampler, v_tex_coord) * u_base_color).a < u_alpha_cutoff) {
+        discard;
+    }
+#ifdef PACK_DEPTH
+    gl_FragColor = pack_depth(gl_FragCoord.z);
+#else
+    gl_FragColor = vec4(1.0);
+#endif
+}
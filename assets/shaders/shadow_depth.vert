@@ -0,0 +1,11 @@
+attribute vec4 a_position;
+attribute vec2 a_tex_coord;
+
+uniform mat4 u_light_model_view_projection;
+
+varying highp vec2 v_tex_coord;
+
+void main() {
+    v_tex_coord = a_tex_coord;
+    gl_Position = u_light_model_view_projection * a_position;
+}
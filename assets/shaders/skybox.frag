@@ -0,0 +1,9 @@
+precision mediump float;
+
+varying FRAGMENT_PRECISION vec3 v_direction;
+
+uniform samplerCube u_skybox;
+
+void main() {
+    gl_FragColor = textureCube(u_skybox, v_direction);
+}
@@ -0,0 +1,13 @@
+attribute vec3 a_position;
+
+// the view projection with the view's translation removed so the sky stays at infinity
+uniform mat4 u_view_projection;
+
+varying highp vec3 v_direction;
+
+void main() {
+    v_direction = a_position;
+    vec4 position = u_view_projection * vec4(a_position, 1.0);
+    // z = w puts every fragment on the far plane, behind everything else
+    gl_Position = position.xyww;
+}
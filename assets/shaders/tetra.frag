@@ -1,9 +1,210 @@
-varying highp vec2 v_tex_coord;
-varying highp vec3 v_lighting;
+#extension GL_EXT_shader_texture_lod : enable
+
+#define MAX_LIGHTS 8
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+#define MAX_SHADOW_MAPS 2
+#define MAX_PCF_RADIUS 2
+#define RGBM_RANGE 8.0
+#define ALPHA_OPAQUE 0
+#define ALPHA_MASK 1
+#define ALPHA_BLEND 2
+
+precision FRAGMENT_PRECISION float;
+
+varying FRAGMENT_PRECISION vec2 v_tex_coord;
+varying FRAGMENT_PRECISION vec3 v_view_position;
+varying FRAGMENT_PRECISION vec3 v_normal;
+varying FRAGMENT_PRECISION vec3 v_world_position;
+varying FRAGMENT_PRECISION vec4 v_instance_color;
 
 uniform sampler2D u_sampler;
+// whether u_sampler holds sRGB colors the context doesn't decode when sampling
+uniform bool u_decode_srgb;
+
+uniform vec3 u_ambient_light;
+uniform int u_light_count;
+uniform int u_light_kind[MAX_LIGHTS];
+uniform vec3 u_light_color[MAX_LIGHTS];
+uniform vec3 u_light_position[MAX_LIGHTS];
+uniform vec3 u_light_direction[MAX_LIGHTS];
+uniform float u_light_range[MAX_LIGHTS];
+// cosines of the inner and outer cone angles of spot lights
+uniform vec2 u_light_cone[MAX_LIGHTS];
+
+uniform int u_shadow_count;
+// the index of the light each shadow map belongs to
+uniform int u_shadow_light[MAX_SHADOW_MAPS];
+uniform mat4 u_shadow_matrix[MAX_SHADOW_MAPS];
+uniform float u_shadow_bias[MAX_SHADOW_MAPS];
+uniform sampler2D u_shadow_map[MAX_SHADOW_MAPS];
+uniform float u_shadow_map_size;
+uniform int u_shadow_pcf_radius;
+// whether depth is packed into rgba because depth textures are unsupported
+uniform bool u_shadow_packed;
+
+uniform vec4 u_base_color;
+uniform float u_metallic;
+uniform float u_roughness;
+uniform int u_alpha_mode;
+// masked fragments with less alpha than this are discarded
+uniform float u_alpha_cutoff;
+
+uniform bool u_ibl_enabled;
+uniform float u_ibl_intensity;
+// irradiance spherical harmonics, already convolved and divided by pi
+uniform vec3 u_irradiance_sh[9];
+// the environment prefiltered for increasing roughness down its mip chain
+uniform samplerCube u_specular_env;
+uniform float u_specular_max_lod;
+// whether the specular environment is rgbm encoded because float textures are unsupported
+uniform bool u_specular_rgbm;
+uniform sampler2D u_brdf_lut;
+uniform mat4 u_view_to_world;
+
+vec3 srgb_to_linear(vec3 srgb) {
+    vec3 low = srgb / 12.92;
+    vec3 high = pow((srgb + 0.055) / 1.055, vec3(2.4));
+    return mix(low, high, step(vec3(0.04045), srgb));
+}
+
+float range_attenuation(float range, float dist) {
+    float attenuation = 1.0 / max(dist * dist, 0.0001);
+    if (range <= 0.0) {
+        return attenuation;
+    }
+    float ratio = dist / range;
+    return clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0) * attenuation;
+}
+
+float unpack_depth(vec4 packed_depth) {
+    return dot(packed_depth, vec4(1.0, 1.0 / 255.0, 1.0 / 65025.0, 1.0 / 16581375.0));
+}
+
+float shadow_map_depth(sampler2D shadow_map, vec2 uv) {
+    vec4 texel = texture2D(shadow_map, uv);
+    return u_shadow_packed ? unpack_depth(texel) : texel.r;
+}
+
+// the fraction of pcf samples around the fragment that the light reaches
+float shadow_factor(sampler2D shadow_map, mat4 shadow_matrix, float bias) {
+    vec4 light_space = shadow_matrix * vec4(v_world_position, 1.0);
+    vec3 coords = light_space.xyz / light_space.w * 0.5 + 0.5;
+    if (coords.x < 0.0 || coords.x > 1.0 || coords.y < 0.0 || coords.y > 1.0 || coords.z > 1.0) {
+        return 1.0;
+    }
+    float texel_size = 1.0 / u_shadow_map_size;
+    float pcf_radius = float(u_shadow_pcf_radius);
+    float lit = 0.0;
+    float samples = 0.0;
+    for (int x = -MAX_PCF_RADIUS; x <= MAX_PCF_RADIUS; x++) {
+        for (int y = -MAX_PCF_RADIUS; y <= MAX_PCF_RADIUS; y++) {
+            vec2 offset = vec2(float(x), float(y));
+            if (abs(offset.x) > pcf_radius || abs(offset.y) > pcf_radius) {
+                continue;
+            }
+            float depth = shadow_map_depth(shadow_map, coords.xy + offset * texel_size);
+            lit += coords.z - bias > depth ? 0.0 : 1.0;
+            samples += 1.0;
+        }
+    }
+    return lit / samples;
+}
+
+vec3 sh_irradiance(vec3 n) {
+    return u_irradiance_sh[0] * 0.282095
+        + u_irradiance_sh[1] * 0.488603 * n.y
+        + u_irradiance_sh[2] * 0.488603 * n.z
+        + u_irradiance_sh[3] * 0.488603 * n.x
+        + u_irradiance_sh[4] * 1.092548 * n.x * n.y
+        + u_irradiance_sh[5] * 1.092548 * n.y * n.z
+        + u_irradiance_sh[6] * 0.315392 * (3.0 * n.z * n.z - 1.0)
+        + u_irradiance_sh[7] * 1.092548 * n.x * n.z
+        + u_irradiance_sh[8] * 0.546274 * (n.x * n.x - n.y * n.y);
+}
+
+vec3 specular_radiance(vec3 direction, float lod) {
+#ifdef GL_EXT_shader_texture_lod
+    vec4 texel = textureCubeLodEXT(u_specular_env, direction, lod);
+#else
+    // without explicit lods a bias is the closest approximation
+    vec4 texel = textureCube(u_specular_env, direction, lod);
+#endif
+    return u_specular_rgbm ? texel.rgb * texel.a * RGBM_RANGE : max(texel.rgb, 0.0);
+}
+
+// split sum image based lighting for a view space normal and direction to the eye
+vec3 image_based_lighting(vec3 albedo, vec3 normal, vec3 to_eye) {
+    vec3 world_normal = normalize((u_view_to_world * vec4(normal, 0.0)).xyz);
+    vec3 reflected = (u_view_to_world * vec4(reflect(-to_eye, normal), 0.0)).xyz;
+    float n_dot_v = clamp(dot(normal, to_eye), 0.0, 1.0);
+    float roughness = clamp(u_roughness, 0.0, 1.0);
+
+    vec3 diffuse = sh_irradiance(world_normal) * albedo * (1.0 - u_metallic);
+    vec3 f0 = mix(vec3(0.04), albedo, u_metallic);
+    vec2 brdf = texture2D(u_brdf_lut, vec2(n_dot_v, roughness)).rg;
+    vec3 prefiltered = specular_radiance(reflected, roughness * u_specular_max_lod);
+    vec3 specular = prefiltered * (f0 * brdf.x + brdf.y);
+    return diffuse + specular;
+}
 
 void main() {
-    highp vec4 texel_color = texture2D(u_sampler, v_tex_coord);
-    gl_FragColor = vec4(texel_color.rgb * v_lighting, texel_color.a);
+    vec3 normal = normalize(v_normal);
+    vec3 lighting = u_ambient_light;
+    // unrolled for each of the MAX_SHADOW_MAPS, since glsl es 3.00 only allows sampler arrays
+    // to be indexed by constants
+    float shadows[MAX_SHADOW_MAPS];
+    shadows[0] = 1.0;
+    shadows[1] = 1.0;
+    if (u_shadow_count > 0) {
+        shadows[0] = shadow_factor(u_shadow_map[0], u_shadow_matrix[0], u_shadow_bias[0]);
+    }
+    if (u_shadow_count > 1) {
+        shadows[1] = shadow_factor(u_shadow_map[1], u_shadow_matrix[1], u_shadow_bias[1]);
+    }
+    for (int i = 0; i < MAX_LIGHTS; i++) {
+        if (i >= u_light_count) {
+            break;
+        }
+        // the body is inlined rather than a function taking the index, since webgl only
+        // guarantees uniform arrays can be indexed by loop indices in fragment shaders
+        vec3 to_light;
+        float attenuation = 1.0;
+        if (u_light_kind[i] == LIGHT_DIRECTIONAL) {
+            to_light = -u_light_direction[i];
+        } else {
+            vec3 offset = u_light_position[i] - v_view_position;
+            float dist = length(offset);
+            to_light = offset / dist;
+            attenuation = range_attenuation(u_light_range[i], dist);
+            if (u_light_kind[i] == LIGHT_SPOT) {
+                float cos_angle = dot(-to_light, u_light_direction[i]);
+                attenuation *= smoothstep(u_light_cone[i].y, u_light_cone[i].x, cos_angle);
+            }
+        }
+        for (int s = 0; s < MAX_SHADOW_MAPS; s++) {
+            if (s < u_shadow_count && u_shadow_light[s] == i) {
+                attenuation *= shadows[s];
+            }
+        }
+        float diffuse = max(dot(normal, to_light), 0.0);
+        lighting += u_light_color[i] * diffuse * attenuation;
+    }
+
+    vec4 texel = texture2D(u_sampler, v_tex_coord);
+    if (u_decode_srgb) {
+        texel.rgb = srgb_to_linear(texel.rgb);
+    }
+    vec4 texel_color = texel * u_base_color * v_instance_color;
+    if (u_alpha_mode == ALPHA_MASK && texel_color.a < u_alpha_cutoff) {
+        discard;
+    }
+    float alpha = u_alpha_mode == ALPHA_BLEND ? texel_color.a : 1.0;
+    vec3 color = texel_color.rgb * lighting;
+    if (u_ibl_enabled) {
+        vec3 to_eye = normalize(-v_view_position);
+        color += image_based_lighting(texel_color.rgb, normal, to_eye) * u_ibl_intensity;
+    }
+    gl_FragColor = vec4(color, alpha);
 }
@@ -1,23 +1,29 @@
 attribute vec4 a_position;
 attribute vec3 a_normal;
 attribute vec2 a_tex_coord;
+// per instance, or constant identity and white when not instancing. Instance transforms are
+// assumed to scale uniformly so normals only need renormalizing
+attribute mat4 a_instance_model;
+attribute vec4 a_instance_color;
 
+uniform mat4 u_model;
 uniform mat4 u_normal_matrix;
+uniform mat4 u_model_view;
 uniform mat4 u_model_view_projection;
 
 varying highp vec2 v_tex_coord;
-varying highp vec3 v_lighting;
+varying highp vec3 v_view_position;
+varying highp vec3 v_normal;
+varying highp vec3 v_world_position;
+varying highp vec4 v_instance_color;
 
 void main() {
-    gl_Position = u_model_view_projection * a_position;
-    
-    v_tex_coord = a_tex_coord;
-
-    highp vec3 ambient_light = vec3(0.3, 0.3, 0.3);
-    highp vec3 directional_light_color = vec3(1, 1, 1);
-    highp vec3 directional_vector = normalize(vec3(0.0, 1.0, 0.5));
+    vec4 position = a_instance_model * a_position;
+    gl_Position = u_model_view_projection * position;
 
-    highp vec4 transformed_normal = u_normal_matrix * vec4(a_normal, 1.0);
-    highp float directional = max(dot(transformed_normal.xyz, directional_vector), 0.0);
-    v_lighting = ambient_light + (directional_light_color * directional);
+    v_tex_coord = a_tex_coord;
+    v_view_position = (u_model_view * position).xyz;
+    v_normal = normalize((u_normal_matrix * a_instance_model * vec4(a_normal, 0.0)).xyz);
+    v_world_position = (u_model * position).xyz;
+    v_instance_color = a_instance_color;
 }
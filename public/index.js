@@ -15,20 +15,22 @@ async function main() {
   const canvas = document.getElementById('webgl');
 
   const wasmModule = await wasmModulePromise;
-  const {Tetra} = wasmModule;
+  const {Tetra, ImageDecoding, fetch_gltf: fetchGltf} = wasmModule;
   const fetchPromises = [vertexPath, fragPath].map(async (path) => {
     return await (await fetch(path)).text();
   });
-  fetchPromises.push((async () => {
-    return new Uint8Array(await (await fetch(modelPath)).arrayBuffer());
-  })());
+  // the model and any files it references are fetched on the rust side, with the browser
+  // decoding its images off the main thread
+  fetchPromises.push(fetchGltf(modelPath, (progress) => {
+    console.log(`loaded ${progress.loaded} of ${progress.total} model files`);
+  }, ImageDecoding.ImageBitmap));
   // get shader sources
   const [vertexSource, fragSource, model] = await Promise.all(fetchPromises);
   const tetra = new Tetra(canvas)
       .add_vert_shader(vertexSource)
       .add_frag_shader(fragSource)
-      .link_program()
-      .load_gltf(model);
+      .link_program();
+  tetra.add_model('model', model);
 
   const resize = () => {
     canvas.width = window.innerWidth;
@@ -38,6 +40,19 @@ async function main() {
   window.addEventListener('resize', resize);
   resize();
 
+  // press p to download a screenshot
+  window.addEventListener('keydown', (event) => {
+    if (event.key !== 'p') {
+      return;
+    }
+    const png = tetra.render_to_png(canvas.width, canvas.height);
+    const link = document.createElement('a');
+    link.href = URL.createObjectURL(new Blob([png], {type: 'image/png'}));
+    link.download = 'screenshot.png';
+    link.click();
+    URL.revokeObjectURL(link.href);
+  });
+
   /**
    * a single draw step
    * @param {DOMHighResTimeStamp} timestamp
@@ -0,0 +1,104 @@
+use gltf::animation::util::ReadOutputs;
+use gltf::animation::Property;
+
+use super::{Interpolation, Sampler};
+use crate::resources::MeshHandle;
+
+/// an animated set of morph target weights for every primitive of a mesh
+#[derive(Debug, Clone)]
+pub struct WeightsChannel {
+    pub mesh: MeshHandle,
+    pub sampler: Sampler,
+}
+
+/// a named animation made up of channels that all play on the same timeline
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    name: String,
+    duration: f32,
+    weights_channels: Vec<WeightsChannel>,
+}
+
+impl AnimationClip {
+    pub fn new(name: String, weights_channels: Vec<WeightsChannel>) -> AnimationClip {
+        let duration = weights_channels
+            .iter()
+            .map(|channel| channel.sampler.duration())
+            .fold(0.0, f32::max);
+        AnimationClip {
+            name,
+            duration,
+            weights_channels,
+        }
+    }
+
+    /// read the channels of a glTF animation that this renderer knows how to play, animating
+    /// `meshes`, the handles the file's meshes were loaded as. Channels targeting nodes without
+    /// a mesh are skipped
+    pub fn from_gltf(
+        animation: &gltf::Animation,
+        buffers: &[gltf::buffer::Data],
+        meshes: &[MeshHandle],
+    ) -> Result<AnimationClip, String> {
+        let name = animation
+            .name()
+            .map(String::from)
+            .unwrap_or_else(|| format!("animation {}", animation.index()));
+        let mut weights_channels = Vec::new();
+        for channel in animation.channels() {
+            if channel.target().property() != Property::MorphTargetWeights {
+                continue;
+            }
+            let mesh = match channel.target().node().mesh() {
+                Some(mesh) => *meshes
+                    .get(mesh.index())
+                    .ok_or("an animation channel targets a mesh that wasn't loaded")?,
+                None => continue,
+            };
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let times: Vec<f32> = reader
+                .read_inputs()
+                .ok_or("animation channel has no input times")?
+                .collect();
+            let values: Vec<f32> = match reader.read_outputs() {
+                Some(ReadOutputs::MorphTargetWeights(weights)) => weights.into_f32().collect(),
+                _ => return Err("weights channel has no weight outputs".into()),
+            };
+            let interpolation = Interpolation::from(channel.sampler().interpolation());
+            let keys_per_time = match interpolation {
+                Interpolation::CubicSpline => 3,
+                _ => 1,
+            };
+            if times.is_empty() {
+                continue;
+            }
+            let width = values.len() / (times.len() * keys_per_time);
+            weights_channels.push(WeightsChannel {
+                mesh,
+                sampler: Sampler::new(interpolation, times, values, width)?,
+            });
+        }
+        Ok(AnimationClip::new(name, weights_channels))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// the length of the clip in seconds
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    pub fn weights_channels(&self) -> &[WeightsChannel] {
+        &self.weights_channels
+    }
+
+    /// the morph target weights of `mesh` at `time`, if the clip animates them
+    pub fn sample_weights(&self, mesh: MeshHandle, time: f32) -> Option<Vec<f32>> {
+        self.weights_channels
+            .iter()
+            .find(|channel| channel.mesh == mesh)
+            .map(|channel| channel.sampler.sample(time))
+    }
+}
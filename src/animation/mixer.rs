@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+
+use super::AnimationClip;
+use crate::resources::MeshHandle;
+
+/// the sampled result of everything the mixer is playing: morph target weights keyed by the
+/// mesh they belong to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pose {
+    pub weights: HashMap<MeshHandle, Vec<f32>>,
+}
+
+/// a single clip being played by the mixer
+#[derive(Debug, Clone)]
+struct Action {
+    clip: usize,
+    time: f32,
+    looping: bool,
+    weight: f32,
+    fade: Option<Fade>,
+}
+
+/// a linear ramp of an action's weight from `from` to `to` over `duration` seconds
+#[derive(Debug, Clone)]
+struct Fade {
+    from: f32,
+    to: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+/// plays and blends animation clips. The mixer only refers to clips by index and has to be
+/// handed the clips when advancing or sampling, so it never touches gl and can be driven with
+/// plain time steps
+#[derive(Debug, Clone)]
+pub struct AnimationMixer {
+    actions: Vec<Action>,
+    speed: f32,
+}
+
+impl Default for AnimationMixer {
+    fn default() -> Self {
+        AnimationMixer::new()
+    }
+}
+
+impl AnimationMixer {
+    pub fn new() -> AnimationMixer {
+        AnimationMixer {
+            actions: Vec::new(),
+            speed: 1.0,
+        }
+    }
+
+    /// stop whatever is playing and play `clip` from the start at full weight
+    pub fn play(&mut self, clip: usize, looping: bool) {
+        self.actions.clear();
+        self.actions.push(Action {
+            clip,
+            time: 0.0,
+            looping,
+            weight: 1.0,
+            fade: None,
+        });
+    }
+
+    /// fade every playing clip out while `clip` fades in from the start over `duration` seconds
+    pub fn crossfade(&mut self, clip: usize, duration: f32, looping: bool) {
+        if !duration.is_finite() || duration <= 0.0 || self.actions.is_empty() {
+            self.play(clip, looping);
+            return;
+        }
+        for action in &mut self.actions {
+            action.fade = Some(Fade {
+                from: action.weight,
+                to: 0.0,
+                duration,
+                elapsed: 0.0,
+            });
+        }
+        self.actions.push(Action {
+            clip,
+            time: 0.0,
+            looping,
+            weight: 0.0,
+            fade: Some(Fade {
+                from: 0.0,
+                to: 1.0,
+                duration,
+                elapsed: 0.0,
+            }),
+        });
+    }
+
+    pub fn stop(&mut self) {
+        self.actions.clear();
+    }
+
+    /// stop playing `clip` when it's removed from the clips, shifting the clips after it down
+    pub fn remove_clip(&mut self, clip: usize) {
+        self.actions.retain(|action| action.clip != clip);
+        for action in &mut self.actions {
+            if action.clip > clip {
+                action.clip -= 1;
+            }
+        }
+    }
+
+    /// whether any clip is currently playing
+    pub fn is_playing(&self) -> bool {
+        !self.actions.is_empty()
+    }
+
+    /// the clip that was most recently started, if any
+    pub fn current_clip(&self) -> Option<usize> {
+        self.actions.last().map(|action| action.clip)
+    }
+
+    /// the playback position in seconds of the most recently started clip
+    pub fn current_time(&self) -> f32 {
+        self.actions.last().map_or(0.0, |action| action.time)
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// set the playback rate, where 1.0 is real time and negative values play backwards
+    pub fn set_speed(&mut self, speed: f32) -> Result<(), String> {
+        if !speed.is_finite() {
+            return Err(format!("animation speed has to be finite, not {}", speed));
+        }
+        self.speed = speed;
+        Ok(())
+    }
+
+    /// advance every action by `delta` seconds of real time, finishing fades and dropping clips
+    /// that have faded out. Clips that don't loop hold their last frame once they reach the end
+    pub fn update(&mut self, clips: &[AnimationClip], delta: f32) {
+        if !delta.is_finite() {
+            return;
+        }
+        let step = delta * self.speed;
+        for action in &mut self.actions {
+            let duration = clips.get(action.clip).map_or(0.0, AnimationClip::duration);
+            action.time += step;
+            if action.looping && duration > 0.0 {
+                action.time = action.time.rem_euclid(duration);
+            } else {
+                action.time = action.time.max(0.0).min(duration);
+            }
+            if let Some(ref mut fade) = action.fade {
+                fade.elapsed += delta;
+                let t = (fade.elapsed / fade.duration).min(1.0);
+                action.weight = fade.from + (fade.to - fade.from) * t;
+                if t >= 1.0 {
+                    action.fade = None;
+                }
+            }
+        }
+        self.actions
+            .retain(|action| action.weight > 0.0 || action.fade.is_some());
+    }
+
+    /// sample every playing clip and blend the results by action weight. Meshes only animated by
+    /// some of the actions are blended over just those actions
+    pub fn sample(&self, clips: &[AnimationClip]) -> Pose {
+        let mut totals: HashMap<MeshHandle, (Vec<f32>, f32)> = HashMap::new();
+        for action in &self.actions {
+            let clip = match clips.get(action.clip) {
+                Some(clip) => clip,
+                None => continue,
+            };
+            for channel in clip.weights_channels() {
+                let sampled = channel.sampler.sample(action.time);
+                let (sum, total_weight) = totals
+                    .entry(channel.mesh)
+                    .or_insert_with(|| (vec![0.0; sampled.len()], 0.0));
+                if sum.len() < sampled.len() {
+                    sum.resize(sampled.len(), 0.0);
+                }
+                for (s, v) in sum.iter_mut().zip(&sampled) {
+                    *s += v * action.weight;
+                }
+                *total_weight += action.weight;
+            }
+        }
+        let weights = totals
+            .into_iter()
+            .filter(|(_, (_, total_weight))| *total_weight > 0.0)
+            .map(|(mesh, (sum, total_weight))| {
+                (mesh, sum.into_iter().map(|s| s / total_weight).collect())
+            })
+            .collect();
+        Pose { weights }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{Interpolation, Sampler, WeightsChannel};
+    use crate::resources::Handle;
+
+    fn mesh(index: u32) -> MeshHandle {
+        MeshHandle::new(index, 0)
+    }
+
+    /// a two second clip moving the single weight of `mesh` linearly from `from` to `to`
+    fn ramp(mesh: MeshHandle, from: f32, to: f32) -> AnimationClip {
+        let sampler = Sampler::new(Interpolation::Linear, vec![0.0, 2.0], vec![from, to], 1);
+        AnimationClip::new(
+            "ramp".into(),
+            vec![WeightsChannel {
+                mesh,
+                sampler: sampler.unwrap(),
+            }],
+        )
+    }
+
+    fn weight(mixer: &AnimationMixer, clips: &[AnimationClip], mesh: MeshHandle) -> Option<f32> {
+        mixer
+            .sample(clips)
+            .weights
+            .get(&mesh)
+            .map(|weights| weights[0])
+    }
+
+    fn assert_weight(mixer: &AnimationMixer, clips: &[AnimationClip], expected: f32) {
+        let weight = weight(mixer, clips, mesh(0)).expect("the mesh isn't animated");
+        assert!(
+            (weight - expected).abs() < 1e-5,
+            "expected a weight of {} but got {}",
+            expected,
+            weight
+        );
+    }
+
+    #[test]
+    fn play_samples_from_the_start() {
+        let clips = [ramp(mesh(0), 0.0, 1.0)];
+        let mut mixer = AnimationMixer::new();
+        mixer.play(0, false);
+        assert_weight(&mixer, &clips, 0.0);
+        mixer.update(&clips, 0.5);
+        assert_weight(&mixer, &clips, 0.25);
+        assert_eq!(weight(&mixer, &clips, mesh(1)), None);
+    }
+
+    #[test]
+    fn stop_clears_the_pose() {
+        let clips = [ramp(mesh(0), 0.0, 1.0)];
+        let mut mixer = AnimationMixer::new();
+        mixer.play(0, true);
+        mixer.update(&clips, 0.5);
+        mixer.stop();
+        assert!(!mixer.is_playing());
+        assert_eq!(mixer.sample(&clips), Pose::default());
+    }
+
+    #[test]
+    fn looping_wraps_around() {
+        let clips = [ramp(mesh(0), 0.0, 1.0)];
+        let mut mixer = AnimationMixer::new();
+        mixer.play(0, true);
+        mixer.update(&clips, 2.5);
+        assert!((mixer.current_time() - 0.5).abs() < 1e-5);
+        assert_weight(&mixer, &clips, 0.25);
+    }
+
+    #[test]
+    fn clips_that_dont_loop_hold_their_last_frame() {
+        let clips = [ramp(mesh(0), 0.0, 1.0)];
+        let mut mixer = AnimationMixer::new();
+        mixer.play(0, false);
+        mixer.update(&clips, 2.5);
+        assert!(mixer.is_playing());
+        assert_weight(&mixer, &clips, 1.0);
+    }
+
+    #[test]
+    fn crossfade_blends_by_weight() {
+        let clips = [ramp(mesh(0), 1.0, 1.0), ramp(mesh(0), 0.0, 0.0)];
+        let mut mixer = AnimationMixer::new();
+        mixer.play(0, true);
+        mixer.crossfade(1, 1.0, true);
+        assert_weight(&mixer, &clips, 1.0);
+        mixer.update(&clips, 0.5);
+        assert_weight(&mixer, &clips, 0.5);
+        mixer.update(&clips, 0.25);
+        assert_weight(&mixer, &clips, 0.25);
+        mixer.update(&clips, 0.25);
+        assert_weight(&mixer, &clips, 0.0);
+        assert_eq!(mixer.current_clip(), Some(1));
+    }
+
+    #[test]
+    fn speed_scales_time() {
+        let clips = [ramp(mesh(0), 0.0, 1.0)];
+        let mut mixer = AnimationMixer::new();
+        mixer.set_speed(2.0).unwrap();
+        mixer.play(0, true);
+        mixer.update(&clips, 0.25);
+        assert_weight(&mixer, &clips, 0.25);
+    }
+
+    #[test]
+    fn negative_speed_plays_backwards() {
+        let clips = [ramp(mesh(0), 0.0, 1.0)];
+        let mut mixer = AnimationMixer::new();
+        mixer.set_speed(-1.0).unwrap();
+        mixer.play(0, true);
+        mixer.update(&clips, 0.5);
+        assert_weight(&mixer, &clips, 0.75);
+
+        mixer.play(0, false);
+        mixer.update(&clips, 0.5);
+        assert_weight(&mixer, &clips, 0.0);
+    }
+
+    #[test]
+    fn zero_speed_pauses() {
+        let clips = [ramp(mesh(0), 0.0, 1.0)];
+        let mut mixer = AnimationMixer::new();
+        mixer.play(0, true);
+        mixer.update(&clips, 0.5);
+        mixer.set_speed(0.0).unwrap();
+        mixer.update(&clips, 1.0);
+        assert_weight(&mixer, &clips, 0.25);
+    }
+
+    #[test]
+    fn non_finite_input_is_rejected() {
+        let clips = [ramp(mesh(0), 0.0, 1.0)];
+        let mut mixer = AnimationMixer::new();
+        assert!(mixer.set_speed(f32::NAN).is_err());
+        assert!(mixer.set_speed(f32::INFINITY).is_err());
+        assert_eq!(mixer.speed(), 1.0);
+        mixer.play(0, true);
+        mixer.update(&clips, f32::NAN);
+        assert_weight(&mixer, &clips, 0.0);
+    }
+
+    #[test]
+    fn removing_a_clip_shifts_the_rest() {
+        let mut mixer = AnimationMixer::new();
+        mixer.play(1, true);
+        mixer.remove_clip(0);
+        assert_eq!(mixer.current_clip(), Some(0));
+        mixer.remove_clip(0);
+        assert!(!mixer.is_playing());
+    }
+}
@@ -0,0 +1,11 @@
+mod sampler;
+pub use sampler::{Interpolation, Sampler};
+
+mod morph;
+pub use morph::MorphTargets;
+
+mod clip;
+pub use clip::{AnimationClip, WeightsChannel};
+
+mod mixer;
+pub use mixer::{AnimationMixer, Pose};
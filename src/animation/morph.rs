@@ -0,0 +1,102 @@
+/// weights below this are treated as zero so inactive targets don't cost anything to blend
+const WEIGHT_EPSILON: f32 = 1e-5;
+
+/// the morph targets (blend shapes) of a single mesh primitive. The base attributes and every
+/// target's deltas are kept on the cpu so blended attributes can be rebuilt whenever the weights
+/// change, which keeps the shaders unaware of morphing and puts no limit on the target count
+#[derive(Debug, Clone)]
+pub struct MorphTargets {
+    base_positions: Vec<f32>,
+    base_normals: Vec<f32>,
+    position_deltas: Vec<Vec<f32>>,
+    normal_deltas: Vec<Vec<f32>>,
+    default_weights: Vec<f32>,
+}
+
+impl MorphTargets {
+    /// create a new set of morph targets
+    /// # Arguments
+    /// * `base_positions` / `base_normals` - the unmorphed, flattened vertex attributes
+    /// * `position_deltas` / `normal_deltas` - one flattened delta array per target, empty if
+    ///   the target doesn't move that attribute
+    /// * `default_weights` - the weights used when nothing is animating the mesh
+    pub fn new(
+        base_positions: Vec<f32>,
+        base_normals: Vec<f32>,
+        position_deltas: Vec<Vec<f32>>,
+        normal_deltas: Vec<Vec<f32>>,
+        default_weights: Vec<f32>,
+    ) -> Result<MorphTargets, String> {
+        let count = position_deltas.len();
+        if normal_deltas.len() != count {
+            return Err("every morph target needs an entry for both positions and normals".into());
+        }
+        let check = |deltas: &[Vec<f32>], base: &[f32], name: &str| match deltas
+            .iter()
+            .find(|delta| !delta.is_empty() && delta.len() != base.len())
+        {
+            Some(delta) => Err(format!(
+                "morph target {} deltas have length {} but the base has {}",
+                name,
+                delta.len(),
+                base.len()
+            )),
+            None => Ok(()),
+        };
+        check(&position_deltas, &base_positions, "position")?;
+        check(&normal_deltas, &base_normals, "normal")?;
+        let mut default_weights = default_weights;
+        default_weights.resize(count, 0.0);
+        Ok(MorphTargets {
+            base_positions,
+            base_normals,
+            position_deltas,
+            normal_deltas,
+            default_weights,
+        })
+    }
+
+    /// the number of morph targets
+    pub fn len(&self) -> usize {
+        self.position_deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position_deltas.is_empty()
+    }
+
+    pub fn default_weights(&self) -> &[f32] {
+        &self.default_weights
+    }
+
+    /// the base positions plus every target's position deltas scaled by its weight
+    pub fn blend_positions(&self, weights: &[f32]) -> Vec<f32> {
+        blend(&self.base_positions, &self.position_deltas, weights)
+    }
+
+    /// like `blend_positions` but for normals, which are renormalized after blending
+    pub fn blend_normals(&self, weights: &[f32]) -> Vec<f32> {
+        let mut normals = blend(&self.base_normals, &self.normal_deltas, weights);
+        for normal in normals.chunks_exact_mut(3) {
+            let length =
+                (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+            if length > 0.0 {
+                normal.iter_mut().for_each(|n| *n /= length);
+            }
+        }
+        normals
+    }
+}
+
+fn blend(base: &[f32], deltas: &[Vec<f32>], weights: &[f32]) -> Vec<f32> {
+    let mut blended = base.to_vec();
+    for (delta, &weight) in deltas.iter().zip(weights) {
+        if weight.abs() < WEIGHT_EPSILON || delta.is_empty() {
+            continue;
+        }
+        for (b, d) in blended.iter_mut().zip(delta) {
+            *b += d * weight;
+        }
+    }
+    blended
+}
@@ -0,0 +1,159 @@
+/// how a sampler fills in values between two keyframes, mirroring the glTF interpolation modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    CubicSpline,
+}
+
+impl From<gltf::animation::Interpolation> for Interpolation {
+    fn from(interpolation: gltf::animation::Interpolation) -> Self {
+        match interpolation {
+            gltf::animation::Interpolation::Step => Interpolation::Step,
+            gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        }
+    }
+}
+
+/// a keyframe track of fixed width float values, e.g. one weight per morph target. For cubic
+/// splines every keyframe stores an in tangent, the value and an out tangent in that order, the
+/// same layout glTF uses
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    interpolation: Interpolation,
+    times: Vec<f32>,
+    values: Vec<f32>,
+    width: usize,
+}
+
+impl Sampler {
+    /// create a new sampler
+    /// # Arguments
+    /// * `interpolation` - the interpolation used between keyframes
+    /// * `times` - the keyframe times in seconds, in increasing order
+    /// * `values` - the flattened keyframe values
+    /// * `width` - how many floats make up a single value
+    pub fn new(
+        interpolation: Interpolation,
+        times: Vec<f32>,
+        values: Vec<f32>,
+        width: usize,
+    ) -> Result<Sampler, String> {
+        let per_key = match interpolation {
+            Interpolation::CubicSpline => 3 * width,
+            _ => width,
+        };
+        if times.is_empty() {
+            return Err("sampler has no keyframes".into());
+        }
+        if values.len() != times.len() * per_key {
+            return Err(format!(
+                "sampler expected {} values for {} keyframes but got {}",
+                times.len() * per_key,
+                times.len(),
+                values.len()
+            ));
+        }
+        Ok(Sampler {
+            interpolation,
+            times,
+            values,
+            width,
+        })
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// the number of floats in a single sampled value
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// the time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        *self.times.last().unwrap()
+    }
+
+    /// sample the track at `time`, clamping to the first and last keyframes
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let mut out = vec![0.0; self.width];
+        self.sample_into(time, &mut out);
+        out
+    }
+
+    /// sample the track at `time` into `out`, which must be `width` long
+    pub fn sample_into(&self, time: f32, out: &mut [f32]) {
+        let last = self.times.len() - 1;
+        // NaN fails every comparison below, so it's treated like a time before the first key
+        if !time.is_finite() || time <= self.times[0] || last == 0 {
+            out.copy_from_slice(self.value(0));
+            return;
+        }
+        if time >= self.times[last] {
+            out.copy_from_slice(self.value(last));
+            return;
+        }
+        // index of the keyframe at or before `time`
+        let prev = self.times.partition_point(|&t| t <= time) - 1;
+        let next = prev + 1;
+        let delta = self.times[next] - self.times[prev];
+        let t = (time - self.times[prev]) / delta;
+        match self.interpolation {
+            Interpolation::Step => out.copy_from_slice(self.value(prev)),
+            Interpolation::Linear => {
+                let (a, b) = (self.value(prev), self.value(next));
+                for (i, o) in out.iter_mut().enumerate() {
+                    *o = a[i] + (b[i] - a[i]) * t;
+                }
+            }
+            Interpolation::CubicSpline => {
+                let (t2, t3) = (t * t, t * t * t);
+                let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+                let h10 = t3 - 2.0 * t2 + t;
+                let h01 = -2.0 * t3 + 3.0 * t2;
+                let h11 = t3 - t2;
+                let (p0, m0) = (self.value(prev), self.out_tangent(prev));
+                let (p1, m1) = (self.value(next), self.in_tangent(next));
+                for (i, o) in out.iter_mut().enumerate() {
+                    *o = h00 * p0[i] + h10 * delta * m0[i] + h01 * p1[i] + h11 * delta * m1[i];
+                }
+            }
+        }
+    }
+
+    fn key(&self, index: usize, offset: usize) -> &[f32] {
+        let start = match self.interpolation {
+            Interpolation::CubicSpline => (index * 3 + offset) * self.width,
+            _ => index * self.width,
+        };
+        &self.values[start..start + self.width]
+    }
+
+    fn in_tangent(&self, index: usize) -> &[f32] {
+        self.key(index, 0)
+    }
+
+    fn value(&self, index: usize) -> &[f32] {
+        self.key(index, 1)
+    }
+
+    fn out_tangent(&self, index: usize) -> &[f32] {
+        self.key(index, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_finite_times_sample_the_first_key() {
+        let sampler = Sampler::new(Interpolation::Linear, vec![0.0, 1.0], vec![2.0, 4.0], 1);
+        let sampler = sampler.unwrap();
+        assert_eq!(sampler.sample(f32::NAN), vec![2.0]);
+        assert_eq!(sampler.sample(0.5), vec![3.0]);
+    }
+}
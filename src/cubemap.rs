@@ -0,0 +1,112 @@
+use nalgebra as na;
+
+/// the direction through texel (`s`, `t`) of cube face `face`, where `s` and `t` run from -1
+/// to 1 across the face, top-down, and faces are in +X, -X, +Y, -Y, +Z, -Z order
+pub fn cube_face_direction(face: usize, s: f32, t: f32) -> na::Vector3<f32> {
+    let direction = match face {
+        0 => na::Vector3::new(1.0, -t, -s),
+        1 => na::Vector3::new(-1.0, -t, s),
+        2 => na::Vector3::new(s, 1.0, t),
+        3 => na::Vector3::new(s, -1.0, -t),
+        4 => na::Vector3::new(s, -t, 1.0),
+        5 => na::Vector3::new(-s, -t, -1.0),
+        _ => panic!("a cube only has 6 faces, not {}", face + 1),
+    };
+    direction.normalize()
+}
+
+/// call `f` with the face index, the texel index within the face and the direction through
+/// the center of every texel of a cube with faces `size` texels wide
+pub fn for_each_cube_texel<F>(size: u32, mut f: F)
+where
+    F: FnMut(usize, usize, na::Vector3<f32>),
+{
+    let size = size as usize;
+    for face in 0..6 {
+        for y in 0..size {
+            for x in 0..size {
+                let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let t = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                f(face, y * size + x, cube_face_direction(face, s, t));
+            }
+        }
+    }
+}
+
+/// the texture coordinates of `direction` in an equirectangular (latitude/longitude) image,
+/// with +Y at the top row and -Z in the middle column
+pub fn equirect_uv(direction: &na::Vector3<f32>) -> (f32, f32) {
+    let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * std::f32::consts::PI);
+    let v = direction.y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+    (u, v)
+}
+
+/// bilinearly sample a top-down image with `channels` floats per texel at (`u`, `v`), wrapping
+/// horizontally and clamping vertically
+pub fn sample_equirect(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    channels: usize,
+    u: f32,
+    v: f32,
+    out: &mut [f32],
+) {
+    let (width, height) = (width as usize, height as usize);
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).max(0.0).min((height - 1) as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let wrap = |x: f32| (x as isize).rem_euclid(width as isize) as usize;
+    let (x0, x1) = (wrap(x0), wrap(x0 + 1.0));
+    let (y0, y1) = (y0 as usize, (y0 as usize + 1).min(height - 1));
+    let texel = |x: usize, y: usize| &pixels[(y * width + x) * channels..][..channels];
+    let (a, b, c, d) = (texel(x0, y0), texel(x1, y0), texel(x0, y1), texel(x1, y1));
+    for (i, o) in out.iter_mut().enumerate().take(channels) {
+        let top = a[i] + (b[i] - a[i]) * fx;
+        let bottom = c[i] + (d[i] - c[i]) * fx;
+        *o = top + (bottom - top) * fy;
+    }
+}
+
+/// resample an equirectangular image with `channels` floats per texel into six cube faces of
+/// `face_size` texels square
+pub fn equirect_to_cube(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    channels: usize,
+    face_size: u32,
+) -> Vec<Vec<f32>> {
+    let face_len = face_size as usize * face_size as usize * channels;
+    let mut faces = vec![vec![0.0; face_len]; 6];
+    for_each_cube_texel(face_size, |face, index, direction| {
+        let (u, v) = equirect_uv(&direction);
+        let out = &mut faces[face][index * channels..(index + 1) * channels];
+        sample_equirect(pixels, width, height, channels, u, v, out);
+    });
+    faces
+}
+
+/// `equirect_to_cube` for 8 bit rgba images
+pub fn equirect_to_cube_rgba8(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    face_size: u32,
+) -> Vec<Vec<u8>> {
+    let floats: Vec<f32> = pixels.iter().map(|&p| p as f32).collect();
+    equirect_to_cube(&floats, width, height, 4, face_size)
+        .into_iter()
+        .map(|face| face.into_iter().map(|p| p.round() as u8).collect())
+        .collect()
+}
+
+/// decode a png or jpeg into its size and top-down 8 bit rgba pixels
+pub fn decode_rgba8(data: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    let image = image::load_from_memory(data)
+        .map_err(|err| format!("unable to decode image: {}", err))?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    Ok((width, height, image.into_raw()))
+}
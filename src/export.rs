@@ -0,0 +1,34 @@
+/// flip rgba pixels read back from gl, whose rows go from the bottom up, so the first row is
+/// the top of the image like image formats expect
+pub fn flip_rows(pixels: &mut [u8], width: u32, height: u32) {
+    let row_len = width as usize * 4;
+    let height = height as usize;
+    for row in 0..height / 2 {
+        let (top, bottom) = pixels.split_at_mut((height - row - 1) * row_len);
+        top[row * row_len..(row + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+    }
+}
+
+/// encode top-down 8 bit rgba pixels as a png file
+pub fn encode_png(pixels: &[u8], width: u32, height: u32) -> Result<Vec<u8>, String> {
+    if pixels.len() != width as usize * height as usize * 4 {
+        return Err(format!(
+            "expected {} bytes of rgba pixels for a {}x{} image but got {}",
+            width as usize * height as usize * 4,
+            width,
+            height,
+            pixels.len()
+        ));
+    }
+    let mut png_data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_data, width, height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+        writer
+            .write_image_data(pixels)
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(png_data)
+}
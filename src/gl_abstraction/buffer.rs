@@ -5,12 +5,13 @@ use web_sys::{WebGlBuffer, WebGlRenderingContext};
 use super::WebGl;
 
 /// a generic type for an opengl buffer that contains both the buffer itself and also owns the
-/// array to the underlying data. Currently the array is immutable so the buffer can't be
-/// invalidated, but this could be changed with an appropriate setter
+/// array to the underlying data. The array can only be replaced as a whole through `set_array`,
+/// which also re-uploads it, so the buffer can't be invalidated
 #[derive(Debug)]
 pub struct GlBuffer<T> {
     gl: WebGl,
     type_: u32,
+    usage: u32,
     array: Vec<T>,
     buffer: WebGlBuffer,
 }
@@ -20,26 +21,28 @@ impl<T> GlBuffer<T> {
     /// # Arguments
     /// * `gl` - a reference counted pointer to the webgl context that should be linked to
     /// * `type_`  - an enum that denotes the type of the buffer e.g. ARRAY_BUFFER or
-    /// ELEMENT_ARRAY_BUFFER
+    ///   ELEMENT_ARRAY_BUFFER
     /// * `array` - a vector to the data to be stored in the buffer
     pub fn new(gl: &WebGl, type_: u32, array: Vec<T>) -> Result<GlBuffer<T>, JsValue> {
-        let buffer = gl
-            .create_buffer()
-            .ok_or_else(|| "failed to create buffer")?;
-        gl.bind_buffer(type_, Some(&buffer));
-
-        let buffer_array = unsafe {
-            std::slice::from_raw_parts(
-                array.as_ptr() as *const u8,
-                array.len() * mem::size_of::<T>(),
-            )
-        };
-        gl.buffer_data_with_u8_array(type_, buffer_array, WebGlRenderingContext::STATIC_DRAW);
+        GlBuffer::new_with_usage(gl, type_, array, WebGlRenderingContext::STATIC_DRAW)
+    }
 
+    /// create a new buffer with an explicit usage hint, e.g. DYNAMIC_DRAW for buffers that will
+    /// be replaced with `set_array` every frame
+    pub fn new_with_usage(
+        gl: &WebGl,
+        type_: u32,
+        array: Vec<T>,
+        usage: u32,
+    ) -> Result<GlBuffer<T>, JsValue> {
+        let buffer = gl.create_buffer().ok_or("failed to create buffer")?;
+        gl.bind_buffer(type_, Some(&buffer));
+        buffer_data(gl, type_, &array, usage);
         gl.bind_buffer(type_, None);
         Ok(GlBuffer {
             gl: gl.clone(),
             type_,
+            usage,
             array,
             buffer,
         })
@@ -59,6 +62,23 @@ impl<T> GlBuffer<T> {
     pub fn array(&self) -> &Vec<T> {
         &self.array
     }
+
+    /// create the buffer again and re-upload the array, after the context was lost and restored
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        self.buffer = self.gl.create_buffer().ok_or("failed to create buffer")?;
+        self.bind();
+        buffer_data(&self.gl, self.type_, &self.array, self.usage);
+        self.unbind();
+        Ok(())
+    }
+
+    /// replace the underlying array and re-upload it to the gpu with the buffer's usage hint
+    pub fn set_array(&mut self, array: Vec<T>) {
+        self.bind();
+        buffer_data(&self.gl, self.type_, &array, self.usage);
+        self.unbind();
+        self.array = array;
+    }
 }
 
 impl<T> Drop for GlBuffer<T> {
@@ -66,3 +86,9 @@ impl<T> Drop for GlBuffer<T> {
         self.gl.delete_buffer(Some(&self.buffer));
     }
 }
+
+fn buffer_data<T>(gl: &WebGlRenderingContext, type_: u32, array: &[T], usage: u32) {
+    let buffer_array =
+        unsafe { std::slice::from_raw_parts(array.as_ptr() as *const u8, mem::size_of_val(array)) };
+    gl.buffer_data_with_u8_array(type_, buffer_array, usage);
+}
@@ -0,0 +1,253 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext as WebGl2Ctx, WebGlRenderingContext as WebGlCtx};
+
+use super::GlVersion;
+
+/// `HALF_FLOAT_OES` from `OES_texture_half_float`, which web-sys doesn't expose
+const HALF_FLOAT_OES: u32 = 0x8D61;
+/// `MAX_TEXTURE_MAX_ANISOTROPY_EXT` from `EXT_texture_filter_anisotropic`
+const MAX_TEXTURE_MAX_ANISOTROPY_EXT: u32 = 0x84FF;
+/// `MAX_DRAW_BUFFERS_WEBGL` from `WEBGL_draw_buffers`, the same as webgl 2's `MAX_DRAW_BUFFERS`
+const MAX_DRAW_BUFFERS: u32 = 0x8824;
+/// vertex uniform vectors the material vertex shader needs before any joints, its four
+/// matrices
+const RESERVED_VERTEX_UNIFORM_VECTORS: u32 = 16;
+
+/// what a context can do, queried once when it's created. Extensions needed for a feature are
+/// enabled while querying, so a `true` here can be relied on
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub version: GlVersion,
+    pub max_texture_size: u32,
+    pub max_cube_map_size: u32,
+    pub max_renderbuffer_size: u32,
+    pub max_vertex_attribs: u32,
+    pub max_vertex_uniform_vectors: u32,
+    pub max_fragment_uniform_vectors: u32,
+    pub max_varying_vectors: u32,
+    /// texture units available to fragment shaders
+    pub max_texture_units: u32,
+    pub max_vertex_texture_units: u32,
+    pub max_combined_texture_units: u32,
+    /// color attachments that can be drawn to at once, 1 without multiple render targets. The
+    /// renderer itself only ever draws to one
+    pub max_draw_buffers: u32,
+    /// the highest anisotropic filtering level, 1 without anisotropic filtering
+    pub max_anisotropy: f32,
+    /// whether fragment shaders have highp floats rather than only mediump
+    pub fragment_high_float: bool,
+    pub instancing: bool,
+    pub vertex_arrays: bool,
+    /// whether element buffers can hold UNSIGNED_INT indices
+    pub uint_indices: bool,
+    /// whether shaders can sample explicit mip levels
+    pub shader_texture_lod: bool,
+    /// whether 8 bit sRGB textures can be uploaded and mipmapped, which only webgl 2 can do.
+    /// Base color images are decoded to linear by the gpu when it can
+    pub srgb_textures: bool,
+    /// whether uniform blocks can be backed by buffers. The renderer sets uniforms one by one
+    /// either way, so this is for programs of your own
+    pub uniform_buffers: bool,
+    /// whether non power of two textures can have mipmaps and repeat
+    pub npot_textures: bool,
+    pub depth_textures: bool,
+    /// whether rgba float textures can be sampled with linear filtering
+    pub float_textures: bool,
+    /// whether rgba half float textures can be rendered into
+    pub half_float_targets: bool,
+    /// whether half float textures can be sampled with linear filtering
+    pub half_float_linear: bool,
+    /// whether BC1 to BC3 compressed textures can be uploaded
+    pub s3tc: bool,
+    /// whether BC7 compressed textures can be uploaded
+    pub bptc: bool,
+    /// whether ETC2 compressed textures can be uploaded
+    pub etc: bool,
+    pub etc1: bool,
+    /// whether ASTC compressed textures can be uploaded, with 4x4 blocks at least
+    pub astc: bool,
+    extensions: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl Capabilities {
+    /// the names of every extension the context supports, enabled or not
+    #[wasm_bindgen(getter)]
+    pub fn extensions(&self) -> js_sys::Array {
+        self.extensions.iter().map(JsValue::from).collect()
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|extension| extension == name)
+    }
+
+    pub fn multiple_render_targets(&self) -> bool {
+        self.max_draw_buffers > 1
+    }
+
+    /// how many joint matrices a skinning vertex shader could take as uniforms alongside the
+    /// material shader's own. The renderer doesn't skin meshes itself
+    pub fn max_joints(&self) -> u32 {
+        self.max_vertex_uniform_vectors
+            .saturating_sub(RESERVED_VERTEX_UNIFORM_VECTORS)
+            / 4
+    }
+}
+
+impl Capabilities {
+    /// query `gl`, enabling the extensions that the supported features come from
+    pub fn query(gl: &WebGlCtx, version: GlVersion) -> Capabilities {
+        let extensions: Vec<String> = gl
+            .get_supported_extensions()
+            .map(|names| names.iter().filter_map(|name| name.as_string()).collect())
+            .unwrap_or_default();
+        let enable = |name: &str| {
+            extensions.iter().any(|extension| extension == name)
+                && gl.get_extension(name).ok().flatten().is_some()
+        };
+        let parameter = |name| {
+            gl.get_parameter(name)
+                .ok()
+                .and_then(|value| value.as_f64())
+                .unwrap_or(0.0)
+        };
+        let fragment_high_float = gl
+            .get_shader_precision_format(WebGlCtx::FRAGMENT_SHADER, WebGlCtx::HIGH_FLOAT)
+            .is_some_and(|format| format.precision() > 0);
+        let max_anisotropy = if enable("EXT_texture_filter_anisotropic") {
+            parameter(MAX_TEXTURE_MAX_ANISOTROPY_EXT) as f32
+        } else {
+            1.0
+        };
+
+        let webgl2 = version == GlVersion::WebGl2;
+        let max_draw_buffers = if webgl2 || enable("WEBGL_draw_buffers") {
+            parameter(MAX_DRAW_BUFFERS) as u32
+        } else {
+            1
+        };
+        // the rest are core in webgl 2 apart from filtering floats and rendering to half floats
+        let float_linear = enable("OES_texture_float_linear");
+        let (float_textures, half_float_targets, half_float_linear) = if webgl2 {
+            let color_buffer_float = enable("EXT_color_buffer_float");
+            let color_buffer_half_float = enable("EXT_color_buffer_half_float");
+            (
+                float_linear,
+                color_buffer_float || color_buffer_half_float,
+                true,
+            )
+        } else {
+            (
+                enable("OES_texture_float") && float_linear,
+                enable("OES_texture_half_float"),
+                enable("OES_texture_half_float_linear"),
+            )
+        };
+
+        Capabilities {
+            version,
+            max_texture_size: parameter(WebGlCtx::MAX_TEXTURE_SIZE) as u32,
+            max_cube_map_size: parameter(WebGlCtx::MAX_CUBE_MAP_TEXTURE_SIZE) as u32,
+            max_renderbuffer_size: parameter(WebGlCtx::MAX_RENDERBUFFER_SIZE) as u32,
+            max_vertex_attribs: parameter(WebGlCtx::MAX_VERTEX_ATTRIBS) as u32,
+            max_vertex_uniform_vectors: parameter(WebGlCtx::MAX_VERTEX_UNIFORM_VECTORS) as u32,
+            max_fragment_uniform_vectors: parameter(WebGlCtx::MAX_FRAGMENT_UNIFORM_VECTORS) as u32,
+            max_varying_vectors: parameter(WebGlCtx::MAX_VARYING_VECTORS) as u32,
+            max_texture_units: parameter(WebGlCtx::MAX_TEXTURE_IMAGE_UNITS) as u32,
+            max_vertex_texture_units: parameter(WebGlCtx::MAX_VERTEX_TEXTURE_IMAGE_UNITS) as u32,
+            max_combined_texture_units: parameter(WebGlCtx::MAX_COMBINED_TEXTURE_IMAGE_UNITS)
+                as u32,
+            max_draw_buffers,
+            max_anisotropy,
+            fragment_high_float,
+            instancing: webgl2 || enable("ANGLE_instanced_arrays"),
+            vertex_arrays: webgl2 || enable("OES_vertex_array_object"),
+            uint_indices: webgl2 || enable("OES_element_index_uint"),
+            shader_texture_lod: webgl2 || enable("EXT_shader_texture_lod"),
+            // webgl 1's EXT_sRGB can't generate mipmaps for them
+            srgb_textures: webgl2,
+            uniform_buffers: webgl2,
+            npot_textures: webgl2,
+            depth_textures: webgl2 || enable("WEBGL_depth_texture"),
+            float_textures,
+            half_float_targets,
+            half_float_linear,
+            s3tc: enable("WEBGL_compressed_texture_s3tc"),
+            bptc: enable("EXT_texture_compression_bptc"),
+            etc: enable("WEBGL_compressed_texture_etc"),
+            etc1: enable("WEBGL_compressed_texture_etc1"),
+            astc: enable("WEBGL_compressed_texture_astc"),
+            extensions,
+        }
+    }
+
+    /// the texel type of half float textures, which webgl 1 numbers differently
+    pub fn half_float_type(&self) -> u32 {
+        match self.version {
+            GlVersion::WebGl1 => HALF_FLOAT_OES,
+            GlVersion::WebGl2 => WebGl2Ctx::HALF_FLOAT,
+        }
+    }
+
+    /// the internal format to allocate a texture of `format` texels of `type_` with. Webgl 1
+    /// takes the format itself, while webgl 2 needs sized formats for float and depth textures
+    pub fn internal_format(&self, format: u32, type_: u32) -> u32 {
+        if self.version == GlVersion::WebGl1 {
+            return format;
+        }
+        match (format, type_) {
+            (WebGlCtx::RGBA, WebGlCtx::FLOAT) => WebGl2Ctx::RGBA32F,
+            (WebGlCtx::RGBA, WebGl2Ctx::HALF_FLOAT) => WebGl2Ctx::RGBA16F,
+            (WebGlCtx::DEPTH_COMPONENT, WebGlCtx::UNSIGNED_INT) => WebGl2Ctx::DEPTH_COMPONENT24,
+            (WebGlCtx::DEPTH_COMPONENT, WebGlCtx::UNSIGNED_SHORT) => WebGl2Ctx::DEPTH_COMPONENT16,
+            _ => format,
+        }
+    }
+
+    /// whether a 2d texture of this size fits, for `width` x `height` texels
+    pub fn fits_texture(&self, width: u32, height: u32) -> bool {
+        width <= self.max_texture_size && height <= self.max_texture_size
+    }
+}
+
+#[cfg(test)]
+impl Capabilities {
+    /// a webgl 1 context with the smallest limits the spec allows and no extensions, for tests
+    /// to turn features on in
+    pub(crate) fn minimal() -> Capabilities {
+        Capabilities {
+            version: GlVersion::WebGl1,
+            max_texture_size: 64,
+            max_cube_map_size: 16,
+            max_renderbuffer_size: 1,
+            max_vertex_attribs: 8,
+            max_vertex_uniform_vectors: 128,
+            max_fragment_uniform_vectors: 16,
+            max_varying_vectors: 8,
+            max_texture_units: 8,
+            max_vertex_texture_units: 0,
+            max_combined_texture_units: 8,
+            max_draw_buffers: 1,
+            max_anisotropy: 1.0,
+            fragment_high_float: false,
+            instancing: false,
+            vertex_arrays: false,
+            uint_indices: false,
+            shader_texture_lod: false,
+            srgb_textures: false,
+            uniform_buffers: false,
+            npot_textures: false,
+            depth_textures: false,
+            float_textures: false,
+            half_float_targets: false,
+            half_float_linear: false,
+            s3tc: false,
+            bptc: false,
+            etc: false,
+            etc1: false,
+            astc: false,
+            extensions: Vec::new(),
+        }
+    }
+}
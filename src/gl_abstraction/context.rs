@@ -0,0 +1,525 @@
+use std::cell::{Ref, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use super::{Capabilities, ContextOptions};
+use web_sys::{
+    AngleInstancedArrays, HtmlCanvasElement, OesVertexArrayObject, WebGl2RenderingContext,
+    WebGlBuffer, WebGlFramebuffer, WebGlProgram, WebGlRenderbuffer, WebGlRenderingContext,
+    WebGlTexture, WebGlVertexArrayObject, WebglLoseContext,
+};
+
+/// which version of webgl a context implements
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlVersion {
+    WebGl1 = 1,
+    WebGl2 = 2,
+}
+
+/// how many binding calls the state cache has passed through to webgl and how many it skipped
+/// because they wouldn't have changed anything
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GlStateStats {
+    pub issued: u32,
+    pub skipped_programs: u32,
+    pub skipped_buffers: u32,
+    pub skipped_textures: u32,
+    pub skipped_attribs: u32,
+    pub skipped_framebuffers: u32,
+}
+
+#[wasm_bindgen]
+impl GlStateStats {
+    /// the total number of skipped calls
+    #[wasm_bindgen(getter)]
+    pub fn skipped(&self) -> u32 {
+        self.skipped_programs
+            + self.skipped_buffers
+            + self.skipped_textures
+            + self.skipped_attribs
+            + self.skipped_framebuffers
+    }
+}
+
+/// the bindings webgl currently has, as far as the cache knows
+#[derive(Debug, Default)]
+struct StateCache {
+    program: Option<WebGlProgram>,
+    /// bound buffers by target
+    buffers: HashMap<u32, WebGlBuffer>,
+    /// the active texture unit as an offset from TEXTURE0
+    active_texture: u32,
+    /// bound textures by unit and target
+    textures: HashMap<(u32, u32), WebGlTexture>,
+    enabled_attribs: HashSet<u32>,
+    /// attributes with a non-zero instance divisor
+    attrib_divisors: HashMap<u32, u32>,
+    /// the bound vertex array object. Attribute state and the element buffer belong to it, so
+    /// they're only cached for the default one
+    vertex_array: Option<WebGlVertexArrayObject>,
+    framebuffer: Option<WebGlFramebuffer>,
+    renderbuffer: Option<WebGlRenderbuffer>,
+    stats: GlStateStats,
+}
+
+/// the webgl 1 extension objects for webgl 2 features that need their own functions
+#[derive(Debug, Default)]
+struct Extensions {
+    instanced_arrays: Option<AngleInstancedArrays>,
+    vertex_array_object: Option<OesVertexArrayObject>,
+}
+
+impl Extensions {
+    /// get the objects for the extensions `capabilities` enabled
+    fn get(gl: &WebGlRenderingContext, capabilities: &Capabilities) -> Extensions {
+        let get = |supported: bool, name| {
+            if supported {
+                gl.get_extension(name).ok().flatten()
+            } else {
+                None
+            }
+        };
+        Extensions {
+            instanced_arrays: get(capabilities.instancing, "ANGLE_instanced_arrays")
+                .map(JsCast::unchecked_into),
+            vertex_array_object: get(capabilities.vertex_arrays, "OES_vertex_array_object")
+                .map(JsCast::unchecked_into),
+        }
+    }
+}
+
+/// a webgl context that remembers the bound program, buffers, textures per unit, framebuffer
+/// and enabled attributes, dropping calls that wouldn't change them. Its methods shadow the
+/// context's own, so everything that binds through a `WebGl` goes through the cache.
+///
+/// It wraps either a webgl 1 or a webgl 2 context. Both deref to the webgl 1 api, which webgl 2
+/// implements in full, and the features webgl 1 only has as extensions go through methods here
+/// that use whichever the context has
+#[derive(Debug)]
+pub struct GlContext {
+    gl: WebGlRenderingContext,
+    gl2: Option<WebGl2RenderingContext>,
+    state: RefCell<StateCache>,
+    capabilities: RefCell<Capabilities>,
+    extensions: RefCell<Extensions>,
+    /// kept from creation, since it's needed while the context is lost
+    lose_context: Option<WebglLoseContext>,
+}
+
+impl GlContext {
+    /// wrap a webgl 1 context, enabling the extensions it has for webgl 2 features
+    pub fn new(gl: WebGlRenderingContext) -> GlContext {
+        let capabilities = Capabilities::query(&gl, GlVersion::WebGl1);
+        let extensions = Extensions::get(&gl, &capabilities);
+        let lose_context = get_lose_context(&gl);
+        GlContext {
+            gl,
+            gl2: None,
+            state: RefCell::new(StateCache::default()),
+            capabilities: RefCell::new(capabilities),
+            extensions: RefCell::new(extensions),
+            lose_context,
+        }
+    }
+
+    /// wrap a webgl 2 context
+    pub fn new_webgl2(gl2: WebGl2RenderingContext) -> GlContext {
+        // web-sys calls methods by name, so the webgl 1 bindings work on a webgl 2 context
+        let gl = gl2.clone().unchecked_into::<WebGlRenderingContext>();
+        let capabilities = Capabilities::query(&gl, GlVersion::WebGl2);
+        let lose_context = get_lose_context(&gl);
+        GlContext {
+            gl,
+            gl2: Some(gl2),
+            state: RefCell::new(StateCache::default()),
+            capabilities: RefCell::new(capabilities),
+            extensions: RefCell::new(Extensions::default()),
+            lose_context,
+        }
+    }
+
+    /// get a webgl 2 context from `canvas`, falling back to webgl 1 where it isn't available
+    pub fn from_canvas(canvas: &HtmlCanvasElement) -> Result<GlContext, JsValue> {
+        GlContext::from_canvas_with_options(canvas, &ContextOptions::default())
+    }
+
+    /// get a context from `canvas` with the attributes in `options`, trying webgl 2 first if
+    /// the options ask for it
+    pub fn from_canvas_with_options(
+        canvas: &HtmlCanvasElement,
+        options: &ContextOptions,
+    ) -> Result<GlContext, JsValue> {
+        let attributes = options.to_attributes();
+        if options.webgl2() {
+            if let Some(gl2) = canvas.get_context_with_context_options("webgl2", &attributes)? {
+                return Ok(GlContext::new_webgl2(gl2.dyn_into()?));
+            }
+        }
+        match canvas.get_context_with_context_options("webgl", &attributes)? {
+            Some(gl) => Ok(GlContext::new(gl.dyn_into()?)),
+            None => Err("unable to get a webgl context from the canvas".into()),
+        }
+    }
+
+    pub fn version(&self) -> GlVersion {
+        match self.gl2 {
+            Some(_) => GlVersion::WebGl2,
+            None => GlVersion::WebGl1,
+        }
+    }
+
+    pub fn is_webgl2(&self) -> bool {
+        self.gl2.is_some()
+    }
+
+    /// the webgl 2 context, for calls the webgl 1 api doesn't have
+    pub fn webgl2(&self) -> Option<&WebGl2RenderingContext> {
+        self.gl2.as_ref()
+    }
+
+    /// the attributes the context actually has, which can differ from the ones it was requested
+    /// with. `None` while the context is lost
+    pub fn context_attributes(&self) -> Option<ContextOptions> {
+        self.gl
+            .get_context_attributes()
+            .map(|attributes| ContextOptions::from_attributes(&attributes, self.is_webgl2()))
+    }
+
+    /// what the context supports, queried when it was created
+    pub fn capabilities(&self) -> Ref<'_, Capabilities> {
+        self.capabilities.borrow()
+    }
+
+    /// start over after the context was lost and restored: forget the cached state, which is
+    /// all back to defaults, and enable the extensions again. Every object created before the
+    /// loss has to be recreated by its owner
+    pub fn restore(&self) {
+        self.invalidate();
+        let capabilities = Capabilities::query(&self.gl, self.version());
+        if !self.is_webgl2() {
+            *self.extensions.borrow_mut() = Extensions::get(&self.gl, &capabilities);
+        }
+        *self.capabilities.borrow_mut() = capabilities;
+    }
+
+    /// make the browser lose the context as if the gpu was reset, returning false without
+    /// `WEBGL_lose_context`. For testing recovery
+    pub fn simulate_loss(&self) -> bool {
+        match self.lose_context {
+            Some(ref lose_context) => {
+                lose_context.lose_context();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// make the browser restore a context lost with `simulate_loss`
+    pub fn simulate_restore(&self) -> bool {
+        match self.lose_context {
+            Some(ref lose_context) => {
+                lose_context.restore_context();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn stats(&self) -> GlStateStats {
+        self.state.borrow().stats
+    }
+
+    pub fn reset_stats(&self) {
+        self.state.borrow_mut().stats = GlStateStats::default();
+    }
+
+    /// forget everything the cache knows, for when the context's state changed behind its back.
+    /// Unit 0 is assumed active with nothing bound
+    pub fn invalidate(&self) {
+        let stats = self.stats();
+        *self.state.borrow_mut() = StateCache {
+            stats,
+            ..StateCache::default()
+        };
+    }
+
+    pub fn use_program(&self, program: Option<&WebGlProgram>) {
+        let mut state = self.state.borrow_mut();
+        if state.program.as_ref() == program {
+            state.stats.skipped_programs += 1;
+            return;
+        }
+        state.program = program.cloned();
+        state.stats.issued += 1;
+        self.gl.use_program(program);
+    }
+
+    pub fn bind_buffer(&self, target: u32, buffer: Option<&WebGlBuffer>) {
+        let mut state = self.state.borrow_mut();
+        if target == WebGlRenderingContext::ELEMENT_ARRAY_BUFFER && state.vertex_array.is_some() {
+            state.stats.issued += 1;
+            self.gl.bind_buffer(target, buffer);
+            return;
+        }
+        if state.buffers.get(&target) == buffer {
+            state.stats.skipped_buffers += 1;
+            return;
+        }
+        match buffer {
+            Some(buffer) => state.buffers.insert(target, buffer.clone()),
+            None => state.buffers.remove(&target),
+        };
+        state.stats.issued += 1;
+        self.gl.bind_buffer(target, buffer);
+    }
+
+    /// make `texture` (e.g. TEXTURE0 + 1) the unit textures are bound to
+    pub fn active_texture(&self, texture: u32) {
+        let mut state = self.state.borrow_mut();
+        let unit = texture - WebGlRenderingContext::TEXTURE0;
+        if state.active_texture == unit {
+            state.stats.skipped_textures += 1;
+            return;
+        }
+        state.active_texture = unit;
+        state.stats.issued += 1;
+        self.gl.active_texture(texture);
+    }
+
+    pub fn bind_texture(&self, target: u32, texture: Option<&WebGlTexture>) {
+        let mut state = self.state.borrow_mut();
+        let key = (state.active_texture, target);
+        if state.textures.get(&key) == texture {
+            state.stats.skipped_textures += 1;
+            return;
+        }
+        match texture {
+            Some(texture) => state.textures.insert(key, texture.clone()),
+            None => state.textures.remove(&key),
+        };
+        state.stats.issued += 1;
+        self.gl.bind_texture(target, texture);
+    }
+
+    pub fn enable_vertex_attrib_array(&self, index: u32) {
+        let mut state = self.state.borrow_mut();
+        if state.vertex_array.is_some() {
+            state.stats.issued += 1;
+            self.gl.enable_vertex_attrib_array(index);
+            return;
+        }
+        if !state.enabled_attribs.insert(index) {
+            state.stats.skipped_attribs += 1;
+            return;
+        }
+        state.stats.issued += 1;
+        self.gl.enable_vertex_attrib_array(index);
+    }
+
+    pub fn disable_vertex_attrib_array(&self, index: u32) {
+        let mut state = self.state.borrow_mut();
+        if state.vertex_array.is_some() {
+            state.stats.issued += 1;
+            self.gl.disable_vertex_attrib_array(index);
+            return;
+        }
+        if !state.enabled_attribs.remove(&index) {
+            state.stats.skipped_attribs += 1;
+            return;
+        }
+        state.stats.issued += 1;
+        self.gl.disable_vertex_attrib_array(index);
+    }
+
+    /// advance attribute `index` once every `divisor` instances rather than every vertex, where
+    /// 0 goes back to per vertex. Does nothing without instancing support
+    pub fn vertex_attrib_divisor(&self, index: u32, divisor: u32) {
+        if !self.capabilities().instancing {
+            return;
+        }
+        let mut state = self.state.borrow_mut();
+        if state.vertex_array.is_none() {
+            if state.attrib_divisors.get(&index).copied().unwrap_or(0) == divisor {
+                state.stats.skipped_attribs += 1;
+                return;
+            }
+            if divisor == 0 {
+                state.attrib_divisors.remove(&index);
+            } else {
+                state.attrib_divisors.insert(index, divisor);
+            }
+        }
+        state.stats.issued += 1;
+        match (&self.gl2, &self.extensions.borrow().instanced_arrays) {
+            (Some(gl2), _) => gl2.vertex_attrib_divisor(index, divisor),
+            (None, Some(instanced_arrays)) => {
+                instanced_arrays.vertex_attrib_divisor_angle(index, divisor)
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// `draw_arrays` repeated for `instances` instances, returning false without instancing
+    /// support
+    pub fn draw_arrays_instanced(&self, mode: u32, first: i32, count: i32, instances: i32) -> bool {
+        match (&self.gl2, &self.extensions.borrow().instanced_arrays) {
+            (Some(gl2), _) => gl2.draw_arrays_instanced(mode, first, count, instances),
+            (None, Some(instanced_arrays)) => {
+                instanced_arrays.draw_arrays_instanced_angle(mode, first, count, instances)
+            }
+            (None, None) => return false,
+        }
+        true
+    }
+
+    /// `draw_elements` repeated for `instances` instances, returning false without instancing
+    /// support
+    pub fn draw_elements_instanced(
+        &self,
+        mode: u32,
+        count: i32,
+        type_: u32,
+        offset: i32,
+        instances: i32,
+    ) -> bool {
+        match (&self.gl2, &self.extensions.borrow().instanced_arrays) {
+            (Some(gl2), _) => {
+                gl2.draw_elements_instanced_with_i32(mode, count, type_, offset, instances)
+            }
+            (None, Some(instanced_arrays)) => instanced_arrays
+                .draw_elements_instanced_angle_with_i32(mode, count, type_, offset, instances),
+            (None, None) => return false,
+        }
+        true
+    }
+
+    /// create a vertex array object, or `None` without support for them
+    pub fn create_vertex_array(&self) -> Option<WebGlVertexArrayObject> {
+        match (&self.gl2, &self.extensions.borrow().vertex_array_object) {
+            (Some(gl2), _) => gl2.create_vertex_array(),
+            (None, Some(vertex_array_object)) => vertex_array_object.create_vertex_array_oes(),
+            (None, None) => None,
+        }
+    }
+
+    /// bind a vertex array object, or the default one with `None`. While one is bound the
+    /// attribute and element buffer calls it captures go straight through to webgl
+    pub fn bind_vertex_array(&self, vertex_array: Option<&WebGlVertexArrayObject>) {
+        let mut state = self.state.borrow_mut();
+        if state.vertex_array.as_ref() == vertex_array {
+            state.stats.skipped_attribs += 1;
+            return;
+        }
+        state.vertex_array = vertex_array.cloned();
+        state.stats.issued += 1;
+        match (&self.gl2, &self.extensions.borrow().vertex_array_object) {
+            (Some(gl2), _) => gl2.bind_vertex_array(vertex_array),
+            (None, Some(vertex_array_object)) => {
+                vertex_array_object.bind_vertex_array_oes(vertex_array)
+            }
+            (None, None) => {}
+        }
+    }
+
+    pub fn delete_vertex_array(&self, vertex_array: Option<&WebGlVertexArrayObject>) {
+        let mut state = self.state.borrow_mut();
+        if vertex_array.is_some() && state.vertex_array.as_ref() == vertex_array {
+            state.vertex_array = None;
+        }
+        match (&self.gl2, &self.extensions.borrow().vertex_array_object) {
+            (Some(gl2), _) => gl2.delete_vertex_array(vertex_array),
+            (None, Some(vertex_array_object)) => {
+                vertex_array_object.delete_vertex_array_oes(vertex_array)
+            }
+            (None, None) => {}
+        }
+    }
+
+    pub fn bind_framebuffer(&self, target: u32, framebuffer: Option<&WebGlFramebuffer>) {
+        let mut state = self.state.borrow_mut();
+        if state.framebuffer.as_ref() == framebuffer {
+            state.stats.skipped_framebuffers += 1;
+            return;
+        }
+        state.framebuffer = framebuffer.cloned();
+        state.stats.issued += 1;
+        self.gl.bind_framebuffer(target, framebuffer);
+    }
+
+    pub fn bind_renderbuffer(&self, target: u32, renderbuffer: Option<&WebGlRenderbuffer>) {
+        let mut state = self.state.borrow_mut();
+        if state.renderbuffer.as_ref() == renderbuffer {
+            state.stats.skipped_framebuffers += 1;
+            return;
+        }
+        state.renderbuffer = renderbuffer.cloned();
+        state.stats.issued += 1;
+        self.gl.bind_renderbuffer(target, renderbuffer);
+    }
+
+    // deleting an object unbinds it everywhere, so the cache has to forget it too
+
+    pub fn delete_program(&self, program: Option<&WebGlProgram>) {
+        let mut state = self.state.borrow_mut();
+        if program.is_some() && state.program.as_ref() == program {
+            state.program = None;
+        }
+        self.gl.delete_program(program);
+    }
+
+    pub fn delete_buffer(&self, buffer: Option<&WebGlBuffer>) {
+        if let Some(buffer) = buffer {
+            self.state
+                .borrow_mut()
+                .buffers
+                .retain(|_, bound| bound != buffer);
+        }
+        self.gl.delete_buffer(buffer);
+    }
+
+    pub fn delete_texture(&self, texture: Option<&WebGlTexture>) {
+        if let Some(texture) = texture {
+            self.state
+                .borrow_mut()
+                .textures
+                .retain(|_, bound| bound != texture);
+        }
+        self.gl.delete_texture(texture);
+    }
+
+    pub fn delete_framebuffer(&self, framebuffer: Option<&WebGlFramebuffer>) {
+        let mut state = self.state.borrow_mut();
+        if framebuffer.is_some() && state.framebuffer.as_ref() == framebuffer {
+            state.framebuffer = None;
+        }
+        self.gl.delete_framebuffer(framebuffer);
+    }
+
+    pub fn delete_renderbuffer(&self, renderbuffer: Option<&WebGlRenderbuffer>) {
+        let mut state = self.state.borrow_mut();
+        if renderbuffer.is_some() && state.renderbuffer.as_ref() == renderbuffer {
+            state.renderbuffer = None;
+        }
+        self.gl.delete_renderbuffer(renderbuffer);
+    }
+}
+
+fn get_lose_context(gl: &WebGlRenderingContext) -> Option<WebglLoseContext> {
+    gl.get_extension("WEBGL_lose_context")
+        .ok()
+        .flatten()
+        .map(JsCast::unchecked_into)
+}
+
+impl Deref for GlContext {
+    type Target = WebGlRenderingContext;
+
+    fn deref(&self) -> &Self::Target {
+        &self.gl
+    }
+}
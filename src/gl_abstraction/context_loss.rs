@@ -0,0 +1,84 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Event, HtmlCanvasElement};
+
+/// whether the canvas' context can be drawn with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextStatus {
+    Live,
+    Lost,
+    /// restored by the browser, but nothing has been recreated on it yet
+    Restored,
+}
+
+/// follows the canvas' `webglcontextlost` and `webglcontextrestored` events. The loss event's
+/// default is prevented, which tells the browser to restore the context when it can
+pub struct ContextLossListener {
+    canvas: HtmlCanvasElement,
+    status: Rc<Cell<ContextStatus>>,
+    on_lost: Closure<dyn FnMut(Event)>,
+    on_restored: Closure<dyn FnMut(Event)>,
+}
+
+impl ContextLossListener {
+    pub fn new(canvas: &HtmlCanvasElement) -> Result<ContextLossListener, JsValue> {
+        let status = Rc::new(Cell::new(ContextStatus::Live));
+        let on_lost = {
+            let status = status.clone();
+            Closure::wrap(Box::new(move |event: Event| {
+                event.prevent_default();
+                status.set(ContextStatus::Lost);
+            }) as Box<dyn FnMut(Event)>)
+        };
+        let on_restored = {
+            let status = status.clone();
+            Closure::wrap(Box::new(move |_: Event| {
+                status.set(ContextStatus::Restored);
+            }) as Box<dyn FnMut(Event)>)
+        };
+        canvas.add_event_listener_with_callback(
+            "webglcontextlost",
+            on_lost.as_ref().unchecked_ref(),
+        )?;
+        canvas.add_event_listener_with_callback(
+            "webglcontextrestored",
+            on_restored.as_ref().unchecked_ref(),
+        )?;
+        Ok(ContextLossListener {
+            canvas: canvas.clone(),
+            status,
+            on_lost,
+            on_restored,
+        })
+    }
+
+    pub fn status(&self) -> ContextStatus {
+        self.status.get()
+    }
+
+    /// record that everything has been recreated on a restored context
+    pub fn set_live(&self) {
+        self.status.set(ContextStatus::Live);
+    }
+}
+
+impl Drop for ContextLossListener {
+    fn drop(&mut self) {
+        // the closures are freed with the listener, so the canvas can't be left calling them
+        self.canvas
+            .remove_event_listener_with_callback(
+                "webglcontextlost",
+                self.on_lost.as_ref().unchecked_ref(),
+            )
+            .ok();
+        self.canvas
+            .remove_event_listener_with_callback(
+                "webglcontextrestored",
+                self.on_restored.as_ref().unchecked_ref(),
+            )
+            .ok();
+    }
+}
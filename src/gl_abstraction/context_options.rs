@@ -0,0 +1,209 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlContextAttributes, WebGlPowerPreference};
+
+/// which gpu the browser should pick on systems with more than one
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerPreference {
+    Default,
+    LowPower,
+    HighPerformance,
+}
+
+impl PowerPreference {
+    fn to_web_sys(self) -> WebGlPowerPreference {
+        match self {
+            PowerPreference::Default => WebGlPowerPreference::Default,
+            PowerPreference::LowPower => WebGlPowerPreference::LowPower,
+            PowerPreference::HighPerformance => WebGlPowerPreference::HighPerformance,
+        }
+    }
+
+    fn from_web_sys(preference: WebGlPowerPreference) -> PowerPreference {
+        match preference {
+            WebGlPowerPreference::LowPower => PowerPreference::LowPower,
+            WebGlPowerPreference::HighPerformance => PowerPreference::HighPerformance,
+            _ => PowerPreference::Default,
+        }
+    }
+}
+
+/// the attributes a context is requested with. They're requests the browser may not honor,
+/// e.g. antialiasing on hardware without multisampling, so the context's actual attributes
+/// come back as another set of options from `Tetra::get_context_attributes`
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextOptions {
+    webgl2: bool,
+    alpha: bool,
+    depth: bool,
+    stencil: bool,
+    antialias: bool,
+    premultiplied_alpha: bool,
+    preserve_drawing_buffer: bool,
+    power_preference: PowerPreference,
+    fail_if_major_performance_caveat: bool,
+}
+
+impl Default for ContextOptions {
+    fn default() -> ContextOptions {
+        ContextOptions {
+            webgl2: true,
+            alpha: true,
+            depth: true,
+            stencil: false,
+            antialias: true,
+            premultiplied_alpha: true,
+            preserve_drawing_buffer: false,
+            power_preference: PowerPreference::Default,
+            fail_if_major_performance_caveat: false,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl ContextOptions {
+    /// the browser's defaults, preferring webgl 2
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ContextOptions {
+        ContextOptions::default()
+    }
+
+    /// whether to try for a webgl 2 context before falling back to webgl 1
+    pub fn with_webgl2(mut self, webgl2: bool) -> ContextOptions {
+        self.webgl2 = webgl2;
+        self
+    }
+
+    /// whether the canvas has an alpha channel to composite with the page
+    pub fn with_alpha(mut self, alpha: bool) -> ContextOptions {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn with_depth(mut self, depth: bool) -> ContextOptions {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_stencil(mut self, stencil: bool) -> ContextOptions {
+        self.stencil = stencil;
+        self
+    }
+
+    pub fn with_antialias(mut self, antialias: bool) -> ContextOptions {
+        self.antialias = antialias;
+        self
+    }
+
+    /// whether the page should treat the canvas' colors as already multiplied by alpha
+    pub fn with_premultiplied_alpha(mut self, premultiplied_alpha: bool) -> ContextOptions {
+        self.premultiplied_alpha = premultiplied_alpha;
+        self
+    }
+
+    /// whether the canvas keeps its contents after being composited, e.g. so it can be read
+    /// back with `toDataURL` outside of a frame
+    pub fn with_preserve_drawing_buffer(mut self, preserve_drawing_buffer: bool) -> ContextOptions {
+        self.preserve_drawing_buffer = preserve_drawing_buffer;
+        self
+    }
+
+    pub fn with_power_preference(mut self, power_preference: PowerPreference) -> ContextOptions {
+        self.power_preference = power_preference;
+        self
+    }
+
+    /// whether to fail rather than get a context that would be much slower than usual, e.g.
+    /// one rendered in software
+    pub fn with_fail_if_major_performance_caveat(mut self, fail: bool) -> ContextOptions {
+        self.fail_if_major_performance_caveat = fail;
+        self
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn webgl2(&self) -> bool {
+        self.webgl2
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn alpha(&self) -> bool {
+        self.alpha
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn depth(&self) -> bool {
+        self.depth
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn stencil(&self) -> bool {
+        self.stencil
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn antialias(&self) -> bool {
+        self.antialias
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn premultiplied_alpha(&self) -> bool {
+        self.premultiplied_alpha
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn preserve_drawing_buffer(&self) -> bool {
+        self.preserve_drawing_buffer
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn power_preference(&self) -> PowerPreference {
+        self.power_preference
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn fail_if_major_performance_caveat(&self) -> bool {
+        self.fail_if_major_performance_caveat
+    }
+}
+
+impl ContextOptions {
+    /// the attributes to pass to `getContext`
+    pub fn to_attributes(&self) -> WebGlContextAttributes {
+        let attributes = WebGlContextAttributes::new();
+        attributes.set_alpha(self.alpha);
+        attributes.set_depth(self.depth);
+        attributes.set_stencil(self.stencil);
+        attributes.set_antialias(self.antialias);
+        attributes.set_premultiplied_alpha(self.premultiplied_alpha);
+        attributes.set_preserve_drawing_buffer(self.preserve_drawing_buffer);
+        attributes.set_power_preference(self.power_preference.to_web_sys());
+        attributes.set_fail_if_major_performance_caveat(self.fail_if_major_performance_caveat);
+        attributes
+    }
+
+    /// the options a context was actually created with, from its attributes. Attributes the
+    /// browser leaves out keep their default
+    pub fn from_attributes(attributes: &WebGlContextAttributes, webgl2: bool) -> ContextOptions {
+        let defaults = ContextOptions::default();
+        ContextOptions {
+            webgl2,
+            alpha: attributes.get_alpha().unwrap_or(defaults.alpha),
+            depth: attributes.get_depth().unwrap_or(defaults.depth),
+            stencil: attributes.get_stencil().unwrap_or(defaults.stencil),
+            antialias: attributes.get_antialias().unwrap_or(defaults.antialias),
+            premultiplied_alpha: attributes
+                .get_premultiplied_alpha()
+                .unwrap_or(defaults.premultiplied_alpha),
+            preserve_drawing_buffer: attributes
+                .get_preserve_drawing_buffer()
+                .unwrap_or(defaults.preserve_drawing_buffer),
+            power_preference: attributes
+                .get_power_preference()
+                .map_or(defaults.power_preference, PowerPreference::from_web_sys),
+            fail_if_major_performance_caveat: attributes
+                .get_fail_if_major_performance_caveat()
+                .unwrap_or(defaults.fail_if_major_performance_caveat),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::ops::Deref;
+
+use wasm_bindgen::JsValue;
+use web_sys::{WebGlFramebuffer, WebGlRenderingContext as WebGlCtx};
+
+use super::{Renderbuffer, Texture2D, WebGl};
+
+/// an image attached to a framebuffer, owned by the framebuffer so it lives as long as it does
+pub enum Attachment {
+    Texture(Texture2D),
+    Renderbuffer(Renderbuffer),
+}
+
+impl Attachment {
+    /// the attached texture, if the attachment can be sampled
+    pub fn texture(&self) -> Option<&Texture2D> {
+        match self {
+            Attachment::Texture(texture) => Some(texture),
+            Attachment::Renderbuffer(_) => None,
+        }
+    }
+
+    fn restore(&mut self) -> Result<(), JsValue> {
+        match self {
+            Attachment::Texture(texture) => texture.restore(),
+            Attachment::Renderbuffer(renderbuffer) => renderbuffer.restore(),
+        }
+    }
+}
+
+/// an offscreen render target that owns its color and depth attachments. Like `GlBuffer` the
+/// framebuffer is only bound between `bind` and `unbind`, and unbinding goes back to drawing to
+/// the canvas
+pub struct Framebuffer {
+    gl: WebGl,
+    framebuffer: WebGlFramebuffer,
+    width: u32,
+    height: u32,
+    color: Option<Attachment>,
+    depth: Option<Attachment>,
+}
+
+impl Framebuffer {
+    /// create a framebuffer without any attachments. `width` and `height` are the size every
+    /// attachment is expected to have and the viewport that should be used when rendering to it
+    pub fn new(gl: &WebGl, width: u32, height: u32) -> Result<Framebuffer, JsValue> {
+        let framebuffer = gl
+            .create_framebuffer()
+            .ok_or("unable to create framebuffer")?;
+        Ok(Framebuffer {
+            gl: gl.clone(),
+            framebuffer,
+            width,
+            height,
+            color: None,
+            depth: None,
+        })
+    }
+
+    /// attach a texture as the color buffer, replacing any previous color attachment
+    pub fn attach_color_texture(&mut self, texture: Texture2D) {
+        self.attach(Attachment::Texture(texture), true);
+    }
+
+    /// attach a renderbuffer as the color buffer, replacing any previous color attachment
+    pub fn attach_color_renderbuffer(&mut self, renderbuffer: Renderbuffer) {
+        self.attach(Attachment::Renderbuffer(renderbuffer), true);
+    }
+
+    /// attach a DEPTH_COMPONENT texture (see `Capabilities::depth_textures`) as the depth buffer
+    pub fn attach_depth_texture(&mut self, texture: Texture2D) {
+        self.attach(Attachment::Texture(texture), false);
+    }
+
+    /// attach a renderbuffer as the depth buffer. DEPTH_STENCIL renderbuffers are attached to
+    /// the combined depth stencil attachment point
+    pub fn attach_depth_renderbuffer(&mut self, renderbuffer: Renderbuffer) {
+        self.attach(Attachment::Renderbuffer(renderbuffer), false);
+    }
+
+    fn attach(&mut self, attachment: Attachment, color: bool) {
+        self.bind();
+        Framebuffer::attach_to_point(&self.gl, &attachment, color);
+        self.unbind();
+        if color {
+            self.color = Some(attachment);
+        } else {
+            self.depth = Some(attachment);
+        }
+    }
+
+    /// attach to the color attachment point, or to the depth point that suits the format.
+    /// DEPTH_STENCIL renderbuffers go to the combined depth stencil point
+    fn attach_to_point(gl: &WebGl, attachment: &Attachment, color: bool) {
+        let point = match attachment {
+            _ if color => WebGlCtx::COLOR_ATTACHMENT0,
+            Attachment::Renderbuffer(renderbuffer)
+                if renderbuffer.format() == WebGlCtx::DEPTH_STENCIL =>
+            {
+                WebGlCtx::DEPTH_STENCIL_ATTACHMENT
+            }
+            _ => WebGlCtx::DEPTH_ATTACHMENT,
+        };
+        match attachment {
+            Attachment::Texture(texture) => gl.framebuffer_texture_2d(
+                WebGlCtx::FRAMEBUFFER,
+                point,
+                WebGlCtx::TEXTURE_2D,
+                Some(texture),
+                0,
+            ),
+            Attachment::Renderbuffer(renderbuffer) => gl.framebuffer_renderbuffer(
+                WebGlCtx::FRAMEBUFFER,
+                point,
+                WebGlCtx::RENDERBUFFER,
+                Some(renderbuffer),
+            ),
+        }
+    }
+
+    /// create the framebuffer and its attachments again and reattach them, after the context
+    /// was lost and restored. What was rendered into it is gone
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        self.framebuffer = self
+            .gl
+            .create_framebuffer()
+            .ok_or("unable to create framebuffer")?;
+        self.bind();
+        for (attachment, color) in [(&mut self.color, true), (&mut self.depth, false)] {
+            if let Some(attachment) = attachment {
+                attachment.restore()?;
+                Framebuffer::attach_to_point(&self.gl, attachment, color);
+            }
+        }
+        self.unbind();
+        Ok(())
+    }
+
+    /// check that the attachments make up a framebuffer that can be rendered to, describing
+    /// the problem if they don't
+    pub fn check_status(&self) -> Result<(), JsValue> {
+        self.bind();
+        let status = self.gl.check_framebuffer_status(WebGlCtx::FRAMEBUFFER);
+        self.unbind();
+        let problem = match status {
+            WebGlCtx::FRAMEBUFFER_COMPLETE => return Ok(()),
+            WebGlCtx::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => {
+                "an attachment is incomplete or its format can't be rendered to"
+            }
+            WebGlCtx::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => "nothing is attached",
+            WebGlCtx::FRAMEBUFFER_INCOMPLETE_DIMENSIONS => "the attachments differ in size",
+            WebGlCtx::FRAMEBUFFER_UNSUPPORTED => {
+                "the combination of attachment formats isn't supported"
+            }
+            _ => "of an unknown reason",
+        };
+        Err(format!(
+            "framebuffer is incomplete because {} (status {:#x})",
+            problem, status
+        )
+        .into())
+    }
+
+    /// bind the framebuffer to the stored WebGl instance so draws render into it
+    pub fn bind(&self) {
+        self.gl
+            .bind_framebuffer(WebGlCtx::FRAMEBUFFER, Some(&self.framebuffer));
+    }
+
+    /// unbind the framebuffer so draws go to the canvas again
+    pub fn unbind(&self) {
+        self.gl.bind_framebuffer(WebGlCtx::FRAMEBUFFER, None);
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn color(&self) -> Option<&Attachment> {
+        self.color.as_ref()
+    }
+
+    pub fn depth(&self) -> Option<&Attachment> {
+        self.depth.as_ref()
+    }
+}
+
+impl Deref for Framebuffer {
+    type Target = WebGlFramebuffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.framebuffer
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        self.gl.delete_framebuffer(Some(&self.framebuffer));
+    }
+}
@@ -1,19 +1,41 @@
 use std::rc::Rc;
 
-use web_sys::WebGlRenderingContext;
+mod capabilities;
+pub use capabilities::Capabilities;
+
+mod context;
+pub use context::{GlContext, GlStateStats, GlVersion};
+
+mod context_options;
+pub use context_options::{ContextOptions, PowerPreference};
+
+mod context_loss;
+pub use context_loss::{ContextLossListener, ContextStatus};
 
 /// a type alias for a reference counted webgl rendering context that can be passed into other
 /// structs and functions
-pub type WebGl = Rc<WebGlRenderingContext>;
+pub type WebGl = Rc<GlContext>;
 
 mod buffer;
 pub use buffer::GlBuffer;
 
 mod shader;
-pub use shader::Shader;
+pub use shader::{upgrade_shader_source, Shader};
 
 mod program;
 pub use program::Program;
 
 mod texture;
 pub use texture::Texture2D;
+
+mod texture_cube;
+pub use texture_cube::{TextureCube, CUBE_FACE_TARGETS};
+
+mod renderbuffer;
+pub use renderbuffer::Renderbuffer;
+
+mod framebuffer;
+pub use framebuffer::{Attachment, Framebuffer};
+
+mod vertex_array;
+pub use vertex_array::VertexArray;
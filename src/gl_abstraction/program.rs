@@ -1,4 +1,5 @@
 use std::ops::Deref;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use wasm_bindgen::JsValue;
 use web_sys::{WebGlProgram, WebGlRenderingContext};
@@ -6,9 +7,15 @@ use web_sys::{WebGlProgram, WebGlRenderingContext};
 use super::shader::Shader;
 use super::WebGl;
 
+static NEXT_PROGRAM_ID: AtomicU32 = AtomicU32::new(0);
+
 pub struct Program {
     gl: WebGl,
     program: WebGlProgram,
+    id: u32,
+    /// the type and source of every shader linked, since the shaders themselves are usually
+    /// dropped after linking
+    sources: Vec<(u32, String)>,
 }
 
 impl Program {
@@ -17,9 +24,31 @@ impl Program {
         Ok(Program {
             gl: gl.clone(),
             program,
+            id: NEXT_PROGRAM_ID.fetch_add(1, Ordering::Relaxed),
+            sources: shaders
+                .iter()
+                .map(|shader| (shader.type_(), shader.source().to_string()))
+                .collect(),
         })
     }
 
+    /// a number unique to this program, used to sort draws that share it together
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// compile and link the shaders again, after the context was lost and restored. The id
+    /// stays the same
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        let shaders = self
+            .sources
+            .iter()
+            .map(|(type_, source)| Shader::new(&self.gl, *type_, source))
+            .collect::<Result<Vec<Shader>, JsValue>>()?;
+        self.program = link_program(&self.gl, &shaders)?;
+        Ok(())
+    }
+
     pub fn set_used(&self) {
         self.gl.use_program(Some(&self.program));
     }
@@ -0,0 +1,78 @@
+use std::ops::Deref;
+
+use wasm_bindgen::JsValue;
+use web_sys::{WebGlRenderbuffer, WebGlRenderingContext as WebGlCtx};
+
+use super::WebGl;
+
+/// storage for a single framebuffer image that can be rendered to but not sampled, e.g. a depth
+/// buffer that only exists for depth testing
+pub struct Renderbuffer {
+    gl: WebGl,
+    renderbuffer: WebGlRenderbuffer,
+    format: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Renderbuffer {
+    /// create a new renderbuffer
+    /// # Arguments
+    /// * `gl` - a reference counted pointer to the webgl context that should be linked to
+    /// * `format` - the internal format e.g. DEPTH_COMPONENT16, RGBA4 or DEPTH_STENCIL
+    /// * `width` / `height` - the size of the storage in pixels
+    pub fn new(gl: &WebGl, format: u32, width: u32, height: u32) -> Result<Renderbuffer, JsValue> {
+        let renderbuffer = gl
+            .create_renderbuffer()
+            .ok_or("unable to create renderbuffer")?;
+        gl.bind_renderbuffer(WebGlCtx::RENDERBUFFER, Some(&renderbuffer));
+        gl.renderbuffer_storage(WebGlCtx::RENDERBUFFER, format, width as i32, height as i32);
+        gl.bind_renderbuffer(WebGlCtx::RENDERBUFFER, None);
+        Ok(Renderbuffer {
+            gl: gl.clone(),
+            renderbuffer,
+            format,
+            width,
+            height,
+        })
+    }
+
+    /// create the storage again, after the context was lost and restored
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        *self = Renderbuffer::new(&self.gl, self.format, self.width, self.height)?;
+        Ok(())
+    }
+
+    /// bind the renderbuffer to the stored WebGl instance
+    pub fn bind(&self) {
+        self.gl
+            .bind_renderbuffer(WebGlCtx::RENDERBUFFER, Some(&self.renderbuffer));
+    }
+
+    /// unbind the renderbuffer from the stored WebGl instance
+    pub fn unbind(&self) {
+        self.gl.bind_renderbuffer(WebGlCtx::RENDERBUFFER, None);
+    }
+
+    pub fn format(&self) -> u32 {
+        self.format
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl Deref for Renderbuffer {
+    type Target = WebGlRenderbuffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.renderbuffer
+    }
+}
+
+impl Drop for Renderbuffer {
+    fn drop(&mut self) {
+        self.gl.delete_renderbuffer(Some(&self.renderbuffer));
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::ops::Deref;
 
 use wasm_bindgen::JsValue;
@@ -8,16 +9,53 @@ use super::WebGl;
 pub struct Shader {
     gl: WebGl,
     shader: WebGlShader,
+    type_: u32,
+    source: String,
 }
 
 impl Shader {
+    /// compile glsl es 1.00 `source`, upgrading it to glsl es 3.00 on webgl 2 contexts.
+    /// Fragment shaders get `FRAGMENT_PRECISION` defined as the highest float precision they
+    /// have, highp or mediump
     pub fn new(gl: &WebGl, type_: u32, source: &str) -> Result<Shader, JsValue> {
-        let shader = compile_shader(gl, type_, source)?;
+        let defined = if type_ == WebGlRenderingContext::FRAGMENT_SHADER {
+            let precision = if gl.capabilities().fragment_high_float {
+                "highp"
+            } else {
+                "mediump"
+            };
+            format!("#define {} {}\n{}", FRAGMENT_PRECISION, precision, source).into()
+        } else {
+            Cow::Borrowed(source)
+        };
+        let shader = if gl.is_webgl2() {
+            compile_shader(gl, type_, &upgrade_shader_source(&defined, type_))?
+        } else {
+            compile_shader(gl, type_, &defined)?
+        };
         Ok(Shader {
             gl: gl.clone(),
             shader,
+            type_,
+            source: source.to_string(),
         })
     }
+
+    /// the type of shader, VERTEX_SHADER or FRAGMENT_SHADER
+    pub fn type_(&self) -> u32 {
+        self.type_
+    }
+
+    /// the source as it was passed in, before any upgrading
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// compile the source again, after the context was lost and restored
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        *self = Shader::new(&self.gl, self.type_, &self.source)?;
+        Ok(())
+    }
 }
 
 impl Deref for Shader {
@@ -34,6 +72,69 @@ impl Drop for Shader {
     }
 }
 
+/// the macro fragment shaders declare their float precision with, since highp is optional in
+/// webgl 1 fragment shaders
+const FRAGMENT_PRECISION: &str = "FRAGMENT_PRECISION";
+
+/// webgl 1 extensions that glsl es 3.00 has built in, so shaders checking for them take the path
+/// that uses them
+const CORE_EXTENSIONS: [&str; 4] = [
+    "GL_EXT_shader_texture_lod",
+    "GL_OES_standard_derivatives",
+    "GL_EXT_frag_depth",
+    "GL_EXT_draw_buffers",
+];
+
+/// the name the upgraded fragment shader's output gets in place of `gl_FragColor`
+const FRAG_COLOR: &str = "frag_color";
+
+/// rewrite glsl es 1.00 `source` as glsl es 3.00: qualifiers and texture functions take their
+/// new names, `gl_FragColor` becomes a declared output, and extension directives for features
+/// that are now core are dropped
+pub fn upgrade_shader_source(source: &str, shader_type: u32) -> String {
+    let fragment = shader_type == WebGlRenderingContext::FRAGMENT_SHADER;
+    let mut upgraded = String::from("#version 300 es\n");
+    if fragment {
+        upgraded.push_str(&format!("out highp vec4 {};\n", FRAG_COLOR));
+    }
+    for line in source.lines() {
+        let directive: Vec<&str> = line.split_whitespace().collect();
+        match directive.as_slice() {
+            ["#extension", ..] => continue,
+            ["#ifdef", name] if CORE_EXTENSIONS.contains(name) => upgraded.push_str("#if 1"),
+            ["#ifndef", name] if CORE_EXTENSIONS.contains(name) => upgraded.push_str("#if 0"),
+            _ => upgrade_identifiers(line, fragment, &mut upgraded),
+        }
+        upgraded.push('\n');
+    }
+    upgraded
+}
+
+fn upgrade_identifiers(line: &str, fragment: bool, upgraded: &mut String) {
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        upgraded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let identifier = &rest[..end];
+        upgraded.push_str(match identifier {
+            "attribute" => "in",
+            "varying" if fragment => "in",
+            "varying" => "out",
+            "texture2D" | "textureCube" => "texture",
+            "texture2DLodEXT" | "textureCubeLodEXT" | "texture2DLod" | "textureCubeLod" => {
+                "textureLod"
+            }
+            "gl_FragColor" => FRAG_COLOR,
+            _ => identifier,
+        });
+        rest = &rest[end..];
+    }
+    upgraded.push_str(rest);
+}
+
 fn compile_shader(
     gl: &WebGlRenderingContext,
     shader_type: u32,
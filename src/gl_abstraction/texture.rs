@@ -1,55 +1,364 @@
+use std::cell::RefCell;
+use std::ops::Deref;
+
 use wasm_bindgen::JsValue;
-use web_sys::{ImageData, WebGlRenderingContext as WebGlCtx, WebGlTexture};
+use web_sys::{
+    HtmlImageElement, ImageBitmap, ImageData, WebGl2RenderingContext as WebGl2Ctx,
+    WebGlRenderingContext as WebGlCtx, WebGlTexture,
+};
 
 use super::WebGl;
 
+/// what a texture's contents come from, kept so they can be uploaded again after the context
+/// is lost
+enum TextureSource {
+    Image(ImageData),
+    Bitmap(ImageBitmap),
+    Element(HtmlImageElement),
+    Pixels {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+    /// mip levels in a compressed format, largest first
+    Compressed {
+        format: u32,
+        width: u32,
+        height: u32,
+        levels: Vec<Vec<u8>>,
+    },
+    Empty {
+        width: u32,
+        height: u32,
+        format: u32,
+        type_: u32,
+    },
+}
+
 pub struct Texture2D {
     gl: WebGl,
     texture: WebGlTexture,
+    source: TextureSource,
+    /// whether the texels are sRGB encoded colors rather than linear values
+    srgb: bool,
+    /// every parameter set on the texture, replayed when it's restored
+    parameters: RefCell<Vec<(u32, u32)>>,
 }
 
 impl Texture2D {
-    pub fn new(gl: &WebGl, image: &ImageData) -> Result<Texture2D, JsValue> {
-        let texture = gl
-            .create_texture()
-            .ok_or_else(|| "unable to create texture")?;
-        gl.bind_texture(WebGlCtx::TEXTURE_2D, Some(&texture));
-
-        gl.tex_image_2d_with_u32_and_u32_and_image_data(
-            WebGlCtx::TEXTURE_2D,
-            0,
-            WebGlCtx::RGBA as i32,
-            WebGlCtx::RGBA,
-            WebGlCtx::UNSIGNED_BYTE,
-            image,
+    /// create a texture from an image, mipmapped if its size allows. Images larger than the
+    /// context's maximum texture size have to be scaled down first. `srgb` images, like base
+    /// colors, are decoded to linear when sampled if the context has sRGB textures
+    pub fn new(gl: &WebGl, image: &ImageData, srgb: bool) -> Result<Texture2D, JsValue> {
+        Texture2D::from_image_source(gl, TextureSource::Image(image.clone()), srgb)
+    }
+
+    /// create a texture from a bitmap decoded by the browser, like `new`
+    pub fn from_image_bitmap(
+        gl: &WebGl,
+        bitmap: &ImageBitmap,
+        srgb: bool,
+    ) -> Result<Texture2D, JsValue> {
+        Texture2D::from_image_source(gl, TextureSource::Bitmap(bitmap.clone()), srgb)
+    }
+
+    /// create a texture from a loaded `<img>` element, like `new`
+    pub fn from_image_element(
+        gl: &WebGl,
+        element: &HtmlImageElement,
+        srgb: bool,
+    ) -> Result<Texture2D, JsValue> {
+        if !element.complete() || element.natural_width() == 0 {
+            return Err("the image element hasn't loaded".into());
+        }
+        Texture2D::from_image_source(gl, TextureSource::Element(element.clone()), srgb)
+    }
+
+    fn from_image_source(
+        gl: &WebGl,
+        source: TextureSource,
+        srgb: bool,
+    ) -> Result<Texture2D, JsValue> {
+        let texture = Texture2D::create(gl, source, srgb)?;
+        if !texture.has_mipmaps() {
+            texture.set_wrap(WebGlCtx::CLAMP_TO_EDGE, WebGlCtx::CLAMP_TO_EDGE);
+            texture.set_parameter(WebGlCtx::TEXTURE_MIN_FILTER, WebGlCtx::LINEAR);
+        }
+        Ok(texture)
+    }
+
+    /// create a texture from top-down 8 bit rgba pixels, with linear filtering and clamping at
+    /// the edges
+    pub fn from_pixels(
+        gl: &WebGl,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<Texture2D, JsValue> {
+        let texture = Texture2D::create(
+            gl,
+            TextureSource::Pixels {
+                width,
+                height,
+                pixels: pixels.to_vec(),
+            },
+            false,
         )?;
+        texture.set_filter(WebGlCtx::LINEAR, WebGlCtx::LINEAR);
+        texture.set_wrap(WebGlCtx::CLAMP_TO_EDGE, WebGlCtx::CLAMP_TO_EDGE);
+        Ok(texture)
+    }
 
-        if is_power_of_2(image.width()) && is_power_of_2(image.height()) {
-            gl.generate_mipmap(WebGlCtx::TEXTURE_2D);
+    /// create a texture from mip levels in a compressed `format`, e.g. one of the formats of
+    /// `WEBGL_compressed_texture_s3tc`. Textures with more than one level are mipmapped, which
+    /// needs a complete chain of levels in webgl 1, while the rest are clamped at the edges.
+    /// `srgb` colors are never decoded by the context, since the compressed formats are linear
+    pub fn compressed(
+        gl: &WebGl,
+        format: u32,
+        width: u32,
+        height: u32,
+        levels: Vec<Vec<u8>>,
+        srgb: bool,
+    ) -> Result<Texture2D, JsValue> {
+        if levels.is_empty() {
+            return Err("a compressed texture needs at least one mip level".into());
+        }
+        let texture = Texture2D::create(
+            gl,
+            TextureSource::Compressed {
+                format,
+                width,
+                height,
+                levels,
+            },
+            srgb,
+        )?;
+        if texture.has_mipmaps() {
+            texture.set_filter(WebGlCtx::LINEAR_MIPMAP_LINEAR, WebGlCtx::LINEAR);
         } else {
-            gl.tex_parameteri(
+            texture.set_filter(WebGlCtx::LINEAR, WebGlCtx::LINEAR);
+            texture.set_wrap(WebGlCtx::CLAMP_TO_EDGE, WebGlCtx::CLAMP_TO_EDGE);
+        }
+        Ok(texture)
+    }
+
+    /// create a texture with uninitialized contents to render into, using nearest filtering and
+    /// clamping at the edges
+    /// # Arguments
+    /// * `format` - the format of the texels, e.g. RGBA or DEPTH_COMPONENT
+    /// * `type_` - the type of each channel, e.g. UNSIGNED_BYTE or UNSIGNED_INT
+    pub fn empty(
+        gl: &WebGl,
+        width: u32,
+        height: u32,
+        format: u32,
+        type_: u32,
+    ) -> Result<Texture2D, JsValue> {
+        let texture = Texture2D::create(
+            gl,
+            TextureSource::Empty {
+                width,
+                height,
+                format,
+                type_,
+            },
+            false,
+        )?;
+        texture.set_filter(WebGlCtx::NEAREST, WebGlCtx::NEAREST);
+        texture.set_wrap(WebGlCtx::CLAMP_TO_EDGE, WebGlCtx::CLAMP_TO_EDGE);
+        Ok(texture)
+    }
+
+    fn create(gl: &WebGl, source: TextureSource, srgb: bool) -> Result<Texture2D, JsValue> {
+        let (width, height) = source.size();
+        let capabilities = gl.capabilities();
+        if !capabilities.fits_texture(width, height) {
+            return Err(format!(
+                "a {}x{} texture is larger than the maximum size of {}",
+                width, height, capabilities.max_texture_size
+            )
+            .into());
+        }
+        let texture = Texture2D {
+            gl: gl.clone(),
+            texture: gl.create_texture().ok_or("unable to create texture")?,
+            source,
+            srgb,
+            parameters: RefCell::new(Vec::new()),
+        };
+        texture.upload()?;
+        Ok(texture)
+    }
+
+    /// upload the source into the texture, generating mipmaps for images that can have them
+    fn upload(&self) -> Result<(), JsValue> {
+        let gl = &self.gl;
+        let rgba = if self.decodes_srgb() {
+            WebGl2Ctx::SRGB8_ALPHA8
+        } else {
+            WebGlCtx::RGBA
+        } as i32;
+        self.bind();
+        match self.source {
+            TextureSource::Image(ref image) => gl.tex_image_2d_with_u32_and_u32_and_image_data(
                 WebGlCtx::TEXTURE_2D,
-                WebGlCtx::TEXTURE_WRAP_S,
-                WebGlCtx::CLAMP_TO_EDGE as i32,
-            );
-            gl.tex_parameteri(
+                0,
+                rgba,
+                WebGlCtx::RGBA,
+                WebGlCtx::UNSIGNED_BYTE,
+                image,
+            )?,
+            TextureSource::Bitmap(ref bitmap) => gl
+                .tex_image_2d_with_u32_and_u32_and_image_bitmap(
+                    WebGlCtx::TEXTURE_2D,
+                    0,
+                    rgba,
+                    WebGlCtx::RGBA,
+                    WebGlCtx::UNSIGNED_BYTE,
+                    bitmap,
+                )?,
+            TextureSource::Element(ref element) => gl.tex_image_2d_with_u32_and_u32_and_image(
                 WebGlCtx::TEXTURE_2D,
-                WebGlCtx::TEXTURE_WRAP_T,
-                WebGlCtx::CLAMP_TO_EDGE as i32,
-            );
-            gl.tex_parameteri(
+                0,
+                rgba,
+                WebGlCtx::RGBA,
+                WebGlCtx::UNSIGNED_BYTE,
+                element,
+            )?,
+            TextureSource::Pixels {
+                width,
+                height,
+                ref pixels,
+            } => gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
                 WebGlCtx::TEXTURE_2D,
-                WebGlCtx::TEXTURE_MIN_FILTER,
-                WebGlCtx::LINEAR as i32,
-            );
+                0,
+                rgba,
+                width as i32,
+                height as i32,
+                0,
+                WebGlCtx::RGBA,
+                WebGlCtx::UNSIGNED_BYTE,
+                Some(pixels),
+            )?,
+            TextureSource::Empty {
+                width,
+                height,
+                format,
+                type_,
+            } => gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                WebGlCtx::TEXTURE_2D,
+                0,
+                gl.capabilities().internal_format(format, type_) as i32,
+                width as i32,
+                height as i32,
+                0,
+                format,
+                type_,
+                None,
+            )?,
+            TextureSource::Compressed {
+                format,
+                width,
+                height,
+                ref levels,
+            } => {
+                for (level, data) in levels.iter().enumerate() {
+                    gl.compressed_tex_image_2d_with_u8_array(
+                        WebGlCtx::TEXTURE_2D,
+                        level as i32,
+                        format,
+                        (width >> level).max(1) as i32,
+                        (height >> level).max(1) as i32,
+                        0,
+                        data,
+                    );
+                }
+            }
+        }
+        let compressed = matches!(self.source, TextureSource::Compressed { .. });
+        if self.has_mipmaps() && !compressed {
+            gl.generate_mipmap(WebGlCtx::TEXTURE_2D);
         }
+        self.unbind();
+        Ok(())
+    }
 
-        gl.bind_texture(WebGlCtx::TEXTURE_2D, None);
+    /// whether the texture is an image with a mipmap chain, which webgl 1 only allows for power
+    /// of two sizes, or compressed with more than one level
+    fn has_mipmaps(&self) -> bool {
+        let (width, height) = self.source.size();
+        match self.source {
+            TextureSource::Image(_) | TextureSource::Bitmap(_) | TextureSource::Element(_) => {
+                self.gl.capabilities().npot_textures
+                    || (is_power_of_2(width) && is_power_of_2(height))
+            }
+            TextureSource::Compressed { ref levels, .. } => levels.len() > 1,
+            _ => false,
+        }
+    }
 
-        Ok(Texture2D {
-            gl: gl.clone(),
-            texture,
-        })
+    /// whether the texels are sRGB encoded colors
+    pub fn is_srgb(&self) -> bool {
+        self.srgb
+    }
+
+    /// whether sampling the texture decodes its sRGB colors to linear, which only uncompressed
+    /// 8 bit images do, where the context has sRGB textures. Shaders have to decode the colors
+    /// of other sRGB textures themselves
+    pub fn decodes_srgb(&self) -> bool {
+        let uncompressed = matches!(
+            self.source,
+            TextureSource::Image(_)
+                | TextureSource::Bitmap(_)
+                | TextureSource::Element(_)
+                | TextureSource::Pixels { .. }
+        );
+        self.srgb && uncompressed && self.gl.capabilities().srgb_textures
+    }
+
+    /// create the texture again and re-upload its contents and parameters, after the context
+    /// was lost and restored. Textures that were rendered into come back uninitialized
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        self.texture = self.gl.create_texture().ok_or("unable to create texture")?;
+        self.upload()?;
+        self.bind();
+        for &(parameter, value) in self.parameters.borrow().iter() {
+            self.gl
+                .tex_parameteri(WebGlCtx::TEXTURE_2D, parameter, value as i32);
+        }
+        self.unbind();
+        Ok(())
+    }
+
+    /// the width and height in texels
+    pub fn size(&self) -> (u32, u32) {
+        self.source.size()
+    }
+
+    /// set a texture parameter, e.g. TEXTURE_MIN_FILTER, remembering it for `restore`
+    pub fn set_parameter(&self, parameter: u32, value: u32) {
+        self.bind();
+        self.gl
+            .tex_parameteri(WebGlCtx::TEXTURE_2D, parameter, value as i32);
+        self.unbind();
+        let mut parameters = self.parameters.borrow_mut();
+        match parameters.iter_mut().find(|(set, _)| *set == parameter) {
+            Some(set) => set.1 = value,
+            None => parameters.push((parameter, value)),
+        }
+    }
+
+    /// set the minification and magnification filters, e.g. NEAREST or LINEAR
+    pub fn set_filter(&self, min: u32, mag: u32) {
+        self.set_parameter(WebGlCtx::TEXTURE_MIN_FILTER, min);
+        self.set_parameter(WebGlCtx::TEXTURE_MAG_FILTER, mag);
+    }
+
+    /// set the wrapping along the s and t axes, e.g. REPEAT or CLAMP_TO_EDGE
+    pub fn set_wrap(&self, s: u32, t: u32) {
+        self.set_parameter(WebGlCtx::TEXTURE_WRAP_S, s);
+        self.set_parameter(WebGlCtx::TEXTURE_WRAP_T, t);
     }
 
     pub fn bind(&self) {
@@ -62,6 +371,35 @@ impl Texture2D {
     }
 }
 
+impl Deref for Texture2D {
+    type Target = WebGlTexture;
+
+    fn deref(&self) -> &Self::Target {
+        &self.texture
+    }
+}
+
+impl Drop for Texture2D {
+    fn drop(&mut self) {
+        self.gl.delete_texture(Some(&self.texture));
+    }
+}
+
+impl TextureSource {
+    fn size(&self) -> (u32, u32) {
+        match *self {
+            TextureSource::Image(ref image) => (image.width(), image.height()),
+            TextureSource::Bitmap(ref bitmap) => (bitmap.width(), bitmap.height()),
+            TextureSource::Element(ref element) => {
+                (element.natural_width(), element.natural_height())
+            }
+            TextureSource::Pixels { width, height, .. }
+            | TextureSource::Compressed { width, height, .. }
+            | TextureSource::Empty { width, height, .. } => (width, height),
+        }
+    }
+}
+
 fn is_power_of_2(value: u32) -> bool {
     (value & (value - 1)) == 0
 }
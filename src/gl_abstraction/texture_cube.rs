@@ -0,0 +1,224 @@
+use std::ops::Deref;
+
+use wasm_bindgen::JsValue;
+use web_sys::{WebGlRenderingContext as WebGlCtx, WebGlTexture};
+
+use super::WebGl;
+
+/// the face targets in the order faces are passed around in: +X, -X, +Y, -Y, +Z, -Z
+pub const CUBE_FACE_TARGETS: [u32; 6] = [
+    WebGlCtx::TEXTURE_CUBE_MAP_POSITIVE_X,
+    WebGlCtx::TEXTURE_CUBE_MAP_NEGATIVE_X,
+    WebGlCtx::TEXTURE_CUBE_MAP_POSITIVE_Y,
+    WebGlCtx::TEXTURE_CUBE_MAP_NEGATIVE_Y,
+    WebGlCtx::TEXTURE_CUBE_MAP_POSITIVE_Z,
+    WebGlCtx::TEXTURE_CUBE_MAP_NEGATIVE_Z,
+];
+
+/// the faces of every mip level, kept so they can be uploaded again after the context is lost
+enum CubeLevels {
+    Rgba8(Vec<Vec<Vec<u8>>>),
+    F32(Vec<Vec<Vec<f32>>>),
+}
+
+impl CubeLevels {
+    fn len(&self) -> usize {
+        match self {
+            CubeLevels::Rgba8(levels) => levels.len(),
+            CubeLevels::F32(levels) => levels.len(),
+        }
+    }
+}
+
+/// a cube map texture made of six square faces
+pub struct TextureCube {
+    gl: WebGl,
+    texture: WebGlTexture,
+    size: u32,
+    levels: CubeLevels,
+}
+
+impl TextureCube {
+    /// create a cube map from six faces of top-down 8 bit rgba pixels, in +X, -X, +Y, -Y, +Z, -Z
+    /// order, each `size` x `size`
+    pub fn new(gl: &WebGl, size: u32, faces: &[Vec<u8>]) -> Result<TextureCube, JsValue> {
+        check_faces(size, faces.len(), faces.iter().map(Vec::len))?;
+        TextureCube::create(gl, size, CubeLevels::Rgba8(vec![faces.to_vec()]))
+    }
+
+    /// create a mipmapped cube map from 8 bit rgba faces for every level of a full mip chain,
+    /// halving from `size` down to 1
+    pub fn with_mips_rgba8(
+        gl: &WebGl,
+        size: u32,
+        levels: &[Vec<Vec<u8>>],
+    ) -> Result<TextureCube, JsValue> {
+        check_levels(size, levels.len())?;
+        for (level, faces) in levels.iter().enumerate() {
+            check_faces(size >> level, faces.len(), faces.iter().map(Vec::len))?;
+        }
+        TextureCube::create(gl, size, CubeLevels::Rgba8(levels.to_vec()))
+    }
+
+    /// `with_mips_rgba8` for rgba float faces, which needs float texture support (see
+    /// `Capabilities::float_textures`)
+    pub fn with_mips_f32(
+        gl: &WebGl,
+        size: u32,
+        levels: &[Vec<Vec<f32>>],
+    ) -> Result<TextureCube, JsValue> {
+        check_levels(size, levels.len())?;
+        for (level, faces) in levels.iter().enumerate() {
+            check_faces(size >> level, faces.len(), faces.iter().map(Vec::len))?;
+        }
+        TextureCube::create(gl, size, CubeLevels::F32(levels.to_vec()))
+    }
+
+    /// check that faces `size` texels square fit the context, before spending time on their
+    /// contents
+    pub fn check_size(gl: &WebGl, size: u32) -> Result<(), JsValue> {
+        let max_size = gl.capabilities().max_cube_map_size;
+        if size > max_size {
+            return Err(format!(
+                "cube map faces of size {} are larger than the maximum of {}",
+                size, max_size
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn create(gl: &WebGl, size: u32, levels: CubeLevels) -> Result<TextureCube, JsValue> {
+        TextureCube::check_size(gl, size)?;
+        let texture = TextureCube {
+            gl: gl.clone(),
+            texture: gl.create_texture().ok_or("unable to create texture")?,
+            size,
+            levels,
+        };
+        texture.upload()?;
+        Ok(texture)
+    }
+
+    /// upload every face of every level and set linear filtering, clamped at the edges
+    fn upload(&self) -> Result<(), JsValue> {
+        let gl = &self.gl;
+        self.bind();
+        for level in 0..self.levels.len() {
+            let size = (self.size >> level).max(1) as i32;
+            for (face, &target) in CUBE_FACE_TARGETS.iter().enumerate() {
+                match self.levels {
+                    CubeLevels::Rgba8(ref levels) => gl
+                        .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                            target,
+                            level as i32,
+                            WebGlCtx::RGBA as i32,
+                            size,
+                            size,
+                            0,
+                            WebGlCtx::RGBA,
+                            WebGlCtx::UNSIGNED_BYTE,
+                            Some(&levels[level][face]),
+                        )?,
+                    CubeLevels::F32(ref levels) => {
+                        let face = js_sys::Float32Array::from(levels[level][face].as_slice());
+                        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+                            target,
+                            level as i32,
+                            gl.capabilities()
+                                .internal_format(WebGlCtx::RGBA, WebGlCtx::FLOAT) as i32,
+                            size,
+                            size,
+                            0,
+                            WebGlCtx::RGBA,
+                            WebGlCtx::FLOAT,
+                            Some(&face),
+                        )?
+                    }
+                }
+            }
+        }
+        let min_filter = if self.levels.len() > 1 {
+            WebGlCtx::LINEAR_MIPMAP_LINEAR
+        } else {
+            WebGlCtx::LINEAR
+        };
+        for &(param, value) in &[
+            (WebGlCtx::TEXTURE_MIN_FILTER, min_filter),
+            (WebGlCtx::TEXTURE_MAG_FILTER, WebGlCtx::LINEAR),
+            (WebGlCtx::TEXTURE_WRAP_S, WebGlCtx::CLAMP_TO_EDGE),
+            (WebGlCtx::TEXTURE_WRAP_T, WebGlCtx::CLAMP_TO_EDGE),
+        ] {
+            gl.tex_parameteri(WebGlCtx::TEXTURE_CUBE_MAP, param, value as i32);
+        }
+        self.unbind();
+        Ok(())
+    }
+
+    /// create the texture again and re-upload every face, after the context was lost and
+    /// restored
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        self.texture = self.gl.create_texture().ok_or("unable to create texture")?;
+        self.upload()
+    }
+
+    /// the width and height of each face
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn bind(&self) {
+        self.gl
+            .bind_texture(WebGlCtx::TEXTURE_CUBE_MAP, Some(&self.texture));
+    }
+
+    pub fn unbind(&self) {
+        self.gl.bind_texture(WebGlCtx::TEXTURE_CUBE_MAP, None);
+    }
+}
+
+impl Deref for TextureCube {
+    type Target = WebGlTexture;
+
+    fn deref(&self) -> &Self::Target {
+        &self.texture
+    }
+}
+
+impl Drop for TextureCube {
+    fn drop(&mut self) {
+        self.gl.delete_texture(Some(&self.texture));
+    }
+}
+
+fn check_levels(size: u32, levels: usize) -> Result<(), JsValue> {
+    let expected = (32 - size.max(1).leading_zeros()) as usize;
+    if !size.is_power_of_two() || levels != expected {
+        return Err(format!(
+            "a mipmapped cube map of size {} needs {} levels but got {}",
+            size, expected, levels
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn check_faces<I>(size: u32, count: usize, lengths: I) -> Result<(), JsValue>
+where
+    I: Iterator<Item = usize>,
+{
+    if count != 6 {
+        return Err(format!("a cube map needs 6 faces but got {}", count).into());
+    }
+    let face_len = size.max(1) as usize * size.max(1) as usize * 4;
+    for len in lengths {
+        if len != face_len {
+            return Err(format!(
+                "cube map faces of size {} need {} values but one has {}",
+                size, face_len, len
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use std::ops::Deref;
+
+use wasm_bindgen::JsValue;
+use web_sys::WebGlVertexArrayObject;
+
+use super::WebGl;
+
+/// a vertex array object, which records attribute pointers and the element buffer so they can
+/// be restored with a single bind. Native in webgl 2 and from `OES_vertex_array_object` in
+/// webgl 1
+pub struct VertexArray {
+    gl: WebGl,
+    vertex_array: WebGlVertexArrayObject,
+}
+
+impl VertexArray {
+    pub fn new(gl: &WebGl) -> Result<VertexArray, JsValue> {
+        if !gl.capabilities().vertex_arrays {
+            return Err("vertex array objects aren't supported".into());
+        }
+        let vertex_array = gl
+            .create_vertex_array()
+            .ok_or("failed to create vertex array")?;
+        Ok(VertexArray {
+            gl: gl.clone(),
+            vertex_array,
+        })
+    }
+
+    /// create the vertex array again after the context was lost and restored. It comes back
+    /// empty, so its attributes have to be set up again
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        self.vertex_array = self
+            .gl
+            .create_vertex_array()
+            .ok_or("failed to create vertex array")?;
+        Ok(())
+    }
+
+    pub fn bind(&self) {
+        self.gl.bind_vertex_array(Some(&self.vertex_array));
+    }
+
+    /// go back to the default vertex array
+    pub fn unbind(&self) {
+        self.gl.bind_vertex_array(None);
+    }
+}
+
+impl Deref for VertexArray {
+    type Target = WebGlVertexArrayObject;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vertex_array
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        self.gl.delete_vertex_array(Some(&self.vertex_array));
+    }
+}
@@ -0,0 +1,88 @@
+use nalgebra as na;
+
+/// the width and height of the generated brdf lookup table
+pub const BRDF_LUT_SIZE: u32 = 32;
+const BRDF_SAMPLES: u32 = 128;
+
+/// the `i`th of `count` points of the hammersley low discrepancy sequence on the unit square
+pub fn hammersley(i: u32, count: u32) -> (f32, f32) {
+    (
+        i as f32 / count as f32,
+        i.reverse_bits() as f32 / 4_294_967_296.0,
+    )
+}
+
+/// a half vector around `normal` distributed according to the ggx normal distribution for
+/// `roughness`, from a point `xi` on the unit square
+pub fn importance_sample_ggx(
+    xi: (f32, f32),
+    normal: &na::Vector3<f32>,
+    roughness: f32,
+) -> na::Vector3<f32> {
+    let alpha = roughness * roughness;
+    let phi = 2.0 * std::f32::consts::PI * xi.0;
+    let cos_theta = ((1.0 - xi.1) / (1.0 + (alpha * alpha - 1.0) * xi.1)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let up = if normal.z.abs() < 0.999 {
+        na::Vector3::z()
+    } else {
+        na::Vector3::x()
+    };
+    let tangent = up.cross(normal).normalize();
+    let bitangent = normal.cross(&tangent);
+    (tangent * sin_theta * phi.cos() + bitangent * sin_theta * phi.sin() + normal * cos_theta)
+        .normalize()
+}
+
+/// the ggx normal distribution function
+pub fn ggx_distribution(n_dot_h: f32, roughness: f32) -> f32 {
+    let alpha2 = roughness.powi(4);
+    let denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (std::f32::consts::PI * denominator * denominator)
+}
+
+fn smith_ggx_geometry(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    let schlick = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+    schlick(n_dot_v) * schlick(n_dot_l)
+}
+
+/// the split sum scale and bias applied to F0 for a view angle (as n dot v) and roughness
+pub fn integrate_brdf(n_dot_v: f32, roughness: f32) -> (f32, f32) {
+    let view = na::Vector3::new((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+    let normal = na::Vector3::z();
+    let (mut scale, mut bias) = (0.0, 0.0);
+    for i in 0..BRDF_SAMPLES {
+        let half = importance_sample_ggx(hammersley(i, BRDF_SAMPLES), &normal, roughness);
+        let light = half * 2.0 * view.dot(&half) - view;
+        let n_dot_l = light.z.max(0.0);
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+        let n_dot_h = half.z.max(0.0);
+        let v_dot_h = view.dot(&half).max(0.0);
+        let visibility = smith_ggx_geometry(n_dot_v, n_dot_l, roughness) * v_dot_h
+            / (n_dot_h * n_dot_v).max(1e-4);
+        let fresnel = (1.0 - v_dot_h).powi(5);
+        scale += (1.0 - fresnel) * visibility;
+        bias += fresnel * visibility;
+    }
+    (scale / BRDF_SAMPLES as f32, bias / BRDF_SAMPLES as f32)
+}
+
+/// the split sum brdf lookup table as 8 bit rgba, with the scale in red and the bias in
+/// green, n dot v increasing along each row and roughness increasing from the first row
+pub fn brdf_lut() -> Vec<u8> {
+    let size = BRDF_LUT_SIZE;
+    let mut pixels = Vec::with_capacity(size as usize * size as usize * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let n_dot_v = (x as f32 + 0.5) / size as f32;
+            let roughness = (y as f32 + 0.5) / size as f32;
+            let (scale, bias) = integrate_brdf(n_dot_v, roughness);
+            let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            pixels.extend_from_slice(&[to_byte(scale), to_byte(bias), 0, 255]);
+        }
+    }
+    pixels
+}
@@ -0,0 +1,113 @@
+use wasm_bindgen::JsValue;
+use web_sys::WebGlRenderingContext as WebGlCtx;
+
+use super::brdf::{brdf_lut, BRDF_LUT_SIZE};
+use super::prefilter::{encode_rgbm, mip_level_count, prefilter_specular};
+use super::sh::{irradiance_sh, SH_COEFFICIENTS};
+use super::HdrImage;
+use crate::gl_abstraction::{Texture2D, TextureCube, WebGl};
+
+/// the texture unit the prefiltered specular cube map is bound to while drawing
+pub const ENVIRONMENT_TEXTURE_UNIT: u32 = 3;
+/// the texture unit the brdf lookup table is bound to while drawing
+pub const BRDF_LUT_TEXTURE_UNIT: u32 = 4;
+
+/// everything needed to light a scene from an environment map: spherical harmonics for the
+/// diffuse irradiance, a prefiltered cube map for specular reflections and the split sum brdf
+/// lookup table
+pub struct Environment {
+    irradiance_sh: [f32; SH_COEFFICIENTS * 3],
+    specular: TextureCube,
+    brdf_lut: Texture2D,
+    rgbm: bool,
+    intensity: f32,
+}
+
+impl Environment {
+    /// precompute the lighting for an equirectangular hdr image, prefiltering into cube faces
+    /// of `face_size` texels, which must be a power of two. The specular map is stored as
+    /// floats when the context can filter them and rgbm encoded otherwise
+    pub fn from_hdr(gl: &WebGl, image: &HdrImage, face_size: u32) -> Result<Environment, JsValue> {
+        if !face_size.is_power_of_two() {
+            return Err(format!(
+                "environment face size must be a power of two, not {}",
+                face_size
+            )
+            .into());
+        }
+        // prefiltering allocates every face of every level up front
+        TextureCube::check_size(gl, face_size)?;
+        let mut coefficients = [0.0; SH_COEFFICIENTS * 3];
+        for (out, coefficient) in coefficients.chunks_exact_mut(3).zip(&irradiance_sh(image)) {
+            out.copy_from_slice(coefficient);
+        }
+
+        let levels = prefilter_specular(image, face_size);
+        let float_textures = gl.capabilities().float_textures;
+        let specular = if float_textures {
+            TextureCube::with_mips_f32(gl, face_size, &levels)?
+        } else {
+            let levels: Vec<Vec<Vec<u8>>> = levels
+                .iter()
+                .map(|faces| faces.iter().map(|face| encode_rgbm(face)).collect())
+                .collect();
+            TextureCube::with_mips_rgba8(gl, face_size, &levels)?
+        };
+        let brdf_lut = Texture2D::from_pixels(gl, BRDF_LUT_SIZE, BRDF_LUT_SIZE, &brdf_lut())?;
+
+        Ok(Environment {
+            irradiance_sh: coefficients,
+            specular,
+            brdf_lut,
+            rgbm: !float_textures,
+            intensity: 1.0,
+        })
+    }
+
+    /// upload the specular map and brdf lookup table again after the context was lost and
+    /// restored
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        self.specular.restore()?;
+        self.brdf_lut.restore()
+    }
+
+    /// the rgb irradiance spherical harmonic coefficients, flattened
+    pub fn irradiance_sh(&self) -> &[f32] {
+        &self.irradiance_sh
+    }
+
+    pub fn specular(&self) -> &TextureCube {
+        &self.specular
+    }
+
+    /// the mip level of the specular map holding fully rough reflections
+    pub fn max_lod(&self) -> f32 {
+        (mip_level_count(self.specular.size()) - 1) as f32
+    }
+
+    pub fn brdf_lut(&self) -> &Texture2D {
+        &self.brdf_lut
+    }
+
+    /// whether the specular map is rgbm encoded rather than stored as floats
+    pub fn rgbm(&self) -> bool {
+        self.rgbm
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.max(0.0);
+    }
+
+    /// bind the specular map and brdf lookup table to their texture units
+    pub fn bind_textures(&self, gl: &WebGl) {
+        gl.active_texture(WebGlCtx::TEXTURE0 + ENVIRONMENT_TEXTURE_UNIT);
+        self.specular.bind();
+        gl.active_texture(WebGlCtx::TEXTURE0 + BRDF_LUT_TEXTURE_UNIT);
+        self.brdf_lut.bind();
+        gl.active_texture(WebGlCtx::TEXTURE0);
+    }
+}
@@ -0,0 +1,202 @@
+/// the largest width or height of an hdr image
+const MAX_SIZE: u32 = 16384;
+
+/// a decoded radiance `.hdr` image as top-down linear rgb floats
+#[derive(Debug, Clone, PartialEq)]
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<f32>,
+}
+
+impl HdrImage {
+    /// parse a radiance rgbe file, supporting both flat and new-style run length encoded
+    /// scanlines with the usual `-Y height +X width` orientation
+    pub fn parse(data: &[u8]) -> Result<HdrImage, String> {
+        let mut cursor = 0;
+        let magic = read_line(data, &mut cursor)?;
+        if !magic.starts_with("#?") {
+            return Err("not a radiance hdr file".into());
+        }
+        loop {
+            let line = read_line(data, &mut cursor)?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(format!("unsupported hdr pixel format {}", format));
+                }
+            }
+        }
+        let resolution = read_line(data, &mut cursor)?;
+        let (width, height) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", height, "+X", width] => (
+                width.parse::<u32>().map_err(|err| err.to_string())?,
+                height.parse::<u32>().map_err(|err| err.to_string())?,
+            ),
+            _ => return Err(format!("unsupported hdr orientation \"{}\"", resolution)),
+        };
+
+        if width == 0 || height == 0 {
+            return Err("hdr image is empty".into());
+        }
+        if width > MAX_SIZE || height > MAX_SIZE {
+            return Err(format!(
+                "{}x{} hdr images are over the {} pixel limit",
+                width, height, MAX_SIZE
+            ));
+        }
+        let floats = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or("hdr image is too large")?;
+        // even fully run length encoded, every scanline takes some bytes
+        let min_size = min_scanline_size(width as usize).saturating_mul(height as usize);
+        if data.len() - cursor < min_size {
+            return Err("hdr pixel data is shorter than the image".into());
+        }
+        let mut pixels = Vec::with_capacity(floats);
+        let mut scanline = vec![[0u8; 4]; width as usize];
+        for _ in 0..height {
+            read_scanline(data, &mut cursor, &mut scanline)?;
+            for rgbe in &scanline {
+                pixels.extend_from_slice(&rgbe_to_rgb(*rgbe));
+            }
+        }
+        Ok(HdrImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+/// convert a shared exponent rgbe texel to linear rgb
+pub fn rgbe_to_rgb([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+    if e == 0 {
+        return [0.0; 3];
+    }
+    let scale = 2f32.powi(e as i32 - (128 + 8));
+    [
+        (r as f32 + 0.5) * scale,
+        (g as f32 + 0.5) * scale,
+        (b as f32 + 0.5) * scale,
+    ]
+}
+
+fn read_line<'a>(data: &'a [u8], cursor: &mut usize) -> Result<&'a str, String> {
+    let start = *cursor;
+    let end = data[start..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|offset| start + offset)
+        .ok_or("hdr header ended unexpectedly")?;
+    *cursor = end + 1;
+    std::str::from_utf8(&data[start..end])
+        .map(str::trim_end)
+        .map_err(|_| "hdr header isn't valid text".to_string())
+}
+
+fn read_byte(data: &[u8], cursor: &mut usize) -> Result<u8, String> {
+    let byte = *data
+        .get(*cursor)
+        .ok_or("hdr pixel data ended unexpectedly")?;
+    *cursor += 1;
+    Ok(byte)
+}
+
+/// the fewest bytes a scanline of `width` texels can be encoded in: four per texel, or a header
+/// and a two byte run per 127 texels of each channel when run length encoded
+fn min_scanline_size(width: usize) -> usize {
+    let runs = width.div_ceil(127);
+    (width * 4).min(4 + runs * 2 * 4)
+}
+
+fn read_scanline(data: &[u8], cursor: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+    let width = scanline.len();
+    let header = data
+        .get(*cursor..*cursor + 4)
+        .ok_or("hdr pixel data ended unexpectedly")?;
+    let run_length_encoded =
+        (8..0x8000).contains(&width) && header[0] == 2 && header[1] == 2 && header[2] & 0x80 == 0;
+    if !run_length_encoded {
+        for texel in scanline.iter_mut() {
+            for channel in texel.iter_mut() {
+                *channel = read_byte(data, cursor)?;
+            }
+        }
+        return Ok(());
+    }
+    if ((header[2] as usize) << 8 | header[3] as usize) != width {
+        return Err("hdr scanline width doesn't match the image width".into());
+    }
+    *cursor += 4;
+    // each channel of the scanline is encoded separately as runs and literal spans
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = read_byte(data, cursor)? as usize;
+            if count > 128 {
+                let count = count - 128;
+                if x + count > width {
+                    return Err("hdr run overflows the scanline".into());
+                }
+                let value = read_byte(data, cursor)?;
+                for texel in &mut scanline[x..x + count] {
+                    texel[channel] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err("hdr span overflows the scanline".into());
+                }
+                for texel in &mut scanline[x..x + count] {
+                    texel[channel] = read_byte(data, cursor)?;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hdr(resolution: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut data =
+            format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution).into_bytes();
+        data.extend_from_slice(pixels);
+        data
+    }
+
+    #[test]
+    fn parses_flat_scanlines() {
+        let image = HdrImage::parse(&hdr("-Y 1 +X 2", &[128, 0, 0, 129, 0, 0, 0, 0])).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels.len(), 6);
+        assert_eq!(image.pixels[3..], [0.0; 3]);
+    }
+
+    #[test]
+    fn rejects_huge_dimensions() {
+        assert!(HdrImage::parse(&hdr("-Y 4294967295 +X 4294967295", &[])).is_err());
+        assert!(HdrImage::parse(&hdr("-Y 16384 +X 16385", &[])).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_images() {
+        for resolution in ["-Y 0 +X 4", "-Y 4 +X 0", "-Y 0 +X 0"].iter() {
+            let err = HdrImage::parse(&hdr(resolution, &[0; 16])).unwrap_err();
+            assert!(err.contains("empty"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn rejects_data_shorter_than_the_image() {
+        let err = HdrImage::parse(&hdr("-Y 16384 +X 16384", &[0; 64])).unwrap_err();
+        assert!(err.contains("shorter"), "{}", err);
+    }
+}
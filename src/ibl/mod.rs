@@ -0,0 +1,16 @@
+mod brdf;
+pub use brdf::{
+    brdf_lut, ggx_distribution, hammersley, importance_sample_ggx, integrate_brdf, BRDF_LUT_SIZE,
+};
+
+mod environment;
+pub use environment::{Environment, BRDF_LUT_TEXTURE_UNIT, ENVIRONMENT_TEXTURE_UNIT};
+
+mod hdr;
+pub use hdr::{rgbe_to_rgb, HdrImage};
+
+mod prefilter;
+pub use prefilter::{encode_rgbm, mip_level_count, prefilter_specular, RGBM_RANGE};
+
+mod sh;
+pub use sh::{equirect_direction, eval_sh, irradiance_sh, sh_basis, SH_COEFFICIENTS};
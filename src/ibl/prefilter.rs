@@ -0,0 +1,140 @@
+use nalgebra as na;
+
+use super::brdf::{ggx_distribution, hammersley, importance_sample_ggx};
+use super::HdrImage;
+use crate::cubemap::{equirect_uv, for_each_cube_texel, sample_equirect};
+
+const PREFILTER_SAMPLES: u32 = 64;
+
+/// an equirectangular image and successively halved copies of it, so wide lobes can be
+/// sampled without aliasing
+struct EquirectPyramid {
+    levels: Vec<HdrImage>,
+}
+
+impl EquirectPyramid {
+    fn new(image: &HdrImage) -> EquirectPyramid {
+        let mut levels = vec![image.clone()];
+        loop {
+            let last = levels.last().unwrap();
+            if last.width < 2 || last.height < 2 {
+                break;
+            }
+            let (width, height) = (last.width / 2, last.height / 2);
+            let mut pixels = Vec::with_capacity(width as usize * height as usize * 3);
+            for y in 0..height as usize {
+                for x in 0..width as usize {
+                    for channel in 0..3 {
+                        let texel = |dx: usize, dy: usize| {
+                            last.pixels
+                                [((y * 2 + dy) * last.width as usize + x * 2 + dx) * 3 + channel]
+                        };
+                        pixels.push((texel(0, 0) + texel(1, 0) + texel(0, 1) + texel(1, 1)) * 0.25);
+                    }
+                }
+            }
+            levels.push(HdrImage {
+                width,
+                height,
+                pixels,
+            });
+        }
+        EquirectPyramid { levels }
+    }
+
+    fn sample(&self, direction: &na::Vector3<f32>, lod: f32, out: &mut [f32]) {
+        let level = &self.levels[(lod.round().max(0.0) as usize).min(self.levels.len() - 1)];
+        let (u, v) = equirect_uv(direction);
+        sample_equirect(&level.pixels, level.width, level.height, 3, u, v, out);
+    }
+}
+
+/// the number of mip levels in a full chain for faces `size` texels wide
+pub fn mip_level_count(size: u32) -> u32 {
+    32 - size.max(1).leading_zeros()
+}
+
+/// convolve an equirectangular environment with the ggx lobe into a full cube mip chain,
+/// starting with faces `face_size` texels wide, where level i holds roughness i / (levels - 1).
+/// Each level has six faces of rgba floats in +X, -X, +Y, -Y, +Z, -Z order
+pub fn prefilter_specular(image: &HdrImage, face_size: u32) -> Vec<Vec<Vec<f32>>> {
+    let pyramid = EquirectPyramid::new(image);
+    let levels = mip_level_count(face_size);
+    let source_texel_area = 4.0 * std::f32::consts::PI / (image.width * image.height) as f32;
+    (0..levels)
+        .map(|level| {
+            let size = (face_size >> level).max(1);
+            let roughness = if levels > 1 {
+                level as f32 / (levels - 1) as f32
+            } else {
+                0.0
+            };
+            let mut faces = vec![vec![0.0; size as usize * size as usize * 4]; 6];
+            let mut sample = [0.0; 3];
+            for_each_cube_texel(size, |face, index, normal| {
+                let out = &mut faces[face][index * 4..index * 4 + 4];
+                out[3] = 1.0;
+                if level == 0 {
+                    // a mirror reflection, sampled at the level matching the face density
+                    let texels = (image.width * image.height) as f32;
+                    let lod = 0.5 * (texels / (6.0 * size as f32 * size as f32)).log2();
+                    pyramid.sample(&normal, lod, &mut out[..3]);
+                    return;
+                }
+                // assume the view and reflection directions match the normal
+                let mut weight = 0.0;
+                for i in 0..PREFILTER_SAMPLES {
+                    let half =
+                        importance_sample_ggx(hammersley(i, PREFILTER_SAMPLES), &normal, roughness);
+                    let light = half * 2.0 * normal.dot(&half) - normal;
+                    let n_dot_l = normal.dot(&light);
+                    if n_dot_l <= 0.0 {
+                        continue;
+                    }
+                    // pick a source level whose texels cover about as much as the sample does
+                    let n_dot_h = normal.dot(&half).max(0.0);
+                    let pdf = ggx_distribution(n_dot_h, roughness) * 0.25;
+                    let sample_area = 1.0 / (PREFILTER_SAMPLES as f32 * pdf + 1e-4);
+                    let lod = 0.5 * (sample_area / source_texel_area).log2() + 1.0;
+                    pyramid.sample(&light, lod, &mut sample);
+                    for (o, s) in out.iter_mut().zip(&sample) {
+                        *o += s * n_dot_l;
+                    }
+                    weight += n_dot_l;
+                }
+                if weight > 0.0 {
+                    for o in &mut out[..3] {
+                        *o /= weight;
+                    }
+                }
+            });
+            faces
+        })
+        .collect()
+}
+
+/// the largest value rgbm encoding can represent
+pub const RGBM_RANGE: f32 = 8.0;
+
+/// encode linear rgba floats as 8 bit rgbm, storing a shared multiplier in alpha so that
+/// values up to `RGBM_RANGE` fit in textures without float support
+pub fn encode_rgbm(pixels: &[f32]) -> Vec<u8> {
+    pixels
+        .chunks_exact(4)
+        .flat_map(|texel| {
+            let max = texel[0].max(texel[1]).max(texel[2]) / RGBM_RANGE;
+            let multiplier = (max.clamp(1e-6, 1.0) * 255.0).ceil() / 255.0;
+            let to_byte = |value: f32| {
+                (value / (multiplier * RGBM_RANGE) * 255.0)
+                    .round()
+                    .clamp(0.0, 255.0) as u8
+            };
+            vec![
+                to_byte(texel[0]),
+                to_byte(texel[1]),
+                to_byte(texel[2]),
+                (multiplier * 255.0).round() as u8,
+            ]
+        })
+        .collect()
+}
@@ -0,0 +1,91 @@
+use nalgebra as na;
+
+use super::HdrImage;
+
+/// the number of coefficients in third order (l <= 2) spherical harmonics
+pub const SH_COEFFICIENTS: usize = 9;
+
+/// the real spherical harmonic basis functions up to l = 2 evaluated in `direction`
+pub fn sh_basis(direction: &na::Vector3<f32>) -> [f32; SH_COEFFICIENTS] {
+    let (x, y, z) = (direction.x, direction.y, direction.z);
+    [
+        0.282_095,
+        0.488_603 * y,
+        0.488_603 * z,
+        0.488_603 * x,
+        1.092_548 * x * y,
+        1.092_548 * y * z,
+        0.315_392 * (3.0 * z * z - 1.0),
+        1.092_548 * x * z,
+        0.546_274 * (x * x - y * y),
+    ]
+}
+
+/// the direction through the center of texel (`x`, `y`) of an equirectangular image, the
+/// inverse of `equirect_uv`
+pub fn equirect_direction(x: u32, y: u32, width: u32, height: u32) -> na::Vector3<f32> {
+    let u = (x as f32 + 0.5) / width as f32;
+    let v = (y as f32 + 0.5) / height as f32;
+    let phi = (u - 0.5) * 2.0 * std::f32::consts::PI;
+    let theta = v * std::f32::consts::PI;
+    na::Vector3::new(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+/// project an equirectangular environment onto spherical harmonics and convolve it with a
+/// clamped cosine lobe, giving coefficients that evaluate to the diffuse irradiance in a
+/// direction divided by pi, ready to multiply by albedo
+pub fn irradiance_sh(image: &HdrImage) -> [[f32; 3]; SH_COEFFICIENTS] {
+    let mut coefficients = [[0.0; 3]; SH_COEFFICIENTS];
+    let texel_area = 2.0 * std::f32::consts::PI / image.width as f32 * std::f32::consts::PI
+        / image.height as f32;
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let direction = equirect_direction(x, y, image.width, image.height);
+            // texels near the poles cover less of the sphere
+            let solid_angle = texel_area * (1.0 - direction.y * direction.y).max(0.0).sqrt();
+            let index = (y * image.width + x) as usize * 3;
+            let radiance = &image.pixels[index..index + 3];
+            for (coefficient, basis) in coefficients.iter_mut().zip(&sh_basis(&direction)) {
+                for (c, r) in coefficient.iter_mut().zip(radiance) {
+                    *c += r * basis * solid_angle;
+                }
+            }
+        }
+    }
+    // the cosine lobe's zonal harmonics per band, divided by pi for lambertian radiance
+    let bands = [
+        1.0,
+        2.0 / 3.0,
+        2.0 / 3.0,
+        2.0 / 3.0,
+        0.25,
+        0.25,
+        0.25,
+        0.25,
+        0.25,
+    ];
+    for (coefficient, band) in coefficients.iter_mut().zip(&bands) {
+        for c in coefficient.iter_mut() {
+            *c *= band;
+        }
+    }
+    coefficients
+}
+
+/// evaluate spherical harmonic coefficients from `irradiance_sh` in `direction`
+pub fn eval_sh(
+    coefficients: &[[f32; 3]; SH_COEFFICIENTS],
+    direction: &na::Vector3<f32>,
+) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (coefficient, basis) in coefficients.iter().zip(&sh_basis(direction)) {
+        for (r, c) in result.iter_mut().zip(coefficient) {
+            *r += c * basis;
+        }
+    }
+    result
+}
@@ -0,0 +1,156 @@
+use nalgebra as na;
+use wasm_bindgen::JsValue;
+use web_sys::WebGlRenderingContext as WebGlCtx;
+
+use crate::gl_abstraction::{GlBuffer, WebGl};
+
+/// floats in a column major 4x4 instance transform
+pub const TRANSFORM_FLOATS: usize = 16;
+/// floats in an rgba instance color
+pub const COLOR_FLOATS: usize = 4;
+
+const IDENTITY: [f32; TRANSFORM_FLOATS] = [
+    1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+];
+const WHITE: [f32; COLOR_FLOATS] = [1.0; COLOR_FLOATS];
+
+/// the attribute locations of the per instance transform (a mat4, so four consecutive
+/// locations) and color
+#[derive(Debug, Clone, Copy)]
+pub struct InstanceAttribs {
+    pub model: u32,
+    pub color: u32,
+}
+
+/// copies of a mesh drawn with their own transform and color, relative to the model transform
+pub struct Instances {
+    transforms: GlBuffer<f32>,
+    colors: GlBuffer<f32>,
+}
+
+impl Instances {
+    pub fn new(gl: &WebGl) -> Result<Instances, JsValue> {
+        Ok(Instances {
+            transforms: GlBuffer::new_with_usage(
+                gl,
+                WebGlCtx::ARRAY_BUFFER,
+                Vec::new(),
+                WebGlCtx::DYNAMIC_DRAW,
+            )?,
+            colors: GlBuffer::new_with_usage(
+                gl,
+                WebGlCtx::ARRAY_BUFFER,
+                Vec::new(),
+                WebGlCtx::DYNAMIC_DRAW,
+            )?,
+        })
+    }
+
+    /// replace every instance with column major transforms, 16 floats each. Colors are kept
+    /// for instances that still exist and new ones are white
+    pub fn set_transforms(&mut self, transforms: &[f32]) -> Result<(), String> {
+        if !transforms.len().is_multiple_of(TRANSFORM_FLOATS) {
+            return Err(format!(
+                "instance transforms need {} floats each but got {} floats",
+                TRANSFORM_FLOATS,
+                transforms.len()
+            ));
+        }
+        let count = transforms.len() / TRANSFORM_FLOATS;
+        if count != self.len() {
+            let mut colors = self.colors.array().clone();
+            colors.resize(count * COLOR_FLOATS, 1.0);
+            self.colors.set_array(colors);
+        }
+        self.transforms.set_array(transforms.to_vec());
+        Ok(())
+    }
+
+    /// set the rgba color of every instance, 4 floats each
+    pub fn set_colors(&mut self, colors: &[f32]) -> Result<(), String> {
+        if colors.len() != self.len() * COLOR_FLOATS {
+            return Err(format!(
+                "{} instances need {} color floats but got {}",
+                self.len(),
+                self.len() * COLOR_FLOATS,
+                colors.len()
+            ));
+        }
+        self.colors.set_array(colors.to_vec());
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.transforms.array().len() / TRANSFORM_FLOATS
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// upload the transforms and colors again after the context was lost and restored
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        self.transforms.restore()?;
+        self.colors.restore()
+    }
+
+    pub fn transform(&self, index: usize) -> na::Matrix4<f32> {
+        na::Matrix4::from_column_slice(
+            &self.transforms.array()[index * TRANSFORM_FLOATS..(index + 1) * TRANSFORM_FLOATS],
+        )
+    }
+
+    pub fn color(&self, index: usize) -> &[f32] {
+        &self.colors.array()[index * COLOR_FLOATS..(index + 1) * COLOR_FLOATS]
+    }
+
+    /// stream the transforms and colors into `attribs`, advancing once per instance. Needs
+    /// instancing support
+    pub fn bind(&self, gl: &WebGl, attribs: InstanceAttribs) {
+        self.transforms.bind();
+        let stride = (TRANSFORM_FLOATS * std::mem::size_of::<f32>()) as i32;
+        for column in 0..4 {
+            let location = attribs.model + column;
+            gl.vertex_attrib_pointer_with_i32(
+                location,
+                4,
+                WebGlCtx::FLOAT,
+                false,
+                stride,
+                (column as usize * 4 * std::mem::size_of::<f32>()) as i32,
+            );
+            gl.enable_vertex_attrib_array(location);
+            gl.vertex_attrib_divisor(location, 1);
+        }
+        self.colors.bind();
+        gl.vertex_attrib_pointer_with_i32(attribs.color, 4, WebGlCtx::FLOAT, false, 0, 0);
+        gl.enable_vertex_attrib_array(attribs.color);
+        gl.vertex_attrib_divisor(attribs.color, 1);
+    }
+
+    /// use instance `index` as the constant transform and color, for drawing one at a time
+    /// when instancing isn't supported
+    pub fn set_constant(&self, gl: &WebGl, attribs: InstanceAttribs, index: usize) {
+        let transform =
+            &self.transforms.array()[index * TRANSFORM_FLOATS..(index + 1) * TRANSFORM_FLOATS];
+        set_constant_attribs(gl, attribs, transform, self.color(index));
+    }
+}
+
+/// stop streaming instance attributes and draw everything untransformed and white
+pub fn reset_instance_attribs(gl: &WebGl, attribs: InstanceAttribs) {
+    set_constant_attribs(gl, attribs, &IDENTITY, &WHITE);
+}
+
+fn set_constant_attribs(gl: &WebGl, attribs: InstanceAttribs, transform: &[f32], color: &[f32]) {
+    for column in 0..4 {
+        let location = attribs.model + column;
+        gl.vertex_attrib_divisor(location, 0);
+        gl.disable_vertex_attrib_array(location);
+        let offset = column as usize * 4;
+        gl.vertex_attrib4fv_with_f32_array(location, &transform[offset..offset + 4]);
+    }
+    gl.vertex_attrib_divisor(attribs.color, 0);
+    gl.disable_vertex_attrib_array(attribs.color);
+    gl.vertex_attrib4fv_with_f32_array(attribs.color, color);
+}
@@ -0,0 +1,21 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// call the method `name` of a javascript object
+pub fn call(object: &JsValue, name: &str, arguments: &[JsValue]) -> Result<JsValue, JsValue> {
+    let method: js_sys::Function = js_sys::Reflect::get(object, &name.into())?.dyn_into()?;
+    let arguments: js_sys::Array = arguments.iter().collect();
+    method.apply(object, &arguments)
+}
+
+/// construct an object with the constructor `name` of a javascript module
+pub fn construct(module: &JsValue, name: &str, arguments: &[JsValue]) -> Result<JsValue, JsValue> {
+    let constructor: js_sys::Function = js_sys::Reflect::get(module, &name.into())?.dyn_into()?;
+    let arguments: js_sys::Array = arguments.iter().collect();
+    js_sys::Reflect::construct(&constructor, &arguments)
+}
+
+/// the message of a javascript error, or its debug representation if it isn't a string
+pub fn error_message(err: JsValue) -> String {
+    err.as_string().unwrap_or_else(|| format!("{:?}", err))
+}
@@ -0,0 +1,829 @@
+use std::borrow::Cow;
+use std::io::Read;
+
+use wasm_bindgen::prelude::*;
+
+use crate::gl_abstraction::Capabilities;
+use crate::interop::{call, construct, error_message};
+
+/// the identifier every KTX2 file starts with
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+/// the size of the header and the index that follows it, up to the level index
+const HEADER_LENGTH: usize = 80;
+
+/// data format descriptor color models for basis universal's two encodings
+const KHR_DF_MODEL_ETC1S: u8 = 163;
+const KHR_DF_MODEL_UASTC: u8 = 166;
+const KHR_DF_TRANSFER_SRGB: u8 = 2;
+/// channel ids of the samples that hold alpha, per color model
+const KHR_DF_CHANNEL_ALPHA: u8 = 15;
+const KHR_DF_CHANNEL_UASTC_RGBA: u8 = 3;
+
+/// compressed formats from the webgl extensions, which web-sys doesn't expose
+const COMPRESSED_RGB_S3TC_DXT1_EXT: u32 = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT5_EXT: u32 = 0x83F3;
+const COMPRESSED_RGBA_BPTC_UNORM_EXT: u32 = 0x8E8C;
+const COMPRESSED_RGB8_ETC2: u32 = 0x9274;
+const COMPRESSED_RGBA8_ETC2_EAC: u32 = 0x9278;
+const ETC1_RGB8_OES: u32 = 0x8D64;
+const COMPRESSED_RGBA_ASTC_4X4_KHR: u32 = 0x93B0;
+
+/// how a KTX2 file's mip levels are compressed on top of their texture format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Supercompression {
+    None,
+    /// basis universal's ETC1S encoding, which only the transcoder can read
+    BasisLz,
+    Zstandard,
+    Zlib,
+}
+
+/// a texture format the gpu can sample, that KTX2 textures are uploaded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFormat {
+    Astc4x4,
+    Bc7,
+    Bc3,
+    Bc1,
+    Etc2Rgba,
+    Etc2Rgb,
+    /// the subset of ETC2 that ETC1 decoders can read
+    Etc1,
+    /// uncompressed 8 bit rgba, the fallback every context has
+    Rgba8,
+}
+
+impl TextureFormat {
+    /// the format of a texture stored in the KTX2 `vk_format` rather than as basis universal.
+    /// sRGB formats map to the same formats as linear ones, `Ktx2::is_srgb` tells them apart
+    pub fn from_vk_format(vk_format: u32) -> Option<TextureFormat> {
+        match vk_format {
+            37 | 43 => Some(TextureFormat::Rgba8),
+            131..=134 => Some(TextureFormat::Bc1),
+            137 | 138 => Some(TextureFormat::Bc3),
+            145 | 146 => Some(TextureFormat::Bc7),
+            147 | 148 => Some(TextureFormat::Etc2Rgb),
+            151 | 152 => Some(TextureFormat::Etc2Rgba),
+            157 | 158 => Some(TextureFormat::Astc4x4),
+            _ => None,
+        }
+    }
+
+    /// the number basis universal's transcoder knows the format by, for the formats it can
+    /// transcode to
+    pub fn basis_format(self) -> Option<u32> {
+        match self {
+            TextureFormat::Etc1 => Some(0),
+            TextureFormat::Etc2Rgba => Some(1),
+            TextureFormat::Bc1 => Some(2),
+            TextureFormat::Bc3 => Some(3),
+            TextureFormat::Bc7 => Some(6),
+            TextureFormat::Astc4x4 => Some(10),
+            TextureFormat::Rgba8 => Some(13),
+            TextureFormat::Etc2Rgb => None,
+        }
+    }
+
+    /// the format to upload with, `None` for uncompressed rgba
+    pub fn gl_format(self, capabilities: &Capabilities) -> Option<u32> {
+        match self {
+            TextureFormat::Astc4x4 => Some(COMPRESSED_RGBA_ASTC_4X4_KHR),
+            TextureFormat::Bc7 => Some(COMPRESSED_RGBA_BPTC_UNORM_EXT),
+            TextureFormat::Bc3 => Some(COMPRESSED_RGBA_S3TC_DXT5_EXT),
+            TextureFormat::Bc1 => Some(COMPRESSED_RGB_S3TC_DXT1_EXT),
+            TextureFormat::Etc2Rgba => Some(COMPRESSED_RGBA8_ETC2_EAC),
+            TextureFormat::Etc2Rgb => Some(COMPRESSED_RGB8_ETC2),
+            // ETC2 decoders read ETC1 too, and contexts more often have them
+            TextureFormat::Etc1 if capabilities.etc => Some(COMPRESSED_RGB8_ETC2),
+            TextureFormat::Etc1 => Some(ETC1_RGB8_OES),
+            TextureFormat::Rgba8 => None,
+        }
+    }
+
+    /// how many bytes a `width` x `height` image in this format takes, `None` if that doesn't
+    /// fit in memory
+    pub fn image_length(self, width: u32, height: u32) -> Option<usize> {
+        let (block_size, block_length) = match self {
+            TextureFormat::Rgba8 => (1, 4),
+            TextureFormat::Bc1 | TextureFormat::Etc2Rgb | TextureFormat::Etc1 => (4, 8),
+            _ => (4, 16),
+        };
+        let blocks = (width.div_ceil(block_size) as usize)
+            .checked_mul(height.div_ceil(block_size) as usize)?;
+        blocks.checked_mul(block_length)
+    }
+
+    /// whether a `width` x `height` texture in this format can be uploaded to the context.
+    /// S3TC and BPTC textures have to be made of whole blocks
+    pub fn is_supported(self, capabilities: &Capabilities, width: u32, height: u32) -> bool {
+        let whole_blocks = width.is_multiple_of(4) && height.is_multiple_of(4);
+        match self {
+            TextureFormat::Astc4x4 => capabilities.astc,
+            TextureFormat::Bc7 => capabilities.bptc && whole_blocks,
+            TextureFormat::Bc3 | TextureFormat::Bc1 => capabilities.s3tc && whole_blocks,
+            TextureFormat::Etc2Rgba | TextureFormat::Etc2Rgb => capabilities.etc,
+            TextureFormat::Etc1 => capabilities.etc || capabilities.etc1,
+            TextureFormat::Rgba8 => true,
+        }
+    }
+}
+
+struct Level {
+    offset: usize,
+    length: usize,
+    uncompressed_length: usize,
+}
+
+/// a parsed KTX2 file holding a single 2d texture and its mip levels
+pub struct Ktx2 {
+    data: Vec<u8>,
+    pub vk_format: u32,
+    pub width: u32,
+    pub height: u32,
+    pub supercompression: Supercompression,
+    color_model: u8,
+    transfer_function: u8,
+    has_alpha: bool,
+    levels: Vec<Level>,
+}
+
+impl Ktx2 {
+    pub fn is_ktx2(data: &[u8]) -> bool {
+        data.starts_with(&KTX2_IDENTIFIER)
+    }
+
+    /// parse the header, level index and data format descriptor of a KTX2 file. Cube maps,
+    /// arrays and 3d textures aren't supported
+    pub fn parse(data: Vec<u8>) -> Result<Ktx2, String> {
+        if !Ktx2::is_ktx2(&data) {
+            return Err("not a KTX2 file".into());
+        }
+        if data.len() < HEADER_LENGTH {
+            return Err("the KTX2 header is cut off".into());
+        }
+        let u32_at = |offset: usize| read_u32(&data, offset);
+        let [vk_format, _type_size, width, height, depth, layer_count, face_count, level_count, scheme] =
+            [12, 16, 20, 24, 28, 32, 36, 40, 44].map(u32_at);
+        if width == 0 {
+            return Err("the KTX2 texture has no width".into());
+        }
+        if height == 0 || depth > 0 || layer_count > 0 || face_count != 1 {
+            return Err("only 2d KTX2 textures are supported".into());
+        }
+        let supercompression = match scheme {
+            0 => Supercompression::None,
+            1 => Supercompression::BasisLz,
+            2 => Supercompression::Zstandard,
+            3 => Supercompression::Zlib,
+            _ => return Err(format!("unknown KTX2 supercompression scheme {}", scheme)),
+        };
+
+        // a level count of 0 asks for mipmaps to be generated from the one level there is
+        let level_count = level_count.max(1) as usize;
+        if level_count > 32 || data.len() < HEADER_LENGTH + level_count * 24 {
+            return Err("the KTX2 level index is cut off".into());
+        }
+        let mut levels = Vec::new();
+        for level in 0..level_count {
+            let entry = HEADER_LENGTH + level * 24;
+            let read = |offset| read_u64(&data, entry + offset) as usize;
+            let level = Level {
+                offset: read(0),
+                length: read(8),
+                uncompressed_length: read(16),
+            };
+            if level
+                .offset
+                .checked_add(level.length)
+                .is_none_or(|end| end > data.len())
+            {
+                return Err("a KTX2 mip level is outside the file".into());
+            }
+            levels.push(level);
+        }
+
+        // the basic data format descriptor block, after the descriptor's total size
+        let (dfd_offset, dfd_length) = (u32_at(48) as usize, u32_at(52) as usize);
+        let block = match (
+            dfd_offset.checked_add(4),
+            dfd_offset.checked_add(dfd_length),
+        ) {
+            (Some(block), Some(end)) if dfd_length >= 28 && end <= data.len() => block,
+            _ => return Err("the KTX2 data format descriptor is missing".into()),
+        };
+        let block_size = (u32_at(block + 4) >> 16) as usize;
+        let color_model = data[block + 8];
+        let transfer_function = data[block + 10];
+        let samples_end = block + block_size.min(dfd_length - 4);
+        let channels: Vec<u8> = (block + 24..samples_end)
+            .step_by(16)
+            .filter(|sample| sample + 16 <= samples_end)
+            .map(|sample| data[sample + 3] & 0x0F)
+            .collect();
+        let has_alpha = match color_model {
+            KHR_DF_MODEL_UASTC => channels.first() == Some(&KHR_DF_CHANNEL_UASTC_RGBA),
+            _ => channels.contains(&KHR_DF_CHANNEL_ALPHA),
+        };
+
+        Ok(Ktx2 {
+            data,
+            vk_format,
+            width,
+            height,
+            supercompression,
+            color_model,
+            transfer_function,
+            has_alpha,
+            levels,
+        })
+    }
+
+    /// the whole file
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// the width and height of mip level `level`
+    pub fn level_size(&self, level: usize) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    /// whether the texture is encoded with basis universal and has to be transcoded
+    pub fn is_basis(&self) -> bool {
+        self.is_etc1s() || self.is_uastc()
+    }
+
+    pub fn is_etc1s(&self) -> bool {
+        self.color_model == KHR_DF_MODEL_ETC1S
+    }
+
+    pub fn is_uastc(&self) -> bool {
+        self.color_model == KHR_DF_MODEL_UASTC
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.has_alpha
+    }
+
+    pub fn is_srgb(&self) -> bool {
+        self.transfer_function == KHR_DF_TRANSFER_SRGB
+    }
+
+    /// how many bytes mip level `level` takes once any supercompression is undone, if the
+    /// texture's format is known
+    fn level_length(&self, level: usize) -> Option<usize> {
+        // UASTC has 16 byte 4x4 blocks, the same as ASTC
+        let format = if self.is_uastc() {
+            TextureFormat::Astc4x4
+        } else {
+            TextureFormat::from_vk_format(self.vk_format)?
+        };
+        let (width, height) = self.level_size(level);
+        format.image_length(width, height)
+    }
+
+    /// the contents of mip level `level` in the texture's format, undoing any zstandard or
+    /// zlib supercompression. Levels never decompress past the size their format gives them
+    pub fn level_data(&self, index: usize) -> Result<Cow<'_, [u8]>, String> {
+        let level = self.levels.get(index).ok_or("no such KTX2 mip level")?;
+        let data = &self.data[level.offset..level.offset + level.length];
+        let length = match self.supercompression {
+            Supercompression::None => return Ok(data.into()),
+            Supercompression::BasisLz => return Err("ETC1S textures have to be transcoded".into()),
+            Supercompression::Zstandard | Supercompression::Zlib => level.uncompressed_length,
+        };
+        let expected = self
+            .level_length(index)
+            .ok_or("supercompressed KTX2 textures need a known format")?;
+        if length > expected {
+            return Err(format!(
+                "a KTX2 mip level claims {} bytes where its format takes {}",
+                length, expected
+            ));
+        }
+        let decompressed = match self.supercompression {
+            Supercompression::Zstandard => {
+                let mut decompressed = Vec::with_capacity(length);
+                ruzstd::StreamingDecoder::new(data)
+                    .map_err(|err| err.to_string())
+                    .and_then(|decoder| {
+                        // one byte past the length is enough to tell the level is too large
+                        decoder
+                            .take(length as u64 + 1)
+                            .read_to_end(&mut decompressed)
+                            .map_err(|err| err.to_string())
+                    })
+                    .map_err(|err| format!("invalid zstandard KTX2 level: {}", err))?;
+                decompressed
+            }
+            _ => inflate_zlib(data, length)?,
+        };
+        if decompressed.len() != length {
+            return Err("a KTX2 mip level decompressed to the wrong size".into());
+        }
+        Ok(decompressed.into())
+    }
+}
+
+/// turns basis universal textures into a format the gpu can sample
+pub trait Transcode {
+    /// every mip level of `ktx2` in `format`, largest first
+    fn transcode(&self, ktx2: &Ktx2, format: TextureFormat) -> Result<Vec<Vec<u8>>, String>;
+}
+
+/// the basis universal transcoder from `basis_transcoder.js`, wrapping the module it exports
+/// once it has loaded
+#[wasm_bindgen]
+pub struct BasisTranscoder {
+    module: JsValue,
+}
+
+#[wasm_bindgen]
+impl BasisTranscoder {
+    #[wasm_bindgen(constructor)]
+    pub fn new(module: JsValue) -> Result<BasisTranscoder, JsValue> {
+        call(&module, "initializeBasis", &[])?;
+        Ok(BasisTranscoder { module })
+    }
+}
+
+impl BasisTranscoder {
+    fn transcode_file(
+        &self,
+        file: &JsValue,
+        ktx2: &Ktx2,
+        format: u32,
+    ) -> Result<Vec<Vec<u8>>, JsValue> {
+        if call(file, "isValid", &[])?.is_falsy() || call(file, "startTranscoding", &[])?.is_falsy()
+        {
+            return Err("the transcoder couldn't read the KTX2 file".into());
+        }
+        let format = JsValue::from(format);
+        let mut levels = Vec::with_capacity(ktx2.level_count());
+        for index in 0..ktx2.level_count() {
+            // of the first layer and face, the only ones 2d textures have
+            let (layer, face) = (JsValue::from(0), JsValue::from(0));
+            let level = JsValue::from(index as u32);
+            let size = call(
+                file,
+                "getImageTranscodedSizeInBytes",
+                &[level.clone(), layer.clone(), face.clone(), format.clone()],
+            )?;
+            let output = js_sys::Uint8Array::new_with_length(size.as_f64().unwrap_or(0.0) as u32);
+            // no flags, and the row pitch and row count worked out from the size
+            let arguments = [
+                output.clone().into(),
+                level.clone(),
+                layer,
+                face,
+                format.clone(),
+                0.into(),
+                (-1).into(),
+                (-1).into(),
+            ];
+            if call(file, "transcodeImage", &arguments)?.is_falsy() {
+                return Err(format!("the transcoder failed on mip level {}", index).into());
+            }
+            levels.push(output.to_vec());
+        }
+        Ok(levels)
+    }
+}
+
+impl Transcode for BasisTranscoder {
+    fn transcode(&self, ktx2: &Ktx2, format: TextureFormat) -> Result<Vec<Vec<u8>>, String> {
+        let basis_format = format
+            .basis_format()
+            .ok_or_else(|| format!("basis universal can't transcode to {:?}", format))?;
+        let data = js_sys::Uint8Array::from(ktx2.data());
+        let file = construct(&self.module, "KTX2File", &[data.into()]).map_err(error_message)?;
+        let levels = self.transcode_file(&file, ktx2, basis_format);
+        // the file lives on the transcoder's heap until it's deleted
+        let _ = call(&file, "close", &[]);
+        let _ = call(&file, "delete", &[]);
+        levels.map_err(error_message)
+    }
+}
+
+/// the format to upload a basis universal texture in, out of the ones the context supports.
+/// UASTC keeps the most quality in ASTC and BC7, while ETC1S maps straight onto ETC
+pub fn select_transcode_format(capabilities: &Capabilities, ktx2: &Ktx2) -> TextureFormat {
+    use TextureFormat::*;
+    let preferences: &[TextureFormat] = match (ktx2.is_uastc(), ktx2.has_alpha()) {
+        (true, true) => &[Astc4x4, Bc7, Etc2Rgba, Bc3],
+        (true, false) => &[Astc4x4, Bc7, Etc1, Bc1],
+        (false, true) => &[Etc2Rgba, Bc7, Bc3, Astc4x4],
+        (false, false) => &[Etc1, Bc7, Bc1, Astc4x4],
+    };
+    preferences
+        .iter()
+        .copied()
+        .find(|format| format.is_supported(capabilities, ktx2.width, ktx2.height))
+        .unwrap_or(Rgba8)
+}
+
+/// the mip levels of `ktx2` in a format the context can upload, transcoding basis universal
+/// textures with `transcoder`. Textures falling back to rgba only get their first level
+pub fn texture_levels(
+    ktx2: &Ktx2,
+    capabilities: &Capabilities,
+    transcoder: Option<&dyn Transcode>,
+) -> Result<(TextureFormat, Vec<Vec<u8>>), String> {
+    if ktx2.is_basis() {
+        let transcoder = transcoder.ok_or(
+            "basis universal textures need a transcoder, set one with Tetra.set_basis_transcoder",
+        )?;
+        let format = select_transcode_format(capabilities, ktx2);
+        let mut levels = transcoder.transcode(ktx2, format)?;
+        if levels.is_empty() {
+            return Err("the transcoder returned no mip levels".into());
+        }
+        if format == TextureFormat::Rgba8 {
+            levels.truncate(1);
+        }
+        return Ok((format, levels));
+    }
+    let format = TextureFormat::from_vk_format(ktx2.vk_format)
+        .filter(|format| format.is_supported(capabilities, ktx2.width, ktx2.height))
+        .ok_or_else(|| {
+            format!(
+                "KTX2 textures in vk format {} can't be uploaded to this context",
+                ktx2.vk_format
+            )
+        })?;
+    let count = match format {
+        TextureFormat::Rgba8 => 1,
+        _ => ktx2.level_count(),
+    };
+    let levels = (0..count)
+        .map(|level| ktx2.level_data(level).map(Cow::into_owned))
+        .collect::<Result<_, _>>()?;
+    Ok((format, levels))
+}
+
+/// how many mip levels a full chain down to 1x1 has
+pub fn full_level_count(width: u32, height: u32) -> usize {
+    32 - width.max(height).max(1).leading_zeros() as usize
+}
+
+/// whether a texture of `width` x `height` with `levels` mip levels can be sampled with
+/// mipmapping, which webgl 1 only allows for complete chains of power of two sizes
+pub fn can_mipmap(capabilities: &Capabilities, width: u32, height: u32, levels: usize) -> bool {
+    let power_of_two = width.is_power_of_two() && height.is_power_of_two();
+    levels > 1
+        && (capabilities.npot_textures
+            || (power_of_two && levels == full_level_count(width, height)))
+}
+
+/// inflate zlib compressed `data` into at most `limit` bytes. Data that inflates to more is
+/// cut off at `limit` plus one byte, so it can be told apart
+fn inflate_zlib(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    use miniz_oxide::inflate::core::{decompress, inflate_flags, DecompressorOxide};
+    use miniz_oxide::inflate::TINFLStatus;
+
+    let flags = inflate_flags::TINFL_FLAG_PARSE_ZLIB_HEADER
+        | inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    let mut inflated = vec![0; limit + 1];
+    let mut decompressor = Box::<DecompressorOxide>::default();
+    let (status, _, length) = decompress(
+        &mut decompressor,
+        data,
+        &mut std::io::Cursor::new(inflated.as_mut_slice()),
+        flags,
+    );
+    match status {
+        TINFLStatus::Done | TINFLStatus::HasMoreOutput => {
+            inflated.truncate(length);
+            Ok(inflated)
+        }
+        status => Err(format!("invalid zlib KTX2 level: {:?}", status)),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the data format descriptor color model of uncompressed formats
+    const KHR_DF_MODEL_RGBSDA: u8 = 1;
+
+    /// a KTX2 file to build, with one sample per channel in its data format descriptor
+    struct Fixture {
+        vk_format: u32,
+        width: u32,
+        height: u32,
+        scheme: u32,
+        color_model: u8,
+        transfer_function: u8,
+        channels: Vec<u8>,
+        /// the bytes stored for each level and the length they decompress to
+        levels: Vec<(Vec<u8>, usize)>,
+    }
+
+    impl Fixture {
+        fn rgba8(width: u32, height: u32) -> Fixture {
+            let length = (width * height * 4) as usize;
+            Fixture {
+                vk_format: 37,
+                width,
+                height,
+                scheme: 0,
+                color_model: KHR_DF_MODEL_RGBSDA,
+                transfer_function: 1,
+                channels: vec![0, 1, 2, KHR_DF_CHANNEL_ALPHA],
+                levels: vec![(vec![7; length], length)],
+            }
+        }
+
+        fn basis(color_model: u8, channels: &[u8]) -> Fixture {
+            Fixture {
+                vk_format: 0,
+                color_model,
+                channels: channels.to_vec(),
+                levels: vec![(vec![0; 64], 64)],
+                ..Fixture::rgba8(8, 8)
+            }
+        }
+
+        fn build(&self) -> Vec<u8> {
+            let block_size = 24 + 16 * self.channels.len();
+            let dfd_offset = HEADER_LENGTH + 24 * self.levels.len();
+            let mut data = KTX2_IDENTIFIER.to_vec();
+            let header = [
+                self.vk_format,
+                1,
+                self.width,
+                self.height,
+                0,
+                0,
+                1,
+                self.levels.len() as u32,
+                self.scheme,
+                dfd_offset as u32,
+                4 + block_size as u32,
+            ];
+            data.extend(header.iter().flat_map(|value| value.to_le_bytes()));
+            // no key/value or supercompression global data
+            data.resize(HEADER_LENGTH, 0);
+
+            let mut offset = dfd_offset + 4 + block_size;
+            for (bytes, uncompressed_length) in &self.levels {
+                let entry = [offset, bytes.len(), *uncompressed_length];
+                data.extend(entry.iter().flat_map(|&value| (value as u64).to_le_bytes()));
+                offset += bytes.len();
+            }
+
+            data.extend(((4 + block_size) as u32).to_le_bytes());
+            let mut block = vec![0; 24];
+            block[4..8].copy_from_slice(&((block_size as u32) << 16 | 2).to_le_bytes());
+            block[8] = self.color_model;
+            block[10] = self.transfer_function;
+            for &channel in &self.channels {
+                let mut sample = [0; 16];
+                sample[3] = channel;
+                block.extend_from_slice(&sample);
+            }
+            data.extend(block);
+            for (bytes, _) in &self.levels {
+                data.extend_from_slice(bytes);
+            }
+            data
+        }
+    }
+
+    fn set_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn set_u64(data: &mut [u8], offset: usize, value: u64) {
+        data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn rejection(data: Vec<u8>) -> String {
+        Ktx2::parse(data).err().expect("the KTX2 file was accepted")
+    }
+
+    /// a zstandard frame that stores `bytes` in a single raw block
+    fn zstd_raw(bytes: &[u8]) -> Vec<u8> {
+        // a single segment frame with a one byte content size
+        let mut frame = vec![0x28, 0xB5, 0x2F, 0xFD, 0x20, bytes.len() as u8];
+        let block_header = (bytes.len() as u32) << 3 | 1;
+        frame.extend_from_slice(&block_header.to_le_bytes()[..3]);
+        frame.extend_from_slice(bytes);
+        frame
+    }
+
+    #[test]
+    fn parses_the_header_and_data_format_descriptor() {
+        let ktx2 = Ktx2::parse(
+            Fixture {
+                transfer_function: KHR_DF_TRANSFER_SRGB,
+                ..Fixture::rgba8(4, 2)
+            }
+            .build(),
+        )
+        .unwrap();
+        assert_eq!((ktx2.vk_format, ktx2.width, ktx2.height), (37, 4, 2));
+        assert_eq!(ktx2.supercompression, Supercompression::None);
+        assert_eq!(ktx2.level_count(), 1);
+        assert!(ktx2.has_alpha() && ktx2.is_srgb() && !ktx2.is_basis());
+        assert_eq!(ktx2.level_data(0).unwrap()[..], [7; 32]);
+        assert!(ktx2.level_data(1).is_err());
+    }
+
+    #[test]
+    fn reads_the_basis_encodings_and_their_alpha() {
+        let parse = |color_model, channels: &[u8]| {
+            let ktx2 = Ktx2::parse(Fixture::basis(color_model, channels).build()).unwrap();
+            (ktx2.is_uastc(), ktx2.is_etc1s(), ktx2.has_alpha())
+        };
+        let uastc_rgba = [KHR_DF_CHANNEL_UASTC_RGBA];
+        assert_eq!(parse(KHR_DF_MODEL_UASTC, &uastc_rgba), (true, false, true));
+        assert_eq!(parse(KHR_DF_MODEL_UASTC, &[0]), (true, false, false));
+        let etc1s_rgba = [0, KHR_DF_CHANNEL_ALPHA];
+        assert_eq!(parse(KHR_DF_MODEL_ETC1S, &etc1s_rgba), (false, true, true));
+        assert_eq!(parse(KHR_DF_MODEL_ETC1S, &[0]), (false, true, false));
+    }
+
+    #[test]
+    fn rejects_textures_that_arent_2d() {
+        assert!(Ktx2::parse(b"not a KTX2 file".to_vec()).is_err());
+        assert!(Ktx2::parse(KTX2_IDENTIFIER.to_vec()).is_err());
+        assert!(Ktx2::parse(Fixture::rgba8(0, 4).build()).is_err());
+        assert!(Ktx2::parse(Fixture::rgba8(4, 0).build()).is_err());
+        let mut cube = Fixture::rgba8(4, 4).build();
+        set_u32(&mut cube, 36, 6);
+        assert!(Ktx2::parse(cube).is_err());
+    }
+
+    #[test]
+    fn rejects_cut_off_or_out_of_range_levels() {
+        let data = Fixture::rgba8(4, 4).build();
+        let err = rejection(data[..HEADER_LENGTH + 12].to_vec());
+        assert!(err.contains("level index"), "{}", err);
+        let mut too_many = data.clone();
+        set_u32(&mut too_many, 40, 33);
+        assert!(Ktx2::parse(too_many).is_err());
+
+        let mut past_the_end = data.clone();
+        set_u64(&mut past_the_end, HEADER_LENGTH + 8, data.len() as u64);
+        let err = rejection(past_the_end);
+        assert!(err.contains("outside"), "{}", err);
+        let mut wrapping = data;
+        set_u64(&mut wrapping, HEADER_LENGTH, u64::MAX - 1);
+        assert!(Ktx2::parse(wrapping).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_data_format_descriptors() {
+        let data = Fixture::rgba8(4, 4).build();
+        for (offset, length) in [
+            (u32::MAX, 28),
+            (u32::MAX - 3, 28),
+            (HEADER_LENGTH as u32, u32::MAX),
+            (data.len() as u32 - 20, 28),
+            (HEADER_LENGTH as u32, 20),
+        ]
+        .iter()
+        {
+            let mut data = data.clone();
+            set_u32(&mut data, 48, *offset);
+            set_u32(&mut data, 52, *length);
+            let err = rejection(data);
+            assert!(err.contains("descriptor"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn undoes_supercompression() {
+        let pixels: Vec<u8> = (0..64).collect();
+        let zlib = miniz_oxide::deflate::compress_to_vec_zlib(&pixels, 6);
+        for (scheme, bytes) in [(2, zstd_raw(&pixels)), (3, zlib)].iter().cloned() {
+            let ktx2 = Ktx2::parse(
+                Fixture {
+                    scheme,
+                    levels: vec![(bytes, 64)],
+                    ..Fixture::rgba8(4, 4)
+                }
+                .build(),
+            )
+            .unwrap();
+            assert_eq!(ktx2.level_data(0).unwrap()[..], pixels[..]);
+        }
+    }
+
+    #[test]
+    fn stops_decompressing_at_the_level_size() {
+        let pixels = vec![0; 4096];
+        let zlib = miniz_oxide::deflate::compress_to_vec_zlib(&pixels, 6);
+        let parse = |scheme, bytes: &[u8], uncompressed_length| {
+            Ktx2::parse(
+                Fixture {
+                    scheme,
+                    levels: vec![(bytes.to_vec(), uncompressed_length)],
+                    ..Fixture::rgba8(4, 4)
+                }
+                .build(),
+            )
+            .unwrap()
+        };
+        // a 4x4 rgba texture takes 64 bytes, whatever the level index claims
+        let err = parse(3, &zlib, usize::MAX).level_data(0).unwrap_err();
+        assert!(err.contains("claims"), "{}", err);
+        let err = parse(3, &zlib, 64).level_data(0).unwrap_err();
+        assert!(err.contains("wrong size"), "{}", err);
+        let zstd = zstd_raw(&pixels[..255]);
+        let err = parse(2, &zstd, 64).level_data(0).unwrap_err();
+        assert!(err.contains("wrong size"), "{}", err);
+
+        let mut unknown = parse(3, &zlib, 64);
+        unknown.vk_format = 0;
+        assert!(unknown.level_data(0).is_err());
+    }
+
+    #[test]
+    fn measures_images_in_blocks() {
+        assert_eq!(TextureFormat::Rgba8.image_length(3, 5), Some(60));
+        assert_eq!(TextureFormat::Bc1.image_length(5, 4), Some(16));
+        assert_eq!(TextureFormat::Astc4x4.image_length(1, 1), Some(16));
+        assert_eq!(TextureFormat::Bc7.image_length(8, 8), Some(64));
+    }
+
+    #[test]
+    fn supports_formats_the_context_has() {
+        let mut capabilities = Capabilities::minimal();
+        assert!(TextureFormat::Rgba8.is_supported(&capabilities, 3, 3));
+        assert!(!TextureFormat::Etc1.is_supported(&capabilities, 4, 4));
+        capabilities.etc1 = true;
+        assert!(TextureFormat::Etc1.is_supported(&capabilities, 4, 4));
+        assert!(!TextureFormat::Etc2Rgb.is_supported(&capabilities, 4, 4));
+        capabilities.s3tc = true;
+        capabilities.bptc = true;
+        assert!(TextureFormat::Bc1.is_supported(&capabilities, 8, 4));
+        assert!(TextureFormat::Bc7.is_supported(&capabilities, 4, 8));
+        // S3TC and BPTC need whole 4x4 blocks
+        assert!(!TextureFormat::Bc3.is_supported(&capabilities, 6, 4));
+        assert!(!TextureFormat::Bc7.is_supported(&capabilities, 4, 2));
+    }
+
+    #[test]
+    fn selects_transcode_formats_by_preference() {
+        use TextureFormat::*;
+        let uastc = Ktx2::parse(Fixture::basis(KHR_DF_MODEL_UASTC, &[0]).build()).unwrap();
+        let uastc_rgba = Fixture::basis(KHR_DF_MODEL_UASTC, &[KHR_DF_CHANNEL_UASTC_RGBA]);
+        let uastc_rgba = Ktx2::parse(uastc_rgba.build()).unwrap();
+        let etc1s = Ktx2::parse(Fixture::basis(KHR_DF_MODEL_ETC1S, &[0]).build()).unwrap();
+        let etc1s_rgba = Fixture::basis(KHR_DF_MODEL_ETC1S, &[0, KHR_DF_CHANNEL_ALPHA]);
+        let etc1s_rgba = Ktx2::parse(etc1s_rgba.build()).unwrap();
+
+        let with = |enable: fn(&mut Capabilities)| {
+            let mut capabilities = Capabilities::minimal();
+            enable(&mut capabilities);
+            capabilities
+        };
+        let none = Capabilities::minimal();
+        let astc = with(|c| c.astc = true);
+        let bptc = with(|c| c.bptc = true);
+        let s3tc = with(|c| c.s3tc = true);
+        let etc = with(|c| c.etc = true);
+        let etc1 = with(|c| c.etc1 = true);
+        let everything = with(|c| {
+            c.astc = true;
+            c.bptc = true;
+            c.s3tc = true;
+            c.etc = true;
+        });
+        let cases = [
+            (&everything, [Astc4x4, Astc4x4, Etc2Rgba, Etc1]),
+            (&astc, [Astc4x4, Astc4x4, Astc4x4, Astc4x4]),
+            (&bptc, [Bc7, Bc7, Bc7, Bc7]),
+            (&s3tc, [Bc3, Bc1, Bc3, Bc1]),
+            (&etc, [Etc2Rgba, Etc1, Etc2Rgba, Etc1]),
+            (&etc1, [Rgba8, Etc1, Rgba8, Etc1]),
+            (&none, [Rgba8, Rgba8, Rgba8, Rgba8]),
+        ];
+        for (capabilities, expected) in cases.iter() {
+            let selected = [&uastc_rgba, &uastc, &etc1s_rgba, &etc1s]
+                .map(|ktx2| select_transcode_format(capabilities, ktx2));
+            assert_eq!(selected, *expected, "{:?}", capabilities);
+        }
+    }
+}
@@ -1,19 +1,102 @@
 #![warn(clippy::all)]
-use gltf::mesh::util::{ReadIndices, ReadTexCoords};
-use log::{info, Level};
+use log::{info, warn, Level};
 use nalgebra as na;
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
 use web_sys::{HtmlCanvasElement, ImageData, WebGlRenderingContext};
 
+mod animation;
+pub use animation::{
+    AnimationClip, AnimationMixer, Interpolation, MorphTargets, Pose, Sampler, WeightsChannel,
+};
+
+mod cubemap;
+pub use cubemap::{
+    cube_face_direction, decode_rgba8, equirect_to_cube, equirect_to_cube_rgba8, equirect_uv,
+    for_each_cube_texel, sample_equirect,
+};
+
+mod export;
+pub use export::{encode_png, flip_rows};
+
 mod gl_abstraction;
-pub use gl_abstraction::{GlBuffer, Program, Shader, Texture2D, WebGl};
+pub use gl_abstraction::{
+    upgrade_shader_source, Attachment, Capabilities, ContextLossListener, ContextOptions,
+    ContextStatus, Framebuffer, GlBuffer, GlContext, GlStateStats, GlVersion, PowerPreference,
+    Program, Renderbuffer, Shader, Texture2D, TextureCube, VertexArray, WebGl, CUBE_FACE_TARGETS,
+};
+
+mod ibl;
+pub use ibl::{
+    brdf_lut, encode_rgbm, equirect_direction, eval_sh, ggx_distribution, hammersley,
+    importance_sample_ggx, integrate_brdf, irradiance_sh, mip_level_count, prefilter_specular,
+    rgbe_to_rgb, sh_basis, Environment, HdrImage, BRDF_LUT_SIZE, BRDF_LUT_TEXTURE_UNIT,
+    ENVIRONMENT_TEXTURE_UNIT, RGBM_RANGE, SH_COEFFICIENTS,
+};
+
+mod interop;
+
+mod ktx2;
+pub use ktx2::{
+    can_mipmap, full_level_count, select_transcode_format, texture_levels, BasisTranscoder, Ktx2,
+    Supercompression, TextureFormat, Transcode,
+};
+
+mod instancing;
+pub use instancing::{
+    reset_instance_attribs, InstanceAttribs, Instances, COLOR_FLOATS, TRANSFORM_FLOATS,
+};
+
+mod light;
+pub use light::{lights_from_gltf, Light, LightKind, LightManager, LightUniforms, MAX_LIGHTS};
+
+mod loader;
+#[cfg(not(target_arch = "wasm32"))]
+pub use loader::FileFetch;
+pub use loader::{
+    buffer_data, decode_data_uri, decode_draco_primitives, decode_image, decode_image_bitmap,
+    decode_image_bitmaps, decode_index_buffer, decode_index_sequence, decode_meshopt,
+    decode_model_images, decode_vertex_buffer, external_uris, fetch_gltf, fetch_gltf_assets,
+    image_data, import_gltf, import_gltf_assets, is_data_uri, parse_gltf, percent_decode,
+    resolve_uri, DataUri, DecodeDraco, DracoAttribute, DracoDecoder, DracoMesh, Fetch, FetchFuture,
+    GltfAssets, GltfModel, HttpFetch, ImageAsset, ImageDecoding, LoadProgress, MeshoptFilter,
+    MeshoptMode, ParsedGltf, Resolve,
+};
+
+mod material;
+pub use material::{AlphaMode, Material};
+
+mod mesh;
+pub use mesh::{ElementBuffer, Mesh, Primitive, VertexLayout};
+
+mod post_process;
+pub use post_process::{EffectSettings, PostChain, PostEffect, PostProcessor};
 
 mod program_info;
 pub use program_info::ProgramInfo;
 
+mod render_queue;
+pub use render_queue::{DrawItem, RenderPass, RenderQueue, RenderStats, SortKey};
+
+mod render_state;
+pub use render_state::{BlendMode, CullMode, DepthFunc, RenderState, RenderStateTracker};
+
+mod resources;
+pub use resources::{
+    Handle, MeshHandle, Pool, ProgramHandle, Resources, TextureHandle, UsesTextures,
+};
+
+mod skybox;
+pub use skybox::Skybox;
+
+mod shadow;
+pub use shadow::{
+    light_view_projection, ShadowCaster, ShadowMap, ShadowRenderer, MAX_PCF_RADIUS,
+    MAX_SHADOW_MAPS, SHADOW_TEXTURE_UNIT,
+};
+
 static LOGGING: sync::Once = sync::Once::new();
 
 #[wasm_bindgen]
@@ -21,50 +104,87 @@ pub struct Tetra {
     gl: WebGl,
     viewport_size: (u32, u32),
     shaders: Vec<Shader>,
-    program: Option<Program>,
+    /// the program the scene is drawn with
+    program: Option<ProgramHandle>,
     program_info: Option<ProgramInfo>,
-    vert_buffer: Option<GlBuffer<f32>>,
-    normal_buffer: Option<GlBuffer<f32>>,
-    tex_coord_buffer: Option<GlBuffer<f32>>,
-    element_buffer: Option<GlBuffer<u16>>,
-    texture: Option<Texture2D>,
+    resources: Resources,
+    /// the id the next loaded material gets, so materials from different files never share one
+    next_material_id: u32,
+    /// bound in place of the base color texture for materials without one
+    white_texture: Texture2D,
+    animations: Vec<AnimationClip>,
+    mixer: AnimationMixer,
+    last_timestamp: Option<f32>,
+    lights: LightManager,
+    shadows: Option<ShadowRenderer>,
+    shadow_pcf_radius: i32,
+    bounding_radius: f32,
+    post_process: Option<PostProcessor>,
+    skybox: Option<Skybox>,
+    environment: Option<Environment>,
+    render_state: RenderState,
+    state_tracker: RenderStateTracker,
+    queue: RenderQueue,
+    render_stats: RenderStats,
+    instances: Option<Instances>,
+    context_listener: ContextLossListener,
+    /// transcodes basis universal textures, which can't be loaded without one
+    transcoder: Option<Box<dyn Transcode>>,
 }
 
 #[wasm_bindgen]
 impl Tetra {
+    /// create a renderer drawing to `canvas`. `options` are the attributes to request the
+    /// context with, the browser's defaults if left out
     #[wasm_bindgen(constructor)]
-    pub fn new(canvas: &HtmlCanvasElement) -> Result<Tetra, JsValue> {
+    pub fn new(
+        canvas: &HtmlCanvasElement,
+        options: Option<ContextOptions>,
+    ) -> Result<Tetra, JsValue> {
         console_error_panic_hook::set_once();
         LOGGING.call_once(|| {
             let level = Level::Info;
             console_log::init_with_level(level).unwrap();
             info!("rust logging started with level {:?}", level);
         });
-        let gl = canvas
-            .get_context("webgl")
-            .expect("invalid web context")
-            .expect("unable to get webgl context from #webgl")
-            .dyn_into::<WebGlRenderingContext>()?;
+        let options = options.unwrap_or_default();
+        let gl = Rc::new(GlContext::from_canvas_with_options(canvas, &options)?);
+        info!("using webgl {}", gl.version() as u32);
         let (width, height) = (canvas.width(), canvas.height());
         gl.viewport(0, 0, width as i32, height as i32);
+        let white_texture = Texture2D::from_pixels(&gl, 1, 1, &[255; 4])?;
+        let context_listener = ContextLossListener::new(canvas)?;
         Ok(Tetra {
-            gl: Rc::new(gl),
+            gl,
             viewport_size: (width, height),
             shaders: Vec::new(),
             program: None,
             program_info: None,
-            vert_buffer: None,
-            normal_buffer: None,
-            tex_coord_buffer: None,
-            element_buffer: None,
-            texture: None,
+            resources: Resources::new(),
+            next_material_id: 0,
+            white_texture,
+            animations: Vec::new(),
+            mixer: AnimationMixer::new(),
+            last_timestamp: None,
+            lights: LightManager::default(),
+            shadows: None,
+            shadow_pcf_radius: 1,
+            bounding_radius: 1.0,
+            post_process: None,
+            skybox: None,
+            environment: None,
+            render_state: RenderState::default(),
+            state_tracker: RenderStateTracker::new(),
+            queue: RenderQueue::new(),
+            render_stats: RenderStats::default(),
+            instances: None,
+            context_listener,
+            transcoder: None,
         })
     }
 
-    pub fn refresh_viewport(&mut self, canvas: &HtmlCanvasElement) {
-        let (width, height) = (canvas.width(), canvas.height());
-        self.gl.viewport(0, 0, width as i32, height as i32);
-        self.viewport_size = (width, height);
+    pub fn refresh_viewport(&mut self, canvas: &HtmlCanvasElement) -> Result<(), JsValue> {
+        self.set_viewport_size(canvas.width(), canvas.height())
     }
 
     pub fn add_shader(mut self, shader_type: u32, source: &str) -> Result<Tetra, JsValue> {
@@ -77,176 +197,834 @@ impl Tetra {
         self.add_shader(WebGlRenderingContext::VERTEX_SHADER, source)
     }
 
+    /// add a fragment shader, which can declare `precision FRAGMENT_PRECISION float;` to get
+    /// highp floats where the context has them and mediump elsewhere
     pub fn add_frag_shader(self, source: &str) -> Result<Tetra, JsValue> {
         self.add_shader(WebGlRenderingContext::FRAGMENT_SHADER, source)
     }
 
+    /// link the shaders added so far into a program and draw the scene with it
     pub fn link_program(mut self) -> Result<Tetra, JsValue> {
-        let program = Program::new(&self.gl, &self.shaders)?;
-        self.shaders.clear();
-        self.program_info = Some(ProgramInfo::new(&self.gl, &program)?);
-        self.program = Some(program);
+        let program = self.link_shaders(None)?;
+        self.use_program(&program)?;
         Ok(self)
     }
 
+    /// link the shaders added so far into a program that can be found by `name`, without
+    /// drawing with it yet
+    pub fn link_named_program(&mut self, name: &str) -> Result<ProgramHandle, JsValue> {
+        self.link_shaders(Some(name.to_string()))
+    }
+
+    /// draw the scene with a linked program, which has to take the same attributes and
+    /// uniforms as `tetra.vert` and `tetra.frag`
+    pub fn use_program(&mut self, program: &ProgramHandle) -> Result<(), JsValue> {
+        let linked = self
+            .resources
+            .programs
+            .get(*program)
+            .ok_or("the program has been unloaded")?;
+        self.program_info = Some(ProgramInfo::new(&self.gl, linked)?);
+        self.program = Some(*program);
+        Ok(())
+    }
+
+    pub fn find_program(&self, name: &str) -> Option<ProgramHandle> {
+        self.resources.programs.find(name)
+    }
+
+    /// unload a program other than the one the scene is drawn with, returning false if it
+    /// already was
+    pub fn unload_program(&mut self, program: &ProgramHandle) -> Result<bool, JsValue> {
+        if self.program == Some(*program) {
+            return Err("can't unload the program the scene is drawn with".into());
+        }
+        Ok(self.resources.programs.remove(*program).is_some())
+    }
+
+    /// load a glb file, or a gltf file with everything embedded as data uris, in place of every
+    /// mesh loaded so far, and of every light if it has any
     pub fn load_gltf(mut self, data: &[u8]) -> Result<Tetra, JsValue> {
-        let (gltf, buffers, images) = gltf::import_slice(data).unwrap();
-        for mesh in gltf.meshes() {
-            for primitive in mesh.primitives() {
-                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-                if let Some(iter) = reader.read_positions() {
-                    info!("loading vertices...");
-                    let mut vertices: Vec<f32> = Vec::new();
-                    for vertex_position in iter {
-                        vertices.extend_from_slice(&vertex_position);
-                    }
-                    self.vert_buffer = Some(GlBuffer::new(
-                        &self.gl,
-                        WebGlRenderingContext::ARRAY_BUFFER,
-                        vertices,
-                    )?);
-                    info!("vertices loaded!");
-                }
-                if let Some(tex_coord_iter_enum) = reader.read_tex_coords(0) {
-                    if let ReadTexCoords::F32(tex_coord_iter) = tex_coord_iter_enum {
-                        info!("loading texture coords for texture 0...");
-                        let mut tex_coords: Vec<f32> = Vec::new();
-                        for tex_coord in tex_coord_iter {
-                            tex_coords.extend_from_slice(&tex_coord);
-                        }
-                        self.tex_coord_buffer = Some(GlBuffer::new(
-                            &self.gl,
-                            WebGlRenderingContext::ARRAY_BUFFER,
-                            tex_coords,
-                        )?);
-                        info!("loaded texture coords for texture 0!");
-                    }
-                }
-                if let Some(normals_iter) = reader.read_normals() {
-                    info!("loading normals...");
-                    let mut normals: Vec<f32> = Vec::new();
-                    for normal in normals_iter {
-                        normals.extend_from_slice(&normal);
-                    }
-                    self.normal_buffer = Some(GlBuffer::new(
-                        &self.gl,
-                        WebGlRenderingContext::ARRAY_BUFFER,
-                        normals,
-                    )?);
-                    info!("normals loaded!");
-                }
-                if let Some(indices_type) = reader.read_indices() {
-                    info!("loading indices...");
-                    let mut indicies_temp: Vec<u16> = Vec::new();
-                    if let ReadIndices::U16(indices_buffer) = indices_type {
-                        indicies_temp.extend(indices_buffer);
-                    }
-                    self.element_buffer = Some(GlBuffer::new(
-                        &self.gl,
-                        WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
-                        indicies_temp,
-                    )?);
-                    info!("indicies loaded!");
-                }
+        self.clear_meshes();
+        let assets = import_gltf_assets(data, &HashMap::new(), true, None)?;
+        self.add_assets("model", &assets, true)?;
+        Ok(self)
+    }
+
+    /// load the meshes and textures of a glb file, or a gltf file with everything embedded as
+    /// data uris, alongside the ones already loaded, returning the handles of its meshes. Meshes
+    /// can be found by `"{name}/{mesh name}"`, or `"{name}/{mesh index}"` for unnamed ones, and
+    /// textures by `"{name}/{image index}"`. Files referencing external files have to be
+    /// imported with `import_gltf` and added with `add_model`
+    pub fn add_gltf(&mut self, name: &str, data: &[u8]) -> Result<js_sys::Array, JsValue> {
+        let assets = import_gltf_assets(data, &HashMap::new(), true, None)?;
+        self.add_assets(name, &assets, false)
+    }
+
+    /// add a model fetched with `fetch_gltf` alongside the meshes already loaded, returning
+    /// the handles of its meshes, named like `add_gltf`'s
+    pub fn add_model(&mut self, name: &str, model: &GltfModel) -> Result<js_sys::Array, JsValue> {
+        self.add_assets(name, model.assets(), false)
+    }
+
+    /// transcode basis universal textures with `transcoder` from now on. Models with
+    /// `KHR_texture_basisu` textures that have no fallback image can't be added without one
+    pub fn set_basis_transcoder(&mut self, transcoder: BasisTranscoder) {
+        self.transcoder = Some(Box::new(transcoder));
+    }
+
+    pub fn find_mesh(&self, name: &str) -> Option<MeshHandle> {
+        self.resources.meshes.find(name)
+    }
+
+    pub fn find_texture(&self, name: &str) -> Option<TextureHandle> {
+        self.resources.textures.find(name)
+    }
+
+    pub fn mesh_count(&self) -> usize {
+        self.resources.meshes.len()
+    }
+
+    pub fn texture_count(&self) -> usize {
+        self.resources.textures.len()
+    }
+
+    /// add a holder to a mesh, so it stays loaded until released once more. Returns the new
+    /// reference count
+    pub fn retain_mesh(&mut self, mesh: &MeshHandle) -> Result<u32, JsValue> {
+        Ok(self.resources.meshes.retain(*mesh)?)
+    }
+
+    /// remove a holder from a mesh, unloading it and any textures only it used if that was
+    /// the last. Returns whether it was unloaded
+    pub fn release_mesh(&mut self, mesh: &MeshHandle) -> Result<bool, JsValue> {
+        let unloaded = self.resources.release_mesh(*mesh)?;
+        if unloaded {
+            self.meshes_unloaded();
+        }
+        Ok(unloaded)
+    }
+
+    /// unload a mesh however many holders it has, returning false if it already was
+    pub fn unload_mesh(&mut self, mesh: &MeshHandle) -> bool {
+        let unloaded = self.resources.unload_mesh(*mesh);
+        if unloaded {
+            self.meshes_unloaded();
+        }
+        unloaded
+    }
+
+    pub fn clear_meshes(&mut self) {
+        self.resources.clear_meshes();
+        self.meshes_unloaded();
+    }
+
+    pub fn retain_texture(&mut self, texture: &TextureHandle) -> Result<u32, JsValue> {
+        Ok(self.resources.textures.retain(*texture)?)
+    }
+
+    /// remove a holder from a texture, unloading it if that was the last. Returns whether it
+    /// was unloaded
+    pub fn release_texture(&mut self, texture: &TextureHandle) -> Result<bool, JsValue> {
+        Ok(self.resources.textures.release(*texture)?.is_some())
+    }
+
+    /// unload a texture however many holders it has. Meshes still using it draw without it
+    pub fn unload_texture(&mut self, texture: &TextureHandle) -> bool {
+        self.resources.textures.remove(*texture).is_some()
+    }
+
+    /// load a skybox from six png or jpeg images, one per cube face. Every face must be square
+    /// and the same size
+    pub fn load_skybox_faces(
+        mut self,
+        positive_x: &[u8],
+        negative_x: &[u8],
+        positive_y: &[u8],
+        negative_y: &[u8],
+        positive_z: &[u8],
+        negative_z: &[u8],
+    ) -> Result<Tetra, JsValue> {
+        info!("loading skybox faces...");
+        let mut size = None;
+        let mut faces = Vec::with_capacity(6);
+        for data in &[
+            positive_x, negative_x, positive_y, negative_y, positive_z, negative_z,
+        ] {
+            let (width, height, pixels) = decode_rgba8(data)?;
+            if width != height || matches!(size, Some(size) if size != width) {
+                return Err("skybox faces must be square and all the same size".into());
             }
+            size = Some(width);
+            faces.push(pixels);
         }
-        if !images.is_empty() {
-            info!("loading image for texture 0...");
-            let image = &images[0];
-            let mut data = image.pixels.clone();
-            self.texture = Some(Texture2D::new(
-                &self.gl,
-                &ImageData::new_with_u8_clamped_array_and_sh(
-                    wasm_bindgen::Clamped(&mut data),
-                    image.width,
-                    image.height,
-                )?,
-            )?);
-            info!("loaded image for texture 0!");
+        let texture = TextureCube::new(&self.gl, size.unwrap_or(0), &faces)?;
+        self.skybox = Some(Skybox::new(&self.gl, texture)?);
+        info!("skybox loaded!");
+        Ok(self)
+    }
+
+    /// load a skybox from a single equirectangular (latitude/longitude) png or jpeg, resampled
+    /// into cube faces `face_size` texels square, at most the context's `max_cube_map_size`
+    pub fn load_skybox_equirect(mut self, data: &[u8], face_size: u32) -> Result<Tetra, JsValue> {
+        info!("loading equirectangular skybox...");
+        if face_size == 0 {
+            return Err("skybox faces need a size".into());
         }
+        TextureCube::check_size(&self.gl, face_size)?;
+        let (width, height, pixels) = decode_rgba8(data)?;
+        let faces = equirect_to_cube_rgba8(&pixels, width, height, face_size);
+        let texture = TextureCube::new(&self.gl, face_size, &faces)?;
+        self.skybox = Some(Skybox::new(&self.gl, texture)?);
+        info!("skybox loaded!");
         Ok(self)
     }
 
-    pub fn draw(&mut self, timestamp: f32) {
-        let program = self
-            .program
-            .as_ref()
-            .expect("program has to have been created to draw");
-        program.set_used();
-        let program_info = self
-            .program_info
-            .as_ref()
-            .expect("program info should've been created");
+    pub fn clear_skybox(&mut self) {
+        self.skybox = None;
+    }
 
-        let vert_buffer = self
-            .vert_buffer
-            .as_ref()
-            .expect("vertex buffer must be created to draw");
+    /// light the scene from a radiance `.hdr` equirectangular environment, prefiltered into
+    /// cube faces `face_size` texels square (a power of two, at most the context's
+    /// `max_cube_map_size`)
+    pub fn load_environment_hdr(mut self, data: &[u8], face_size: u32) -> Result<Tetra, JsValue> {
+        info!("loading hdr environment...");
+        let image = HdrImage::parse(data)?;
+        let mut environment = Environment::from_hdr(&self.gl, &image, face_size)?;
+        if let Some(ref previous) = self.environment {
+            environment.set_intensity(previous.intensity());
+        }
+        if environment.rgbm() {
+            warn!("float textures are unsupported, so the environment is rgbm encoded");
+        }
+        self.environment = Some(environment);
+        info!("hdr environment loaded!");
+        Ok(self)
+    }
 
-        let normal_buffer = self
-            .normal_buffer
-            .as_ref()
-            .expect("normal buffer must exist to calculate lighting");
+    /// scale the light contributed by the environment
+    pub fn set_environment_intensity(&mut self, intensity: f32) {
+        if let Some(ref mut environment) = self.environment {
+            environment.set_intensity(intensity);
+        }
+    }
 
-        let tex_coord_buffer = self
-            .tex_coord_buffer
-            .as_ref()
-            .expect("tex coord buffer must exist to render texture");
+    pub fn clear_environment(&mut self) {
+        self.environment = None;
+    }
+
+    /// the names of every loaded animation clip, in load order
+    pub fn clip_names(&self) -> js_sys::Array {
+        self.animations
+            .iter()
+            .map(|clip| JsValue::from_str(clip.name()))
+            .collect()
+    }
+
+    /// play a clip by name from the start, replacing anything that is playing
+    pub fn play_animation(&mut self, name: &str, looping: bool) -> Result<(), JsValue> {
+        let clip = self.clip_index(name)?;
+        self.mixer.play(clip, looping);
+        Ok(())
+    }
+
+    /// fade from whatever is playing to the named clip over `duration` seconds
+    pub fn crossfade_animation(
+        &mut self,
+        name: &str,
+        duration: f32,
+        looping: bool,
+    ) -> Result<(), JsValue> {
+        let clip = self.clip_index(name)?;
+        self.mixer.crossfade(clip, duration, looping);
+        Ok(())
+    }
 
-        let texture = self
-            .texture
+    pub fn stop_animation(&mut self) {
+        self.mixer.stop();
+    }
+
+    pub fn is_animation_playing(&self) -> bool {
+        self.mixer.is_playing()
+    }
+
+    /// the name of the most recently started clip, if anything is playing
+    pub fn current_animation(&self) -> Option<String> {
+        self.mixer
+            .current_clip()
+            .map(|clip| self.animations[clip].name().to_string())
+    }
+
+    /// the playback position of the most recently started clip in seconds
+    pub fn animation_time(&self) -> f32 {
+        self.mixer.current_time()
+    }
+
+    pub fn set_animation_speed(&mut self, speed: f32) -> Result<(), JsValue> {
+        Ok(self.mixer.set_speed(speed)?)
+    }
+
+    /// add a light to the scene, returning its index
+    pub fn add_light(&mut self, light: &Light) -> Result<usize, JsValue> {
+        Ok(self.lights.add(light.clone())?)
+    }
+
+    /// replace the light at `index`
+    pub fn set_light(&mut self, index: usize, light: &Light) -> Result<(), JsValue> {
+        Ok(self.lights.set(index, light.clone())?)
+    }
+
+    /// remove the light at `index`, shifting the indices of the lights after it down by one
+    pub fn remove_light(&mut self, index: usize) -> Result<(), JsValue> {
+        self.lights.remove(index)?;
+        Ok(())
+    }
+
+    pub fn clear_lights(&mut self) {
+        self.lights.clear();
+    }
+
+    pub fn light_count(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn set_ambient_light(&mut self, r: f32, g: f32, b: f32) {
+        self.lights.set_ambient(na::Vector3::new(r, g, b));
+    }
+
+    /// render shadows for lights created with `Light.with_shadows`, using square shadow maps of
+    /// `map_size` texels
+    pub fn enable_shadows(&mut self, map_size: u32) -> Result<(), JsValue> {
+        match self.shadows {
+            Some(ref mut shadows) => shadows.set_map_size(map_size)?,
+            None => {
+                let mut shadows = ShadowRenderer::new(&self.gl, map_size)?;
+                shadows.set_pcf_radius(self.shadow_pcf_radius);
+                self.shadows = Some(shadows);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn disable_shadows(&mut self) {
+        self.shadows = None;
+    }
+
+    /// how many texels around each shadow lookup are averaged, from 0 for hard edges up to 2.
+    /// Kept for when shadows are enabled if they aren't yet
+    pub fn set_shadow_pcf_radius(&mut self, radius: i32) {
+        self.shadow_pcf_radius = radius;
+        if let Some(ref mut shadows) = self.shadows {
+            shadows.set_pcf_radius(radius);
+        }
+    }
+
+    /// append a post process effect to the end of the chain, returning its index
+    pub fn add_post_effect(&mut self, effect: PostEffect) -> Result<usize, JsValue> {
+        let (width, height) = self.viewport_size;
+        let post_process = match self.post_process {
+            Some(ref mut post_process) => post_process,
+            None => self
+                .post_process
+                .get_or_insert(PostProcessor::new(&self.gl)?),
+        };
+        post_process.resize(width, height)?;
+        Ok(post_process.chain_mut().add(effect))
+    }
+
+    pub fn remove_post_effect(&mut self, index: usize) -> Result<(), JsValue> {
+        self.post_chain()?.remove(index)?;
+        Ok(())
+    }
+
+    /// move the effect at `from` so that it runs at position `to` in the chain
+    pub fn move_post_effect(&mut self, from: usize, to: usize) -> Result<(), JsValue> {
+        Ok(self.post_chain()?.move_effect(from, to)?)
+    }
+
+    pub fn set_post_effect_enabled(&mut self, index: usize, enabled: bool) -> Result<(), JsValue> {
+        Ok(self.post_chain()?.set_enabled(index, enabled)?)
+    }
+
+    /// set a named effect parameter: `exposure` for tone mapping, `gamma` for gamma correction,
+    /// `strength` and `radius` for the vignette and `threshold`, `intensity` and `radius` for
+    /// bloom
+    pub fn set_post_effect_param(
+        &mut self,
+        index: usize,
+        name: &str,
+        value: f32,
+    ) -> Result<(), JsValue> {
+        Ok(self.post_chain()?.set_param(index, name, value)?)
+    }
+
+    pub fn clear_post_effects(&mut self) {
+        if let Some(ref mut post_process) = self.post_process {
+            post_process.chain_mut().clear();
+        }
+    }
+
+    pub fn post_effect_count(&self) -> usize {
+        self.post_process
             .as_ref()
-            .expect("texture must have been loaded");
+            .map_or(0, |post_process| post_process.chain().effects().len())
+    }
+    /// the clear values and fixed function state the scene is drawn with. Materials can
+    /// override parts of it, e.g. double-sided materials disable culling
+    pub fn set_render_state(&mut self, state: &RenderState) {
+        self.render_state = state.clone();
+    }
 
-        self.gl.active_texture(WebGlRenderingContext::TEXTURE0);
-        texture.bind();
-        self.gl
-            .uniform1i(Some(&program_info.uniform_locs.sampler), 0);
+    pub fn render_state(&self) -> RenderState {
+        self.render_state.clone()
+    }
 
-        self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
-        self.gl.clear_depth(1.0);
-        self.gl.enable(WebGlRenderingContext::DEPTH_TEST);
-        self.gl.depth_func(WebGlRenderingContext::LEQUAL);
+    pub fn set_clear_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
+        self.render_state = self.render_state.clone().with_clear_color(r, g, b, a);
+    }
 
-        self.gl.clear(
-            WebGlRenderingContext::COLOR_BUFFER_BIT | WebGlRenderingContext::DEPTH_BUFFER_BIT,
-        );
+    /// draw the model once per column major 4x4 transform in `transforms` (16 floats each,
+    /// e.g. a `Float32Array`) instead of once. Instances keep their colors when the count
+    /// doesn't change
+    pub fn set_instance_transforms(&mut self, transforms: &[f32]) -> Result<(), JsValue> {
+        let instances = match self.instances {
+            Some(ref mut instances) => instances,
+            None => self.instances.get_or_insert(Instances::new(&self.gl)?),
+        };
+        Ok(instances.set_transforms(transforms)?)
+    }
 
-        vert_buffer.bind();
-        self.gl.vertex_attrib_pointer_with_i32(
-            program_info.attrib_locs.position,
-            3,
-            WebGlRenderingContext::FLOAT,
-            false,
-            0,
-            0,
-        );
-        self.gl
-            .enable_vertex_attrib_array(program_info.attrib_locs.position);
+    /// tint each instance by an rgba color (4 floats each), multiplied into its base color
+    pub fn set_instance_colors(&mut self, colors: &[f32]) -> Result<(), JsValue> {
+        Ok(self
+            .instances
+            .as_mut()
+            .ok_or("instance transforms have to be set before colors")?
+            .set_colors(colors)?)
+    }
 
-        normal_buffer.bind();
-        self.gl.vertex_attrib_pointer_with_i32(
-            program_info.attrib_locs.normal,
-            3,
-            WebGlRenderingContext::FLOAT,
-            false,
+    pub fn clear_instances(&mut self) {
+        self.instances = None;
+    }
+
+    pub fn instance_count(&self) -> usize {
+        self.instances.as_ref().map_or(0, Instances::len)
+    }
+
+    /// the draw calls and state switches of the last frame
+    pub fn render_stats(&self) -> RenderStats {
+        self.render_stats
+    }
+
+    /// the webgl version the canvas gave us, 2 unless only webgl 1 is available
+    pub fn webgl_version(&self) -> GlVersion {
+        self.gl.version()
+    }
+
+    /// the attributes the context was actually created with, e.g. `antialias` is false if the
+    /// browser couldn't multisample. `None` while the context is lost
+    pub fn get_context_attributes(&self) -> Option<ContextOptions> {
+        self.gl.context_attributes()
+    }
+
+    /// the limits and optional features of the context, which decide the paths textures,
+    /// meshes and effects take
+    pub fn capabilities(&self) -> Capabilities {
+        self.gl.capabilities().clone()
+    }
+
+    /// how many program, buffer, texture, attribute and framebuffer bindings were issued and
+    /// skipped as redundant since the last reset
+    pub fn gl_state_stats(&self) -> GlStateStats {
+        self.gl.stats()
+    }
+
+    pub fn reset_gl_state_stats(&self) {
+        self.gl.reset_stats();
+    }
+
+    /// draw a frame, or nothing while the context is lost. The first frame after the browser
+    /// restores the context recreates every gpu resource first
+    pub fn draw(&mut self, timestamp: f32) -> Result<(), JsValue> {
+        if self.ensure_context()? {
+            self.render(timestamp, None);
+        }
+        Ok(())
+    }
+
+    /// whether the context is currently lost, so nothing is being drawn
+    pub fn is_context_lost(&self) -> bool {
+        self.context_listener.status() == ContextStatus::Lost || self.gl.is_context_lost()
+    }
+
+    /// make the browser lose the context as if the gpu was reset, to test recovery. Returns
+    /// false if the browser can't simulate it
+    pub fn simulate_context_loss(&self) -> bool {
+        self.gl.simulate_loss()
+    }
+
+    /// make the browser restore a context lost with `simulate_context_loss`
+    pub fn simulate_context_restore(&self) -> bool {
+        self.gl.simulate_restore()
+    }
+
+    /// render the current frame offscreen at `width` x `height` and read it back as 8 bit rgba
+    /// pixels, top row first
+    pub fn render_to_pixels(&mut self, width: u32, height: u32) -> Result<Vec<u8>, JsValue> {
+        if !self.ensure_context()? {
+            return Err("can't render while the webgl context is lost".into());
+        }
+        let mut target = Framebuffer::new(&self.gl, width, height)?;
+        target.attach_color_texture(Texture2D::empty(
+            &self.gl,
+            width,
+            height,
+            WebGlRenderingContext::RGBA,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+        )?);
+        target.attach_depth_renderbuffer(Renderbuffer::new(
+            &self.gl,
+            WebGlRenderingContext::DEPTH_COMPONENT16,
+            width,
+            height,
+        )?);
+        target.check_status()?;
+
+        let viewport_size = self.viewport_size;
+        self.set_viewport_size(width, height)?;
+        let timestamp = self.last_timestamp.unwrap_or(0.0);
+        self.render(timestamp, Some(&target));
+
+        let mut pixels = vec![0; width as usize * height as usize * 4];
+        target.bind();
+        let read = self.gl.read_pixels_with_opt_u8_array(
             0,
             0,
+            width as i32,
+            height as i32,
+            WebGlRenderingContext::RGBA,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+            Some(&mut pixels),
         );
-        self.gl
-            .enable_vertex_attrib_array(program_info.attrib_locs.normal);
+        target.unbind();
+        self.set_viewport_size(viewport_size.0, viewport_size.1)?;
+        read?;
 
-        tex_coord_buffer.bind();
-        self.gl.vertex_attrib_pointer_with_i32(
-            program_info.attrib_locs.tex_coord,
-            2,
-            WebGlRenderingContext::FLOAT,
-            false,
-            0,
-            0,
+        flip_rows(&mut pixels, width, height);
+        Ok(pixels)
+    }
+
+    /// like `render_to_pixels` but encoded as a png file, ready to be downloaded as a
+    /// screenshot
+    pub fn render_to_png(&mut self, width: u32, height: u32) -> Result<Vec<u8>, JsValue> {
+        let pixels = self.render_to_pixels(width, height)?;
+        Ok(encode_png(&pixels, width, height)?)
+    }
+}
+
+impl Tetra {
+    fn link_shaders(&mut self, name: Option<String>) -> Result<ProgramHandle, JsValue> {
+        let program = Program::new(&self.gl, &self.shaders)?;
+        self.shaders.clear();
+        Ok(self.resources.programs.insert(name, program)?)
+    }
+
+    /// upload image `index` of a glTF file. Pixels are scaled down if they're larger than a
+    /// texture can be, while images the browser decoded have to fit already
+    fn load_texture(&self, index: usize, image: &ImageAsset) -> Result<Texture2D, JsValue> {
+        info!("loading image for texture {}...", index);
+        match image {
+            ImageAsset::Pixels(image) => self.load_pixels_texture(index, image, true),
+            ImageAsset::Bitmap(bitmap) => Texture2D::from_image_bitmap(&self.gl, bitmap, true),
+            ImageAsset::Element(element) => Texture2D::from_image_element(&self.gl, element, true),
+            ImageAsset::Encoded { .. } => {
+                Err(format!("image {} hasn't been decoded", index).into())
+            }
+            ImageAsset::Ktx2(ktx2) => self.load_ktx2_texture(index, ktx2),
+        }
+    }
+
+    /// upload decoded rgba pixels, scaling them down to the maximum texture size if needed
+    fn load_pixels_texture(
+        &self,
+        index: usize,
+        image: &gltf::image::Data,
+        srgb: bool,
+    ) -> Result<Texture2D, JsValue> {
+        let max_size = self.gl.capabilities().max_texture_size;
+        let (pixels, width, height) =
+            fit_texture(&image.pixels, image.width, image.height, max_size)?;
+        if (width, height) != (image.width, image.height) {
+            warn!(
+                "scaled texture {} down from {}x{} to {}x{} to fit the maximum size",
+                index, image.width, image.height, width, height
+            );
+        }
+        let texture = Texture2D::new(
+            &self.gl,
+            &ImageData::new_with_u8_clamped_array_and_sh(
+                wasm_bindgen::Clamped(&pixels),
+                width,
+                height,
+            )?,
+            srgb,
+        )?;
+        info!("loaded image for texture {}!", index);
+        Ok(texture)
+    }
+
+    /// upload a KTX2 image in its own format or one it can be transcoded to if the context
+    /// supports one, and as rgba otherwise
+    fn load_ktx2_texture(&self, index: usize, ktx2: &Ktx2) -> Result<Texture2D, JsValue> {
+        let capabilities = self.gl.capabilities();
+        let (format, mut levels) = texture_levels(ktx2, &capabilities, self.transcoder.as_deref())?;
+        let gl_format = match format.gl_format(&capabilities) {
+            Some(gl_format) => gl_format,
+            None => {
+                let image = gltf::image::Data {
+                    pixels: levels.swap_remove(0),
+                    format: gltf::image::Format::R8G8B8A8,
+                    width: ktx2.width,
+                    height: ktx2.height,
+                };
+                return self.load_pixels_texture(index, &image, ktx2.is_srgb());
+            }
+        };
+        if !can_mipmap(&capabilities, ktx2.width, ktx2.height, levels.len()) {
+            levels.truncate(1);
+        }
+        let texture = Texture2D::compressed(
+            &self.gl,
+            gl_format,
+            ktx2.width,
+            ktx2.height,
+            levels,
+            ktx2.is_srgb(),
+        )?;
+        info!("loaded texture {} as {:?}!", index, format);
+        Ok(texture)
+    }
+
+    /// load the meshes, textures, animations and lights of a glTF file. Its lights are added to
+    /// the ones in the scene, or take their place if `replace_lights` is set
+    fn add_assets(
+        &mut self,
+        name: &str,
+        assets: &GltfAssets,
+        replace_lights: bool,
+    ) -> Result<js_sys::Array, JsValue> {
+        let GltfAssets {
+            document: gltf,
+            buffers,
+            images,
+            basisu_images,
+        } = assets;
+        // KTX2 images that textures have a fallback image for are allowed to fail
+        let optional = |image: usize| {
+            basisu_images.contains(&Some(image))
+                && !gltf
+                    .textures()
+                    .any(|texture| texture.source().index() == image)
+        };
+        let mut image_textures = Vec::with_capacity(images.len());
+        for (index, image) in images.iter().enumerate() {
+            let texture = match self.load_texture(index, image) {
+                Ok(texture) => texture,
+                Err(err) if optional(index) => {
+                    let reason = err.as_string().unwrap_or_else(|| format!("{:?}", err));
+                    warn!("using fallbacks in place of texture {}: {}", index, reason);
+                    image_textures.push(None);
+                    continue;
+                }
+                Err(err) => return Err(self.abandon_gltf(&image_textures, &[], err)),
+            };
+            match self
+                .resources
+                .textures
+                .insert(Some(format!("{}/{}", name, index)), texture)
+            {
+                Ok(texture) => image_textures.push(Some(texture)),
+                Err(err) => return Err(self.abandon_gltf(&image_textures, &[], err.into())),
+            }
+        }
+        // each glTF texture uses its KTX2 image if that loaded, and its source image otherwise
+        let textures: Vec<Option<TextureHandle>> = gltf
+            .textures()
+            .map(|texture| {
+                basisu_images
+                    .get(texture.index())
+                    .copied()
+                    .flatten()
+                    .and_then(|image| image_textures.get(image).copied().flatten())
+                    .or(image_textures[texture.source().index()])
+            })
+            .collect();
+
+        let first_material_id = self.next_material_id;
+        self.next_material_id += gltf.materials().len() as u32;
+        let mut meshes = Vec::new();
+        for mesh in gltf.meshes() {
+            let mesh_name = match mesh.name() {
+                Some(mesh_name) => format!("{}/{}", name, mesh_name),
+                None => format!("{}/{}", name, mesh.index()),
+            };
+            let loaded = Mesh::from_gltf(&self.gl, &mesh, buffers, &textures, first_material_id)
+                .and_then(|loaded| Ok(self.resources.insert_mesh(Some(mesh_name), loaded)?));
+            match loaded {
+                Ok(handle) => meshes.push(handle),
+                Err(err) => return Err(self.abandon_gltf(&image_textures, &meshes, err)),
+            }
+        }
+        // the meshes hold the textures now, and textures no mesh uses are unloaded
+        for &texture in image_textures.iter().flatten() {
+            self.resources.textures.release(texture)?;
+        }
+        info!(
+            "loaded {} meshes with {} primitives!",
+            meshes.len(),
+            meshes
+                .iter()
+                .filter_map(|&mesh| self.resources.meshes.get(mesh))
+                .map(|mesh| mesh.primitives.len())
+                .sum::<usize>()
         );
-        self.gl
-            .enable_vertex_attrib_array(program_info.attrib_locs.tex_coord);
+        self.update_bounding_radius();
+
+        for animation in gltf.animations() {
+            let clip = AnimationClip::from_gltf(&animation, buffers, &meshes)?;
+            if !clip.weights_channels().is_empty() {
+                info!("loaded animation \"{}\"", clip.name());
+                self.animations.push(clip);
+            }
+        }
+        let lights = lights_from_gltf(gltf);
+        if !lights.is_empty() {
+            info!("loading {} lights...", lights.len());
+            if replace_lights {
+                self.lights.clear();
+            }
+            let room = MAX_LIGHTS - self.lights.len();
+            if lights.len() > room {
+                warn!("only {} more lights fit, the rest won't be used", room);
+            }
+            for light in lights.into_iter().take(room) {
+                self.lights.add(light)?;
+            }
+            info!("lights loaded!");
+        }
+        if !self.animations.is_empty() && !self.mixer.is_playing() {
+            self.mixer.play(0, true);
+        }
+        Ok(meshes.into_iter().map(JsValue::from).collect())
+    }
+
+    /// unload what a glTF file had loaded before failing with `err`
+    fn abandon_gltf(
+        &mut self,
+        textures: &[Option<TextureHandle>],
+        meshes: &[MeshHandle],
+        err: JsValue,
+    ) -> JsValue {
+        for &mesh in meshes {
+            self.resources.unload_mesh(mesh);
+        }
+        for &texture in textures.iter().flatten() {
+            self.resources.textures.remove(texture);
+        }
+        err
+    }
+
+    /// drop the clips that only animated unloaded meshes and refit the shadows to what's left
+    fn meshes_unloaded(&mut self) {
+        let mut clip = 0;
+        while clip < self.animations.len() {
+            let meshes = &self.resources.meshes;
+            if self.animations[clip]
+                .weights_channels()
+                .iter()
+                .any(|channel| meshes.contains(channel.mesh))
+            {
+                clip += 1;
+            } else {
+                info!("unloaded animation \"{}\"", self.animations[clip].name());
+                self.animations.remove(clip);
+                self.mixer.remove_clip(clip);
+            }
+        }
+        self.update_bounding_radius();
+    }
+
+    /// the radius the loaded meshes fit in, which shadow frusta have to cover
+    fn update_bounding_radius(&mut self) {
+        self.bounding_radius = self
+            .resources
+            .meshes
+            .iter()
+            .map(|(_, mesh)| mesh.bounding_radius())
+            .fold(0.0, f32::max);
+    }
+
+    /// check that the context can be drawn with, recreating everything on it first if it was
+    /// just restored
+    fn ensure_context(&mut self) -> Result<bool, JsValue> {
+        match self.context_listener.status() {
+            ContextStatus::Live => Ok(!self.gl.is_context_lost()),
+            ContextStatus::Lost => Ok(false),
+            ContextStatus::Restored => {
+                self.restore_context()?;
+                self.context_listener.set_live();
+                Ok(true)
+            }
+        }
+    }
+
+    /// recreate every gpu resource on a restored context from the copies kept on the cpu.
+    /// Nothing from before the loss is valid any more, including uniform locations
+    fn restore_context(&mut self) -> Result<(), JsValue> {
+        info!("restoring gpu resources after the webgl context was restored...");
+        self.gl.restore();
+        self.state_tracker.invalidate();
+        let (width, height) = self.viewport_size;
+        self.gl.viewport(0, 0, width as i32, height as i32);
+        for shader in &mut self.shaders {
+            shader.restore()?;
+        }
+        self.resources.restore()?;
+        if let Some(program) = self.program {
+            self.use_program(&program)?;
+        }
+        self.white_texture.restore()?;
+        if let Some(ref mut instances) = self.instances {
+            instances.restore()?;
+        }
+        if let Some(ref mut shadows) = self.shadows {
+            shadows.restore()?;
+        }
+        if let Some(ref mut post_process) = self.post_process {
+            post_process.restore()?;
+        }
+        if let Some(ref mut skybox) = self.skybox {
+            skybox.restore()?;
+        }
+        if let Some(ref mut environment) = self.environment {
+            environment.restore()?;
+        }
+        info!("gpu resources restored!");
+        Ok(())
+    }
+
+    fn set_viewport_size(&mut self, width: u32, height: u32) -> Result<(), JsValue> {
+        self.gl.viewport(0, 0, width as i32, height as i32);
+        self.viewport_size = (width, height);
+        if let Some(ref mut post_process) = self.post_process {
+            post_process.resize(width, height)?;
+        }
+        Ok(())
+    }
+
+    /// draw a frame at `timestamp` into `target`, or the canvas if there is none
+    fn render(&mut self, timestamp: f32, target: Option<&Framebuffer>) {
+        self.animate(timestamp);
 
         let model_rot = na::UnitQuaternion::from_scaled_axis(
             na::Vector3::new(1.0, 0.8, 0.0) * timestamp * 0.001,
@@ -262,8 +1040,105 @@ impl Tetra {
             0.1,
             100.0,
         );
+        let model_matrix = model.to_homogeneous();
 
+        let instance_transforms: Vec<na::Matrix4<f32>> = match self.instances {
+            Some(ref instances) => (0..instances.len())
+                .map(|index| instances.transform(index))
+                .collect(),
+            None => Vec::new(),
+        };
+        // shadow frusta have to cover every instance, so pad the radius by the furthest one
+        let scene_radius = instance_transforms
+            .iter()
+            .map(|transform| {
+                let scale = (0..3)
+                    .map(|column| transform.fixed_slice::<na::U3, na::U1>(0, column).norm())
+                    .fold(0.0, f32::max);
+                transform.fixed_slice::<na::U3, na::U1>(0, 3).norm() + self.bounding_radius * scale
+            })
+            .fold(self.bounding_radius, f32::max);
+
+        let shadow_casters: Vec<ShadowCaster> = match self.shadows {
+            Some(_) => self
+                .lights
+                .lights()
+                .iter()
+                .enumerate()
+                .filter(|(_, light)| light.casts_shadows())
+                .filter_map(|(light_index, light)| {
+                    light_view_projection(light, &na::Point3::origin(), scene_radius).map(
+                        |view_projection| ShadowCaster {
+                            light_index,
+                            view_projection,
+                            bias: light.shadow_bias(),
+                        },
+                    )
+                })
+                .take(MAX_SHADOW_MAPS)
+                .collect(),
+            None => Vec::new(),
+        };
+        if let Some(ref shadows) = self.shadows {
+            if !shadow_casters.is_empty() {
+                shadows.render(
+                    &mut self.state_tracker,
+                    &shadow_casters,
+                    &model_matrix,
+                    &instance_transforms,
+                    &self
+                        .resources
+                        .meshes
+                        .iter()
+                        .flat_map(|(_, mesh)| &mesh.primitives)
+                        .collect::<Vec<&Primitive>>(),
+                    &self.resources.textures,
+                );
+                self.gl.viewport(0, 0, width as i32, height as i32);
+            }
+        }
+
+        let post_process = self
+            .post_process
+            .as_ref()
+            .filter(|post_process| post_process.is_active());
+        match (post_process, target) {
+            (Some(post_process), _) => post_process.begin(),
+            (None, Some(target)) => target.bind(),
+            (None, None) => self
+                .gl
+                .bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, None),
+        }
+
+        let programs = &self.resources.programs;
+        let program = self
+            .program
+            .and_then(|program| programs.get(program))
+            .expect("program has to have been created to draw");
+        program.set_used();
+        let program_info = self
+            .program_info
+            .as_ref()
+            .expect("program info should've been created");
+
+        // clear with depth writes on, since the depth mask applies to clears too
+        self.state_tracker
+            .apply(&self.gl, &self.render_state.clone().with_depth_write(true));
+        self.gl.clear(
+            WebGlRenderingContext::COLOR_BUFFER_BIT | WebGlRenderingContext::DEPTH_BUFFER_BIT,
+        );
+
+        self.gl.uniform_matrix4fv_with_f32_array(
+            Some(&program_info.uniform_locs.model),
+            false,
+            model_matrix.as_slice(),
+        );
         let matrix_view = (view * model).to_homogeneous();
+        self.gl.uniform_matrix4fv_with_f32_array(
+            Some(&program_info.uniform_locs.model_view),
+            false,
+            matrix_view.as_slice(),
+        );
         let mvp = projection.as_matrix() * matrix_view;
         self.gl.uniform_matrix4fv_with_f32_array(
             Some(&program_info.uniform_locs.model_view_projection),
@@ -271,32 +1146,318 @@ impl Tetra {
             mvp.as_slice(),
         );
 
-        let normal_matrix = matrix_view.clone().try_inverse().unwrap().transpose();
+        let normal_matrix = matrix_view.try_inverse().unwrap().transpose();
         self.gl.uniform_matrix4fv_with_f32_array(
             Some(&program_info.uniform_locs.normal_matrix),
             false,
             normal_matrix.as_slice(),
         );
 
-        if let Some(ref element_buffer) = self.element_buffer {
-            element_buffer.bind();
-            self.gl.draw_elements_with_i32(
-                WebGlRenderingContext::TRIANGLES,
-                element_buffer.array().len() as i32,
-                WebGlRenderingContext::UNSIGNED_SHORT,
-                0,
+        let light_locs = &program_info.uniform_locs.lights;
+        let lights = self.lights.uniforms(&view);
+        self.gl
+            .uniform3fv_with_f32_array(Some(&light_locs.ambient), self.lights.ambient().as_slice());
+        self.gl.uniform1i(Some(&light_locs.count), lights.count);
+        self.gl
+            .uniform1iv_with_i32_array(Some(&light_locs.kind), &lights.kinds);
+        self.gl
+            .uniform3fv_with_f32_array(Some(&light_locs.color), &lights.colors);
+        self.gl
+            .uniform3fv_with_f32_array(Some(&light_locs.position), &lights.positions);
+        self.gl
+            .uniform3fv_with_f32_array(Some(&light_locs.direction), &lights.directions);
+        self.gl
+            .uniform1fv_with_f32_array(Some(&light_locs.range), &lights.ranges);
+        self.gl
+            .uniform2fv_with_f32_array(Some(&light_locs.cone), &lights.cones);
+
+        let shadow_locs = &program_info.uniform_locs.shadows;
+        let mut shadow_lights = [-1; MAX_SHADOW_MAPS];
+        let mut shadow_matrices = [0.0; MAX_SHADOW_MAPS * 16];
+        let mut shadow_biases = [0.0; MAX_SHADOW_MAPS];
+        let mut shadow_units = [0; MAX_SHADOW_MAPS];
+        for (i, caster) in shadow_casters.iter().enumerate() {
+            shadow_lights[i] = caster.light_index as i32;
+            shadow_matrices[i * 16..i * 16 + 16].copy_from_slice(caster.view_projection.as_slice());
+            shadow_biases[i] = caster.bias;
+        }
+        for (i, unit) in shadow_units.iter_mut().enumerate() {
+            *unit = (SHADOW_TEXTURE_UNIT as usize + i) as i32;
+        }
+        if let Some(ref shadows) = self.shadows {
+            for (map, &unit) in shadows.maps().iter().zip(&shadow_units) {
+                map.bind_texture(&self.gl, unit as u32);
+            }
+            self.gl
+                .uniform1f(Some(&shadow_locs.map_size), shadows.map_size() as f32);
+            self.gl
+                .uniform1i(Some(&shadow_locs.pcf_radius), shadows.pcf_radius());
+            self.gl
+                .uniform1i(Some(&shadow_locs.packed), shadows.packed_depth() as i32);
+        }
+        self.gl
+            .uniform1i(Some(&shadow_locs.count), shadow_casters.len() as i32);
+        self.gl
+            .uniform1iv_with_i32_array(Some(&shadow_locs.light), &shadow_lights);
+        self.gl.uniform_matrix4fv_with_f32_array(
+            Some(&shadow_locs.matrix),
+            false,
+            &shadow_matrices,
+        );
+        self.gl
+            .uniform1fv_with_f32_array(Some(&shadow_locs.bias), &shadow_biases);
+        self.gl
+            .uniform1iv_with_i32_array(Some(&shadow_locs.map), &shadow_units);
+
+        // the samplers always get their own units, since samplers of different types can't
+        // share one even when unused
+        let ibl_locs = &program_info.uniform_locs.ibl;
+        self.gl
+            .uniform1i(Some(&ibl_locs.specular), ENVIRONMENT_TEXTURE_UNIT as i32);
+        self.gl
+            .uniform1i(Some(&ibl_locs.brdf_lut), BRDF_LUT_TEXTURE_UNIT as i32);
+        self.gl
+            .uniform1i(Some(&ibl_locs.enabled), self.environment.is_some() as i32);
+        if let Some(ref environment) = self.environment {
+            environment.bind_textures(&self.gl);
+            self.gl
+                .uniform1f(Some(&ibl_locs.intensity), environment.intensity());
+            self.gl.uniform3fv_with_f32_array(
+                Some(&ibl_locs.irradiance_sh),
+                environment.irradiance_sh(),
+            );
+            self.gl
+                .uniform1f(Some(&ibl_locs.max_lod), environment.max_lod());
+            self.gl
+                .uniform1i(Some(&ibl_locs.rgbm), environment.rgbm() as i32);
+            self.gl.uniform_matrix4fv_with_f32_array(
+                Some(&ibl_locs.view_to_world),
+                false,
+                view.inverse().to_homogeneous().as_slice(),
             );
-            element_buffer.unbind();
-        } else {
-            self.gl.draw_arrays(
-                WebGlRenderingContext::TRIANGLES,
-                0,
-                (vert_buffer.array().len() / 3) as i32,
+        }
+
+        let model_view = view * model;
+        self.queue.clear();
+        for (mesh, primitives) in self.resources.meshes.iter() {
+            for (index, primitive) in primitives.primitives.iter().enumerate() {
+                let material = &primitive.material;
+                self.queue.push(DrawItem {
+                    key: SortKey {
+                        pass: material.alpha_mode.into(),
+                        program: program.id(),
+                        material: primitive.material_id,
+                        texture: material
+                            .base_color_texture
+                            .map_or(u64::MAX, |texture| texture.to_bits()),
+                        depth: -(model_view * primitive.centroid).z,
+                    },
+                    mesh,
+                    primitive: index,
+                });
+            }
+        }
+        self.queue.sort();
+
+        let attrib_locs = &program_info.attrib_locs;
+        let material_locs = &program_info.uniform_locs.material;
+        self.gl
+            .uniform1i(Some(&program_info.uniform_locs.sampler), 0);
+        self.gl.active_texture(WebGlRenderingContext::TEXTURE0);
+        let instance_attribs = InstanceAttribs {
+            model: attrib_locs.instance_model,
+            color: attrib_locs.instance_color,
+        };
+        let instances = self
+            .instances
+            .as_ref()
+            .filter(|instances| !instances.is_empty());
+        let hardware_instancing = self.gl.capabilities().instancing;
+        self.render_stats = self.queue.stats(match instances {
+            Some(instances) if !hardware_instancing => instances.len() as u32,
+            _ => 1,
+        });
+        let layout = VertexLayout {
+            position: attrib_locs.position,
+            normal: attrib_locs.normal,
+            tex_coord: attrib_locs.tex_coord,
+            instanced: instances.is_some() && hardware_instancing,
+        };
+        // instanced draws bind the instance attributes into each primitive's vertex array
+        if !layout.instanced {
+            reset_instance_attribs(&self.gl, instance_attribs);
+        }
+        for mesh in self.resources.meshes.values_mut() {
+            for primitive in &mut mesh.primitives {
+                primitive.prepare_vertex_array(&self.gl, layout);
+            }
+        }
+        // other passes draw with their own attributes, which may not cover these buffers, and
+        // without instance divisors
+        let release_attributes = |gl: &WebGl| {
+            gl.bind_vertex_array(None);
+            gl.disable_vertex_attrib_array(attrib_locs.normal);
+            gl.disable_vertex_attrib_array(attrib_locs.tex_coord);
+            reset_instance_attribs(gl, instance_attribs);
+        };
+        // consecutive draws sharing a material or texture only set it up once
+        let mut previous: Option<SortKey> = None;
+        let mut sky_pending = self.skybox.is_some();
+        for item in self.queue.items() {
+            // the sky goes after opaque draws, which hide it by depth, but before blended ones,
+            // which write no depth to keep it from covering them
+            if sky_pending && item.key.pass == RenderPass::Blend {
+                sky_pending = false;
+                release_attributes(&self.gl);
+                if let Some(ref skybox) = self.skybox {
+                    skybox.draw(
+                        &mut self.state_tracker,
+                        &self.render_state,
+                        &view,
+                        projection.as_matrix(),
+                    );
+                }
+                program.set_used();
+                self.gl.active_texture(WebGlRenderingContext::TEXTURE0);
+                previous = None;
+            }
+            let primitive = match self.resources.meshes.get(item.mesh) {
+                Some(mesh) => &mesh.primitives[item.primitive],
+                None => continue,
+            };
+            let material = &primitive.material;
+            primitive.bind_attributes(&self.gl, layout);
+
+            if previous.map(|previous| previous.texture) != Some(item.key.texture) {
+                let texture = material
+                    .base_color_texture
+                    .and_then(|texture| self.resources.textures.get(texture))
+                    .unwrap_or(&self.white_texture);
+                texture.bind();
+                let decode_srgb = texture.is_srgb() && !texture.decodes_srgb();
+                self.gl.uniform1i(
+                    Some(&program_info.uniform_locs.decode_srgb),
+                    decode_srgb as i32,
+                );
+            }
+
+            if previous.map(|previous| previous.material) != Some(item.key.material) {
+                self.gl.uniform4fv_with_f32_array(
+                    Some(&material_locs.base_color),
+                    &material.base_color,
+                );
+                self.gl
+                    .uniform1f(Some(&material_locs.metallic), material.metallic);
+                self.gl
+                    .uniform1f(Some(&material_locs.roughness), material.roughness);
+                self.gl
+                    .uniform1i(Some(&material_locs.alpha_mode), material.alpha_mode as i32);
+                self.gl
+                    .uniform1f(Some(&material_locs.alpha_cutoff), material.alpha_cutoff);
+                self.state_tracker
+                    .apply(&self.gl, &self.render_state.for_material(material));
+            }
+            previous = Some(item.key);
+
+            match instances {
+                Some(instances) if hardware_instancing => {
+                    instances.bind(&self.gl, instance_attribs);
+                    primitive.draw_instanced(&self.gl, instances.len());
+                }
+                // without instancing support each instance is drawn with constant attributes
+                Some(instances) => {
+                    for index in 0..instances.len() {
+                        instances.set_constant(&self.gl, instance_attribs, index);
+                        primitive.draw(&self.gl);
+                    }
+                }
+                None => primitive.draw(&self.gl),
+            }
+        }
+        release_attributes(&self.gl);
+
+        if let Some(skybox) = self.skybox.as_ref().filter(|_| sky_pending) {
+            skybox.draw(
+                &mut self.state_tracker,
+                &self.render_state,
+                &view,
+                projection.as_matrix(),
             );
         }
 
-        normal_buffer.unbind();
-        vert_buffer.unbind();
-        program.set_unused();
+        if let Some(post_process) = post_process {
+            post_process.end(&mut self.state_tracker, target);
+        }
+    }
+    fn post_chain(&mut self) -> Result<&mut PostChain, JsValue> {
+        Ok(self
+            .post_process
+            .as_mut()
+            .ok_or("no post effects have been added")?
+            .chain_mut())
+    }
+
+    fn clip_index(&self, name: &str) -> Result<usize, JsValue> {
+        self.animations
+            .iter()
+            .position(|clip| clip.name() == name)
+            .ok_or_else(|| format!("no animation named \"{}\"", name).into())
+    }
+
+    /// advance the mixer to `timestamp` and re-upload the morphed positions and normals for the
+    /// resulting pose
+    fn animate(&mut self, timestamp: f32) {
+        let delta = self
+            .last_timestamp
+            .map_or(0.0, |last| (timestamp - last) * 0.001);
+        self.last_timestamp = Some(timestamp);
+        self.mixer.update(&self.animations, delta);
+
+        let mut primitives = self
+            .resources
+            .meshes
+            .iter_mut()
+            .flat_map(|(handle, mesh)| {
+                mesh.primitives
+                    .iter_mut()
+                    .map(move |primitive| (handle, primitive))
+            })
+            .filter(|(_, primitive)| primitive.morph_targets.is_some())
+            .peekable();
+        if primitives.peek().is_none() {
+            return;
+        }
+        let pose = self.mixer.sample(&self.animations);
+        for (mesh, primitive) in primitives {
+            if let Some(weights) = pose.weights.get(&mesh) {
+                primitive.set_morph_weights(weights);
+            }
+        }
+    }
+}
+
+/// scale rgba `pixels` down, keeping their aspect ratio, so neither side is over `max_size`
+fn fit_texture(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    max_size: u32,
+) -> Result<(std::borrow::Cow<'_, [u8]>, u32, u32), String> {
+    if width <= max_size && height <= max_size {
+        return Ok((pixels.into(), width, height));
     }
+    let image = image::RgbaImage::from_raw(width, height, pixels.to_vec())
+        .ok_or("texture pixels don't match its size")?;
+    let scale = max_size as f32 / width.max(height) as f32;
+    let (fit_width, fit_height) = (
+        ((width as f32 * scale) as u32).clamp(1, max_size),
+        ((height as f32 * scale) as u32).clamp(1, max_size),
+    );
+    let image = image::imageops::resize(
+        &image,
+        fit_width,
+        fit_height,
+        image::imageops::FilterType::Triangle,
+    );
+    Ok((image.into_raw().into(), fit_width, fit_height))
 }
@@ -0,0 +1,488 @@
+use nalgebra as na;
+use wasm_bindgen::prelude::*;
+
+/// the most lights the shaders loop over. Must match `MAX_LIGHTS` in `tetra.frag`
+pub const MAX_LIGHTS: usize = 8;
+
+/// the narrowest falloff in radians between a spot light's inner and outer cones, which keeps
+/// the shader's smoothstep between them from dividing by zero
+const MIN_CONE_FALLOFF: f32 = 0.001;
+
+/// the kind of a light, uploaded as an int so the values must match the `LIGHT_` constants in
+/// `tetra.frag`
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightKind {
+    Directional = 0,
+    Point = 1,
+    Spot = 2,
+}
+
+/// a single light source. Positions and directions are in world space and get moved into view
+/// space when uploaded. A range of 0 means the light never fades out
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Light {
+    kind: LightKind,
+    color: na::Vector3<f32>,
+    intensity: f32,
+    range: f32,
+    position: na::Point3<f32>,
+    direction: na::Vector3<f32>,
+    inner_cone_angle: f32,
+    outer_cone_angle: f32,
+    cast_shadows: bool,
+    shadow_bias: f32,
+}
+
+#[wasm_bindgen]
+impl Light {
+    /// a light infinitely far away shining in the direction (x, y, z)
+    pub fn directional(x: f32, y: f32, z: f32) -> Light {
+        Light {
+            kind: LightKind::Directional,
+            direction: na::Vector3::new(x, y, z),
+            ..Light::default()
+        }
+    }
+
+    /// a light at (x, y, z) shining equally in every direction
+    pub fn point(x: f32, y: f32, z: f32) -> Light {
+        Light {
+            kind: LightKind::Point,
+            position: na::Point3::new(x, y, z),
+            ..Light::default()
+        }
+    }
+
+    /// a light at (x, y, z) shining a cone in the direction (dx, dy, dz)
+    pub fn spot(x: f32, y: f32, z: f32, dx: f32, dy: f32, dz: f32) -> Light {
+        Light {
+            kind: LightKind::Spot,
+            position: na::Point3::new(x, y, z),
+            direction: na::Vector3::new(dx, dy, dz),
+            ..Light::default()
+        }
+    }
+
+    pub fn with_color(mut self, r: f32, g: f32, b: f32) -> Light {
+        self.color = na::Vector3::new(r, g, b);
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Light {
+        self.intensity = intensity;
+        self
+    }
+
+    /// the distance at which point and spot lights have faded out completely
+    pub fn with_range(mut self, range: f32) -> Light {
+        self.range = range;
+        self
+    }
+
+    /// the angles in radians from the spot direction where the light starts to fade and where
+    /// it has faded out completely. The outer angle is clamped to a right angle and the inner
+    /// one to just inside the outer one
+    pub fn with_cone(mut self, inner_angle: f32, outer_angle: f32) -> Light {
+        let outer_angle = outer_angle.clamp(MIN_CONE_FALLOFF, std::f32::consts::FRAC_PI_2);
+        self.inner_cone_angle = inner_angle.clamp(0.0, outer_angle - MIN_CONE_FALLOFF);
+        self.outer_cone_angle = outer_angle;
+        self
+    }
+
+    /// make a directional or spot light cast shadows, offsetting depth comparisons by `bias` to
+    /// avoid shadow acne
+    pub fn with_shadows(mut self, bias: f32) -> Light {
+        self.cast_shadows = true;
+        self.shadow_bias = bias;
+        self
+    }
+
+    pub fn without_shadows(mut self) -> Light {
+        self.cast_shadows = false;
+        self
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn kind(&self) -> LightKind {
+        self.kind
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn range(&self) -> f32 {
+        self.range
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn casts_shadows(&self) -> bool {
+        self.cast_shadows
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn shadow_bias(&self) -> f32 {
+        self.shadow_bias
+    }
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Light {
+            kind: LightKind::Directional,
+            color: na::Vector3::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            range: 0.0,
+            position: na::Point3::origin(),
+            direction: na::Vector3::new(0.0, 0.0, -1.0),
+            inner_cone_angle: 0.0,
+            outer_cone_angle: std::f32::consts::FRAC_PI_4,
+            cast_shadows: false,
+            shadow_bias: 0.005,
+        }
+    }
+}
+
+impl Light {
+    pub fn color(&self) -> na::Vector3<f32> {
+        self.color
+    }
+
+    pub fn position(&self) -> na::Point3<f32> {
+        self.position
+    }
+
+    pub fn direction(&self) -> na::Vector3<f32> {
+        self.direction
+    }
+
+    pub fn cone_angles(&self) -> (f32, f32) {
+        (self.inner_cone_angle, self.outer_cone_angle)
+    }
+
+    pub fn set_position(&mut self, position: na::Point3<f32>) {
+        self.position = position;
+    }
+
+    pub fn set_direction(&mut self, direction: na::Vector3<f32>) {
+        self.direction = direction;
+    }
+
+    /// convert a `KHR_lights_punctual` light attached to a node with the world transform
+    /// `transform`. Lights shine down their node's -Z axis, and the intensity is used as-is
+    /// rather than converted from candela or lux
+    pub fn from_gltf(
+        light: &gltf::khr_lights_punctual::Light,
+        transform: &na::Matrix4<f32>,
+    ) -> Light {
+        use gltf::khr_lights_punctual::Kind;
+
+        let [r, g, b] = light.color();
+        let position = transform.transform_point(&na::Point3::origin());
+        let forward = na::Vector3::new(0.0, 0.0, -1.0);
+        // a transform scaling the axis away leaves the light pointing down -Z
+        let direction = transform
+            .transform_vector(&forward)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or(forward);
+        let light_base = match light.kind() {
+            Kind::Directional => Light::directional(direction.x, direction.y, direction.z),
+            Kind::Point => Light::point(position.x, position.y, position.z),
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => Light::spot(
+                position.x,
+                position.y,
+                position.z,
+                direction.x,
+                direction.y,
+                direction.z,
+            )
+            .with_cone(inner_cone_angle, outer_cone_angle),
+        };
+        light_base
+            .with_color(r, g, b)
+            .with_intensity(light.intensity())
+            .with_range(light.range().unwrap_or(0.0))
+    }
+}
+
+/// collect every light placed in the default scene of a glTF document (or the first scene if
+/// there is no default), positioned by their nodes' world transforms
+pub fn lights_from_gltf(document: &gltf::Document) -> Vec<Light> {
+    fn visit(node: gltf::Node, parent: &na::Matrix4<f32>, lights: &mut Vec<Light>) {
+        let local: na::Matrix4<f32> = node.transform().matrix().into();
+        let world = parent * local;
+        if let Some(light) = node.light() {
+            lights.push(Light::from_gltf(&light, &world));
+        }
+        for child in node.children() {
+            visit(child, &world, lights);
+        }
+    }
+
+    let mut lights = Vec::new();
+    if let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        for node in scene.nodes() {
+            visit(node, &na::Matrix4::identity(), &mut lights);
+        }
+    }
+    lights
+}
+
+/// the flattened per-light uniform arrays, padded out to `MAX_LIGHTS` entries
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightUniforms {
+    pub count: i32,
+    pub kinds: Vec<i32>,
+    pub colors: Vec<f32>,
+    pub positions: Vec<f32>,
+    pub directions: Vec<f32>,
+    pub ranges: Vec<f32>,
+    pub cones: Vec<f32>,
+}
+
+/// owns every light in the scene along with the ambient term
+#[derive(Debug, Clone)]
+pub struct LightManager {
+    lights: Vec<Light>,
+    ambient: na::Vector3<f32>,
+}
+
+impl Default for LightManager {
+    /// the lighting the renderer has always used: a white light from above and slightly in
+    /// front plus a dim ambient term
+    fn default() -> Self {
+        LightManager {
+            lights: vec![Light::directional(0.0, -1.0, -0.5)],
+            ambient: na::Vector3::new(0.3, 0.3, 0.3),
+        }
+    }
+}
+
+impl LightManager {
+    /// a light manager with no lights and no ambient light
+    pub fn empty() -> LightManager {
+        LightManager {
+            lights: Vec::new(),
+            ambient: na::zero(),
+        }
+    }
+
+    /// add a light, returning its index
+    pub fn add(&mut self, light: Light) -> Result<usize, String> {
+        if self.lights.len() >= MAX_LIGHTS {
+            return Err(format!("can't have more than {} lights", MAX_LIGHTS));
+        }
+        self.lights.push(light);
+        Ok(self.lights.len() - 1)
+    }
+
+    pub fn set(&mut self, index: usize, light: Light) -> Result<(), String> {
+        let slot = self
+            .lights
+            .get_mut(index)
+            .ok_or_else(|| format!("no light with index {}", index))?;
+        *slot = light;
+        Ok(())
+    }
+
+    /// remove a light, shifting the indices of the lights after it down by one
+    pub fn remove(&mut self, index: usize) -> Result<Light, String> {
+        if index >= self.lights.len() {
+            return Err(format!("no light with index {}", index));
+        }
+        Ok(self.lights.remove(index))
+    }
+
+    pub fn clear(&mut self) {
+        self.lights.clear();
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn ambient(&self) -> na::Vector3<f32> {
+        self.ambient
+    }
+
+    pub fn set_ambient(&mut self, ambient: na::Vector3<f32>) {
+        self.ambient = ambient;
+    }
+
+    /// flatten the lights into uniform arrays with positions and directions moved into the
+    /// space of `view`
+    pub fn uniforms(&self, view: &na::Isometry3<f32>) -> LightUniforms {
+        let mut uniforms = LightUniforms {
+            count: self.lights.len() as i32,
+            kinds: vec![0; MAX_LIGHTS],
+            colors: vec![0.0; MAX_LIGHTS * 3],
+            positions: vec![0.0; MAX_LIGHTS * 3],
+            directions: vec![0.0; MAX_LIGHTS * 3],
+            ranges: vec![0.0; MAX_LIGHTS],
+            cones: vec![0.0; MAX_LIGHTS * 2],
+        };
+        for (i, light) in self.lights.iter().enumerate() {
+            let color = light.color * light.intensity;
+            let position = view * light.position;
+            let direction = (view * light.direction)
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(|| na::Vector3::new(0.0, 0.0, -1.0));
+            uniforms.kinds[i] = light.kind as i32;
+            uniforms.colors[i * 3..i * 3 + 3].copy_from_slice(color.as_slice());
+            uniforms.positions[i * 3..i * 3 + 3].copy_from_slice(position.coords.as_slice());
+            uniforms.directions[i * 3..i * 3 + 3].copy_from_slice(direction.as_slice());
+            uniforms.ranges[i] = light.range;
+            uniforms.cones[i * 2] = light.inner_cone_angle.cos();
+            uniforms.cones[i * 2 + 1] = light.outer_cone_angle.cos();
+        }
+        uniforms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    fn document(json: &str) -> gltf::Document {
+        gltf::Gltf::from_slice(json.as_bytes()).unwrap().document
+    }
+
+    #[test]
+    fn packs_lights_into_view_space() {
+        let mut lights = LightManager::empty();
+        let point = Light::point(1.0, 2.0, 3.0)
+            .with_color(1.0, 0.5, 0.0)
+            .with_intensity(2.0)
+            .with_range(10.0);
+        let spot = Light::spot(0.0, 0.0, 0.0, 0.0, 0.0, -4.0).with_cone(0.0, FRAC_PI_2);
+        lights.add(point).unwrap();
+        lights.add(spot).unwrap();
+        let view = na::Isometry3::translation(0.0, 0.0, -5.0);
+        let uniforms = lights.uniforms(&view);
+
+        assert_eq!(uniforms.count, 2);
+        assert_eq!(uniforms.kinds, [1, 2, 0, 0, 0, 0, 0, 0]);
+        assert_close(&uniforms.colors[..6], &[2.0, 1.0, 0.0, 1.0, 1.0, 1.0]);
+        assert_close(&uniforms.positions[..6], &[1.0, 2.0, -2.0, 0.0, 0.0, -5.0]);
+        assert_close(&uniforms.directions[3..6], &[0.0, 0.0, -1.0]);
+        assert_close(&uniforms.ranges[..2], &[10.0, 0.0]);
+        assert_close(&uniforms.cones[2..4], &[1.0, 0.0]);
+        // the unused entries stay zeroed, up to MAX_LIGHTS
+        assert_eq!(uniforms.colors.len(), MAX_LIGHTS * 3);
+        assert!(uniforms.colors[6..].iter().all(|&value| value == 0.0));
+    }
+
+    #[test]
+    fn points_zero_directions_down_negative_z() {
+        let mut lights = LightManager::empty();
+        lights.add(Light::directional(0.0, 0.0, 0.0)).unwrap();
+        let uniforms = lights.uniforms(&na::Isometry3::identity());
+        assert_close(&uniforms.directions[..3], &[0.0, 0.0, -1.0]);
+
+        let collapsed = document(
+            r#"{
+                "asset": { "version": "2.0" },
+                "extensionsUsed": ["KHR_lights_punctual"],
+                "extensions": {
+                    "KHR_lights_punctual": { "lights": [{ "type": "directional" }] }
+                },
+                "nodes": [{
+                    "scale": [1, 1, 0],
+                    "extensions": { "KHR_lights_punctual": { "light": 0 } }
+                }],
+                "scenes": [{ "nodes": [0] }]
+            }"#,
+        );
+        let lights = lights_from_gltf(&collapsed);
+        assert_close(lights[0].direction().as_slice(), &[0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn keeps_spot_cones_open() {
+        let cone = |inner, outer| Light::default().with_cone(inner, outer).cone_angles();
+        assert_eq!(cone(0.2, FRAC_PI_4), (0.2, FRAC_PI_4));
+        let (inner, outer) = cone(0.5, 0.5);
+        assert!(inner < outer && outer == 0.5);
+        let (inner, outer) = cone(1.0, 0.5);
+        assert!(inner < outer && outer == 0.5);
+        let (inner, outer) = cone(-1.0, 0.0);
+        assert!(inner == 0.0 && outer > 0.0);
+        assert_eq!(cone(0.0, 4.0).1, FRAC_PI_2);
+    }
+
+    #[test]
+    fn places_gltf_lights_by_their_nodes() {
+        let document = document(
+            r#"{
+                "asset": { "version": "2.0" },
+                "extensionsUsed": ["KHR_lights_punctual"],
+                "extensions": {
+                    "KHR_lights_punctual": {
+                        "lights": [
+                            { "type": "point", "color": [1, 0, 0], "intensity": 3, "range": 5 },
+                            {
+                                "type": "spot",
+                                "spot": { "innerConeAngle": 0.25, "outerConeAngle": 0.5 }
+                            }
+                        ]
+                    }
+                },
+                "nodes": [
+                    { "translation": [1, 0, 0], "children": [1] },
+                    {
+                        "translation": [0, 2, 0],
+                        "extensions": { "KHR_lights_punctual": { "light": 0 } }
+                    },
+                    {
+                        "rotation": [-0.70710677, 0, 0, 0.70710677],
+                        "extensions": { "KHR_lights_punctual": { "light": 1 } }
+                    },
+                    { "extensions": { "KHR_lights_punctual": { "light": 0 } } }
+                ],
+                "scenes": [{ "nodes": [3] }, { "nodes": [0, 2] }],
+                "scene": 1
+            }"#,
+        );
+        let lights = lights_from_gltf(&document);
+        assert_eq!(lights.len(), 2);
+
+        let point = &lights[0];
+        assert_eq!(point.kind(), LightKind::Point);
+        assert_close(point.position().coords.as_slice(), &[1.0, 2.0, 0.0]);
+        assert_close(point.color().as_slice(), &[1.0, 0.0, 0.0]);
+        assert_eq!((point.intensity(), point.range()), (3.0, 5.0));
+
+        // rotated a quarter turn about -X, so it shines straight down
+        let spot = &lights[1];
+        assert_eq!(spot.kind(), LightKind::Spot);
+        assert_close(spot.direction().as_slice(), &[0.0, -1.0, 0.0]);
+        assert_eq!(spot.cone_angles(), (0.25, 0.5));
+        assert_eq!(spot.range(), 0.0);
+    }
+}
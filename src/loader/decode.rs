@@ -0,0 +1,105 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    Blob, BlobPropertyBag, ColorSpaceConversion, HtmlImageElement, ImageBitmap, ImageBitmapOptions,
+    ImageData, PremultiplyAlpha, Window, WorkerGlobalScope,
+};
+
+use crate::cubemap::decode_rgba8;
+use crate::ktx2::Ktx2;
+
+/// how a model's images are decoded
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageDecoding {
+    /// by the importer on the thread it runs on, blocking it for large images
+    Blocking,
+    /// by the browser with `createImageBitmap`, off the main thread
+    ImageBitmap,
+    /// not at all, leaving them to be set on the `GltfModel`, e.g. after decoding them in a web
+    /// worker with `decode_image`
+    Deferred,
+}
+
+/// the contents of one of a model's images, in whatever form it was decoded to
+pub enum ImageAsset {
+    /// decoded on the cpu
+    Pixels(gltf::image::Data),
+    /// still encoded, e.g. as a png or jpeg
+    Encoded {
+        data: Vec<u8>,
+        mime_type: Option<String>,
+    },
+    Bitmap(ImageBitmap),
+    Element(HtmlImageElement),
+    /// a KTX2 texture, uploaded as it is or transcoded when it's added to a scene
+    Ktx2(Ktx2),
+}
+
+impl ImageAsset {
+    pub fn is_decoded(&self) -> bool {
+        !matches!(self, ImageAsset::Encoded { .. })
+    }
+}
+
+/// decode a png or jpeg to 8 bit rgba image data. Meant for web workers running this module,
+/// which can post the result back to the main thread for `GltfModel.set_decoded_image`
+#[wasm_bindgen]
+pub fn decode_image(data: &[u8]) -> Result<ImageData, JsValue> {
+    let (width, height, pixels) = decode_rgba8(data)?;
+    ImageData::new_with_u8_clamped_array_and_sh(wasm_bindgen::Clamped(&pixels), width, height)
+}
+
+/// decode an encoded image with the browser's `createImageBitmap`, from either a window or a
+/// worker. The pixels are kept as they are in the file, without premultiplying alpha or color
+/// space conversion, the same as decoding on the cpu
+pub async fn decode_image_bitmap(
+    data: &[u8],
+    mime_type: Option<&str>,
+) -> Result<ImageBitmap, JsValue> {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+    let properties = BlobPropertyBag::new();
+    if let Some(mime_type) = mime_type {
+        properties.set_type(mime_type);
+    }
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &properties)?;
+    let options = ImageBitmapOptions::new();
+    options.set_premultiply_alpha(PremultiplyAlpha::None);
+    options.set_color_space_conversion(ColorSpaceConversion::None);
+
+    let global = js_sys::global();
+    let promise = if let Some(window) = global.dyn_ref::<Window>() {
+        window.create_image_bitmap_with_blob_and_image_bitmap_options(&blob, &options)?
+    } else if let Some(worker) = global.dyn_ref::<WorkerGlobalScope>() {
+        worker.create_image_bitmap_with_blob_and_image_bitmap_options(&blob, &options)?
+    } else {
+        return Err("createImageBitmap needs a window or a worker".into());
+    };
+    JsFuture::from(promise).await?.dyn_into()
+}
+
+/// decode every encoded image with `decode_image_bitmap`, concurrently
+pub async fn decode_image_bitmaps(images: &mut [ImageAsset]) -> Result<(), JsValue> {
+    let decoding: Vec<(usize, js_sys::Promise)> = images
+        .iter()
+        .enumerate()
+        .filter_map(|(index, image)| match image {
+            ImageAsset::Encoded { data, mime_type } => {
+                let data = data.clone();
+                let mime_type = mime_type.clone();
+                let bitmap = async move {
+                    decode_image_bitmap(&data, mime_type.as_deref())
+                        .await
+                        .map(JsValue::from)
+                };
+                Some((index, wasm_bindgen_futures::future_to_promise(bitmap)))
+            }
+            _ => None,
+        })
+        .collect();
+    for (index, promise) in decoding {
+        images[index] = ImageAsset::Bitmap(JsFuture::from(promise).await?.dyn_into()?);
+    }
+    Ok(())
+}
@@ -0,0 +1,539 @@
+use gltf::json::Value;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::WebGlRenderingContext as WebGlCtx;
+
+use crate::interop::{call, construct, error_message};
+
+const KHR_DRACO_MESH_COMPRESSION: &str = "KHR_draco_mesh_compression";
+
+/// an attribute to read out of a draco mesh, by the id the glTF file gives it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DracoAttribute {
+    pub id: u32,
+    /// the type of each component, e.g. FLOAT or UNSIGNED_SHORT
+    pub component_type: u32,
+    pub components: u32,
+}
+
+/// a decoded draco mesh, with the attributes that were asked for as little endian values
+pub struct DracoMesh {
+    pub vertex_count: u32,
+    pub indices: Vec<u32>,
+    pub attributes: Vec<Vec<u8>>,
+}
+
+/// decodes the meshes `KHR_draco_mesh_compression` compresses primitives into
+pub trait DecodeDraco {
+    fn decode(&self, data: &[u8], attributes: &[DracoAttribute]) -> Result<DracoMesh, String>;
+}
+
+/// the decoder from draco's `draco_decoder.js`, wrapping the `DecoderModule` it creates once
+/// it has loaded
+pub struct DracoDecoder {
+    module: JsValue,
+}
+
+impl DracoDecoder {
+    pub fn new(module: JsValue) -> DracoDecoder {
+        DracoDecoder { module }
+    }
+
+    fn decode_mesh(
+        &self,
+        decoder: &JsValue,
+        mesh: &JsValue,
+        data: &[u8],
+        attributes: &[DracoAttribute],
+    ) -> Result<DracoMesh, JsValue> {
+        let module = &self.module;
+        let buffer = construct(module, "DecoderBuffer", &[])?;
+        let bytes = js_sys::Int8Array::new(&js_sys::Uint8Array::from(data));
+        call(&buffer, "Init", &[bytes.into(), (data.len() as u32).into()])?;
+        let status = call(
+            decoder,
+            "DecodeBufferToMesh",
+            &[buffer.clone(), mesh.clone()],
+        );
+        let _ = call(module, "destroy", &[buffer]);
+        let status = status?;
+        if call(&status, "ok", &[])?.is_falsy() {
+            return Err(call(&status, "error_msg", &[])?);
+        }
+
+        let count = |name| -> Result<u32, JsValue> {
+            Ok(call(mesh, name, &[])?.as_f64().unwrap_or(0.0) as u32)
+        };
+        let vertex_count = count("num_points")?;
+        let indices = self
+            .read_heap(
+                decoder,
+                "GetTrianglesUInt32Array",
+                &[mesh],
+                count("num_faces")? * 12,
+            )?
+            .chunks_exact(4)
+            .map(|index| u32::from_le_bytes([index[0], index[1], index[2], index[3]]))
+            .collect();
+        let mut decoded = Vec::with_capacity(attributes.len());
+        for attribute in attributes {
+            let (data_type, size) = match attribute.component_type {
+                WebGlCtx::FLOAT => ("DT_FLOAT32", 4),
+                WebGlCtx::BYTE => ("DT_INT8", 1),
+                WebGlCtx::UNSIGNED_BYTE => ("DT_UINT8", 1),
+                WebGlCtx::SHORT => ("DT_INT16", 2),
+                WebGlCtx::UNSIGNED_SHORT => ("DT_UINT16", 2),
+                WebGlCtx::UNSIGNED_INT => ("DT_UINT32", 4),
+                other => return Err(format!("draco can't decode component type {}", other).into()),
+            };
+            let draco_attribute = call(
+                decoder,
+                "GetAttributeByUniqueId",
+                &[mesh.clone(), attribute.id.into()],
+            )?;
+            let data_type = js_sys::Reflect::get(module, &data_type.into())?;
+            let length = vertex_count * attribute.components * size;
+            decoded.push(self.read_heap(
+                decoder,
+                "GetAttributeDataArrayForAllPoints",
+                &[mesh, &draco_attribute, &data_type],
+                length,
+            )?);
+        }
+        Ok(DracoMesh {
+            vertex_count,
+            indices,
+            attributes: decoded,
+        })
+    }
+
+    /// call a decoder method that writes `length` bytes to memory it's given on the module's
+    /// heap, after `arguments`, and copy them out
+    fn read_heap(
+        &self,
+        decoder: &JsValue,
+        method: &str,
+        arguments: &[&JsValue],
+        length: u32,
+    ) -> Result<Vec<u8>, JsValue> {
+        let module = &self.module;
+        let pointer = call(module, "_malloc", &[length.into()])?;
+        let mut arguments: Vec<JsValue> =
+            arguments.iter().map(|&argument| argument.clone()).collect();
+        arguments.extend([length.into(), pointer.clone()]);
+        let read = call(decoder, method, &arguments).and_then(|_| {
+            // the heap may have grown and been replaced while decoding
+            let heap: js_sys::Uint8Array =
+                js_sys::Reflect::get(module, &"HEAPU8".into())?.dyn_into()?;
+            let start = pointer.as_f64().unwrap_or(0.0) as u32;
+            Ok(heap.subarray(start, start + length).to_vec())
+        });
+        let _ = call(module, "_free", &[pointer]);
+        read
+    }
+}
+
+impl DecodeDraco for DracoDecoder {
+    fn decode(&self, data: &[u8], attributes: &[DracoAttribute]) -> Result<DracoMesh, String> {
+        let decoder = construct(&self.module, "Decoder", &[]).map_err(error_message)?;
+        let mesh = construct(&self.module, "Mesh", &[]).map_err(error_message)?;
+        let decoded = self.decode_mesh(&decoder, &mesh, data, attributes);
+        let _ = call(&self.module, "destroy", &[mesh]);
+        let _ = call(&self.module, "destroy", &[decoder]);
+        decoded.map_err(|err| format!("failed to decode a draco mesh: {}", error_message(err)))
+    }
+}
+
+/// decode every primitive compressed with `KHR_draco_mesh_compression` into a new buffer,
+/// pointing the primitives' accessors at it. Returns whether there were any
+pub fn decode_draco_primitives(
+    json: &mut Value,
+    buffers: &mut Vec<gltf::buffer::Data>,
+    decoder: Option<&dyn DecodeDraco>,
+) -> Result<bool, String> {
+    let primitives: Vec<Value> = draco_primitives(json).cloned().collect();
+    if primitives.is_empty() {
+        return Ok(false);
+    }
+    let decoder = decoder.ok_or(
+        "the model has draco compressed meshes, which need a draco decoder to be imported",
+    )?;
+
+    let mut decoded = Vec::new();
+    let mut views = Vec::new();
+    // each accessor's new view and element count, and whether its indices are now u32
+    let mut updates = Vec::new();
+    for primitive in primitives {
+        let index = |pointer: &str| {
+            primitive
+                .pointer(pointer)
+                .and_then(Value::as_u64)
+                .map(|index| index as usize)
+        };
+        let view = index(&extension_pointer("/bufferView"))
+            .ok_or("a draco compressed primitive has no buffer view")?;
+        let data = view_data(json, buffers, view)?;
+
+        let mut accessors = Vec::new();
+        if let Some(names) = primitive
+            .pointer(&extension_pointer("/attributes"))
+            .and_then(Value::as_object)
+        {
+            for (name, id) in names {
+                let accessor = match index(&format!("/attributes/{}", name)) {
+                    Some(accessor) => accessor,
+                    None => continue,
+                };
+                let field = |name| json.pointer(&format!("/accessors/{}/{}", accessor, name));
+                let components = match field("type").and_then(Value::as_str) {
+                    Some("SCALAR") => 1,
+                    Some("VEC2") => 2,
+                    Some("VEC3") => 3,
+                    Some("VEC4") => 4,
+                    _ => return Err(format!("draco can't decode accessor {}", accessor)),
+                };
+                let id = id
+                    .as_u64()
+                    .ok_or("draco attribute ids have to be numbers")?;
+                let component_type = field("componentType").and_then(Value::as_u64);
+                accessors.push((
+                    accessor,
+                    DracoAttribute {
+                        id: id as u32,
+                        component_type: component_type.unwrap_or(0) as u32,
+                        components,
+                    },
+                ));
+            }
+        }
+        let attributes: Vec<DracoAttribute> =
+            accessors.iter().map(|&(_, attribute)| attribute).collect();
+        let mesh = decoder.decode(data, &attributes)?;
+
+        let mut push_view = |bytes: &[u8]| {
+            while !decoded.len().is_multiple_of(4) {
+                decoded.push(0);
+            }
+            views.push((decoded.len(), bytes.len()));
+            decoded.extend_from_slice(bytes);
+            views.len() - 1
+        };
+        for (&(accessor, _), data) in accessors.iter().zip(&mesh.attributes) {
+            let view = push_view(data);
+            updates.push((accessor, view, mesh.vertex_count as usize, false));
+        }
+        if let Some(accessor) = index("/indices") {
+            let bytes: Vec<u8> = mesh
+                .indices
+                .iter()
+                .flat_map(|index| index.to_le_bytes())
+                .collect();
+            let view = push_view(&bytes);
+            updates.push((accessor, view, mesh.indices.len(), true));
+        }
+    }
+
+    let buffer = buffers.len();
+    let first_view = json
+        .get("bufferViews")
+        .and_then(Value::as_array)
+        .map_or(0, Vec::len);
+    push_array(
+        json,
+        "buffers",
+        json_object(&[("byteLength", decoded.len().max(1))]),
+    );
+    for (offset, length) in views {
+        push_array(
+            json,
+            "bufferViews",
+            json_object(&[
+                ("buffer", buffer),
+                ("byteOffset", offset),
+                ("byteLength", length),
+            ]),
+        );
+    }
+    for (accessor, view, count, indices) in updates {
+        let accessor = json
+            .pointer_mut(&format!("/accessors/{}", accessor))
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| format!("accessor {} doesn't exist", accessor))?;
+        accessor.insert("bufferView".into(), (first_view + view).into());
+        accessor.insert("byteOffset".into(), 0.into());
+        accessor.insert("count".into(), count.into());
+        if indices {
+            accessor.insert("componentType".into(), WebGlCtx::UNSIGNED_INT.into());
+        }
+    }
+    decoded.resize(decoded.len().max(1).next_multiple_of(4), 0);
+    buffers.push(gltf::buffer::Data(decoded));
+    Ok(true)
+}
+
+/// point the accessors of draco compressed primitives that have no buffer view, which the gltf
+/// crate requires, at the compressed data until they're decoded
+pub fn stand_in_draco_views(json: &mut Value) {
+    let mut stand_ins = Vec::new();
+    for primitive in draco_primitives(json) {
+        let view = match primitive.pointer(&extension_pointer("/bufferView")) {
+            Some(view) => view.clone(),
+            None => continue,
+        };
+        let attributes = primitive
+            .get("attributes")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flat_map(|attributes| attributes.values());
+        for accessor in attributes.chain(primitive.get("indices")) {
+            if let Some(accessor) = accessor.as_u64() {
+                stand_ins.push((accessor, view.clone()));
+            }
+        }
+    }
+    for (accessor, view) in stand_ins {
+        if let Some(accessor) = json
+            .pointer_mut(&format!("/accessors/{}", accessor))
+            .and_then(Value::as_object_mut)
+        {
+            accessor.entry("bufferView").or_insert(view);
+        }
+    }
+}
+
+/// every mesh primitive with `KHR_draco_mesh_compression`
+fn draco_primitives(json: &Value) -> impl Iterator<Item = &Value> {
+    json.get("meshes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|mesh| mesh.get("primitives").and_then(Value::as_array))
+        .flatten()
+        .filter(|primitive| primitive.pointer(&extension_pointer("")).is_some())
+}
+
+fn extension_pointer(path: &str) -> String {
+    format!("/extensions/{}{}", KHR_DRACO_MESH_COMPRESSION, path)
+}
+
+/// the bytes of buffer view `view`
+fn view_data<'a>(
+    json: &Value,
+    buffers: &'a [gltf::buffer::Data],
+    view: usize,
+) -> Result<&'a [u8], String> {
+    let field = |name| {
+        json.pointer(&format!("/bufferViews/{}/{}", view, name))
+            .and_then(Value::as_u64)
+            .map(|value| value as usize)
+    };
+    let buffer = field("buffer").and_then(|buffer| buffers.get(buffer));
+    let offset = field("byteOffset").unwrap_or(0);
+    let length = field("byteLength").unwrap_or(0);
+    buffer
+        .and_then(|buffer| buffer.0.get(offset..offset + length))
+        .ok_or_else(|| format!("buffer view {} is outside its buffer", view))
+}
+
+fn push_array(json: &mut Value, name: &str, value: Value) {
+    if let Some(object) = json.as_object_mut() {
+        let array = object
+            .entry(name)
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Some(array) = array.as_array_mut() {
+            array.push(value);
+        }
+    }
+}
+
+fn json_object(fields: &[(&str, usize)]) -> Value {
+    Value::Object(
+        fields
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.into()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::parse_gltf;
+
+    const POSITIONS: [f32; 12] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0];
+    const TEX_COORDS: [f32; 8] = [0.0, 1.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0];
+    const INDICES: [u32; 6] = [0, 1, 2, 2, 1, 3];
+    /// stands in for a compressed mesh, which the stub decoder checks it's given
+    const COMPRESSED: &[u8] = b"DRACO\0\0\0";
+
+    fn bytes_of<T: Copy>(values: &[T], to_bytes: fn(T) -> [u8; 4]) -> Vec<u8> {
+        values.iter().flat_map(|&value| to_bytes(value)).collect()
+    }
+
+    /// decodes `COMPRESSED` into a quad, with the positions as attribute 0 and the texture
+    /// coordinates as 1
+    struct StubDecoder;
+
+    impl DecodeDraco for StubDecoder {
+        fn decode(&self, data: &[u8], attributes: &[DracoAttribute]) -> Result<DracoMesh, String> {
+            assert_eq!(data, COMPRESSED);
+            let attributes = attributes
+                .iter()
+                .map(|attribute| {
+                    assert_eq!(attribute.component_type, WebGlCtx::FLOAT);
+                    match (attribute.id, attribute.components) {
+                        (0, 3) => Ok(bytes_of(&POSITIONS, f32::to_le_bytes)),
+                        (1, 2) => Ok(bytes_of(&TEX_COORDS, f32::to_le_bytes)),
+                        _ => Err(format!("no attribute like {:?}", attribute)),
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(DracoMesh {
+                vertex_count: 4,
+                indices: INDICES.to_vec(),
+                attributes,
+            })
+        }
+    }
+
+    fn parse(json: &str) -> Value {
+        gltf::json::deserialize::from_str(json).unwrap()
+    }
+
+    /// a quad compressed with draco, its accessors having no buffer views
+    fn compressed_gltf() -> Value {
+        parse(
+            r#"{
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": 8 }],
+                "bufferViews": [{ "buffer": 0, "byteLength": 8 }],
+                "accessors": [
+                    {
+                        "componentType": 5126,
+                        "count": 4,
+                        "type": "VEC3",
+                        "min": [0, 0, 0],
+                        "max": [1, 1, 0]
+                    },
+                    { "componentType": 5126, "count": 4, "type": "VEC2" },
+                    { "componentType": 5123, "count": 6, "type": "SCALAR" }
+                ],
+                "meshes": [{
+                    "primitives": [{
+                        "attributes": { "POSITION": 0, "TEXCOORD_0": 1 },
+                        "indices": 2,
+                        "extensions": {
+                            "KHR_draco_mesh_compression": {
+                                "bufferView": 0,
+                                "attributes": { "POSITION": 0, "TEXCOORD_0": 1 }
+                            }
+                        }
+                    }]
+                }],
+                "extensionsUsed": ["KHR_draco_mesh_compression"]
+            }"#,
+        )
+    }
+
+    /// the same quad uncompressed in a second buffer, laid out like the decoded one should be,
+    /// with u32 indices
+    fn reference_gltf() -> Value {
+        parse(
+            r#"{
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": 8 }, { "byteLength": 104 }],
+                "bufferViews": [
+                    { "buffer": 0, "byteLength": 8 },
+                    { "buffer": 1, "byteOffset": 0, "byteLength": 48 },
+                    { "buffer": 1, "byteOffset": 48, "byteLength": 32 },
+                    { "buffer": 1, "byteOffset": 80, "byteLength": 24 }
+                ],
+                "accessors": [
+                    {
+                        "bufferView": 1,
+                        "byteOffset": 0,
+                        "componentType": 5126,
+                        "count": 4,
+                        "type": "VEC3",
+                        "min": [0, 0, 0],
+                        "max": [1, 1, 0]
+                    },
+                    {
+                        "bufferView": 2,
+                        "byteOffset": 0,
+                        "componentType": 5126,
+                        "count": 4,
+                        "type": "VEC2"
+                    },
+                    {
+                        "bufferView": 3,
+                        "byteOffset": 0,
+                        "componentType": 5125,
+                        "count": 6,
+                        "type": "SCALAR"
+                    }
+                ]
+            }"#,
+        )
+    }
+
+    fn reference_buffer() -> Vec<u8> {
+        let mut buffer = bytes_of(&POSITIONS, f32::to_le_bytes);
+        buffer.extend(bytes_of(&TEX_COORDS, f32::to_le_bytes));
+        buffer.extend(bytes_of(&INDICES, u32::to_le_bytes));
+        buffer
+    }
+
+    #[test]
+    fn stands_in_the_compressed_view_for_accessors() {
+        let mut json = compressed_gltf();
+        stand_in_draco_views(&mut json);
+        for accessor in 0..3 {
+            let view = json.pointer(&format!("/accessors/{}/bufferView", accessor));
+            assert_eq!(view, Some(&0.into()));
+        }
+    }
+
+    #[test]
+    fn decodes_primitives_into_a_new_buffer() {
+        let data = gltf::json::serialize::to_vec(&compressed_gltf()).unwrap();
+        let mut gltf = parse_gltf(&data).unwrap();
+        let mut buffers = vec![gltf::buffer::Data(COMPRESSED.to_vec())];
+        gltf.decode_draco_primitives(&mut buffers, Some(&StubDecoder))
+            .unwrap();
+
+        let reference = reference_gltf();
+        for field in &["buffers", "bufferViews", "accessors"] {
+            assert_eq!(gltf.json[field], reference[field], "{}", field);
+        }
+        assert_eq!(buffers.len(), 2);
+        assert_eq!(buffers[1].0, reference_buffer());
+
+        // the reread document reads the decoded data
+        let mesh = gltf.document.meshes().next().unwrap();
+        let primitive = mesh.primitives().next().unwrap();
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+        let positions: Vec<[f32; 3]> = reader.read_positions().unwrap().collect();
+        assert_eq!(positions.concat(), POSITIONS);
+        let indices: Vec<u32> = reader.read_indices().unwrap().into_u32().collect();
+        assert_eq!(indices, INDICES);
+    }
+
+    #[test]
+    fn needs_a_decoder_for_compressed_primitives() {
+        let mut json = compressed_gltf();
+        let mut buffers = vec![gltf::buffer::Data(COMPRESSED.to_vec())];
+        let err = decode_draco_primitives(&mut json, &mut buffers, None).unwrap_err();
+        assert!(err.contains("draco decoder"), "{}", err);
+        assert_eq!(json, compressed_gltf());
+    }
+
+    #[test]
+    fn leaves_uncompressed_models_alone() {
+        let mut json = reference_gltf();
+        let mut buffers = Vec::new();
+        let decoded = decode_draco_primitives(&mut json, &mut buffers, Some(&StubDecoder));
+        assert_eq!(decoded, Ok(false));
+        assert!(buffers.is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+use gltf::json::Value;
+
+use super::draco::stand_in_draco_views;
+use super::{decode_draco_primitives, decode_meshopt, DecodeDraco, MeshoptFilter, MeshoptMode};
+
+const KHR_TEXTURE_BASISU: &str = "KHR_texture_basisu";
+const EXT_MESHOPT_COMPRESSION: &str = "EXT_meshopt_compression";
+
+/// a parsed `.gltf` or `.glb`, keeping its json for the extensions the gltf crate doesn't know
+/// about
+pub struct ParsedGltf {
+    pub document: gltf::Document,
+    pub blob: Option<Vec<u8>>,
+    pub json: Value,
+    /// for each texture, the KTX2 image `KHR_texture_basisu` gives it
+    pub basisu_images: Vec<Option<usize>>,
+}
+
+/// parse a `.gltf` or `.glb`, reading the extensions the gltf crate doesn't know about from
+/// its json first
+pub fn parse_gltf(data: &[u8]) -> Result<ParsedGltf, String> {
+    let (json, blob) = if data.starts_with(b"glTF") {
+        let glb = gltf::Glb::from_slice(data).map_err(|err| invalid(&err))?;
+        (glb.json.into_owned(), glb.bin.map(|bin| bin.into_owned()))
+    } else {
+        (data.to_vec(), None)
+    };
+    let mut json: Value =
+        gltf::json::deserialize::from_slice(&json).map_err(|err| invalid(&err))?;
+    let basisu_images = take_basisu_sources(&mut json);
+    let mut document = json.clone();
+    stand_in_draco_views(&mut document);
+    Ok(ParsedGltf {
+        document: read_document(document)?,
+        blob,
+        json,
+        basisu_images,
+    })
+}
+
+impl ParsedGltf {
+    /// whether buffer `index` is only a fallback for views compressed with
+    /// `EXT_meshopt_compression`, having no data of its own until they're decoded into it
+    pub fn is_meshopt_fallback(&self, index: usize) -> bool {
+        let pointer = format!(
+            "/buffers/{}/extensions/{}/fallback",
+            index, EXT_MESHOPT_COMPRESSION
+        );
+        self.json
+            .pointer(&pointer)
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+    }
+
+    /// decode every buffer view compressed with `EXT_meshopt_compression` into the part of the
+    /// buffer it's a view of
+    pub fn decode_meshopt_views(&self, buffers: &mut [gltf::buffer::Data]) -> Result<(), String> {
+        let views = match self.json.get("bufferViews").and_then(Value::as_array) {
+            Some(views) => views,
+            None => return Ok(()),
+        };
+        for (index, view) in views.iter().enumerate() {
+            let compressed = match view.pointer(&format!("/extensions/{}", EXT_MESHOPT_COMPRESSION))
+            {
+                Some(compressed) => compressed,
+                None => continue,
+            };
+            let field = |value: &Value, name| {
+                value
+                    .get(name)
+                    .and_then(Value::as_u64)
+                    .map(|value| value as usize)
+            };
+            let required = |name| {
+                field(compressed, name)
+                    .ok_or_else(|| format!("buffer view {} has no compressed {}", index, name))
+            };
+            let source = required("buffer")?;
+            let offset = field(compressed, "byteOffset").unwrap_or(0);
+            let length = required("byteLength")?;
+            let stride = required("byteStride")?;
+            let count = required("count")?;
+            let mode = match compressed.get("mode").and_then(Value::as_str) {
+                Some("ATTRIBUTES") => MeshoptMode::Attributes,
+                Some("TRIANGLES") => MeshoptMode::Triangles,
+                Some("INDICES") => MeshoptMode::Indices,
+                mode => return Err(format!("unknown meshopt mode {:?}", mode)),
+            };
+            let filter = match compressed.get("filter").and_then(Value::as_str) {
+                None | Some("NONE") => MeshoptFilter::None,
+                Some("OCTAHEDRAL") => MeshoptFilter::Octahedral,
+                Some("QUATERNION") => MeshoptFilter::Quaternion,
+                Some("EXPONENTIAL") => MeshoptFilter::Exponential,
+                Some(filter) => return Err(format!("unknown meshopt filter {}", filter)),
+            };
+
+            let data = buffers
+                .get(source)
+                .and_then(|buffer| buffer.0.get(offset..offset + length))
+                .ok_or_else(|| format!("buffer view {} is compressed outside its buffer", index))?;
+            let decoded = decode_meshopt(data, count, stride, mode, filter)
+                .map_err(|err| format!("buffer view {}: {}", index, err))?;
+            let target = field(view, "byteOffset").unwrap_or(0);
+            buffers
+                .get_mut(field(view, "buffer").unwrap_or(usize::MAX))
+                .and_then(|buffer| buffer.0.get_mut(target..target + decoded.len()))
+                .ok_or_else(|| format!("buffer view {} is outside its buffer", index))?
+                .copy_from_slice(&decoded);
+        }
+        Ok(())
+    }
+
+    /// decode every primitive compressed with `KHR_draco_mesh_compression` into a new buffer
+    /// at the end of `buffers`, rereading the document to point at it
+    pub fn decode_draco_primitives(
+        &mut self,
+        buffers: &mut Vec<gltf::buffer::Data>,
+        decoder: Option<&dyn DecodeDraco>,
+    ) -> Result<(), String> {
+        if decode_draco_primitives(&mut self.json, buffers, decoder)? {
+            self.document = read_document(self.json.clone())?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(err: &dyn std::fmt::Display) -> String {
+    format!("invalid gltf: {}", err)
+}
+
+fn read_document(json: Value) -> Result<gltf::Document, String> {
+    let root = gltf::json::deserialize::from_value(json).map_err(|err| invalid(&err))?;
+    gltf::Document::from_json(root).map_err(|err| invalid(&err))
+}
+
+/// the KTX2 image of every texture with `KHR_texture_basisu`. Textures without a fallback
+/// image are given the KTX2 one as their source, which the gltf crate requires
+fn take_basisu_sources(root: &mut Value) -> Vec<Option<usize>> {
+    let textures = match root.get_mut("textures").and_then(Value::as_array_mut) {
+        Some(textures) => textures,
+        None => return Vec::new(),
+    };
+    textures
+        .iter_mut()
+        .map(|texture| {
+            let image = texture
+                .pointer(&format!("/extensions/{}/source", KHR_TEXTURE_BASISU))
+                .and_then(Value::as_u64)?;
+            if let Some(texture) = texture.as_object_mut() {
+                texture.entry("source").or_insert_with(|| image.into());
+            }
+            Some(image as usize)
+        })
+        .collect()
+}
@@ -0,0 +1,210 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::Response;
+
+/// the bytes of a fetched url, or why they couldn't be fetched
+pub type FetchFuture = Pin<Box<dyn Future<Output = Result<Vec<u8>, String>>>>;
+
+/// where loaders get the files a model is made of from
+pub trait Fetch {
+    fn fetch(&self, url: &str) -> FetchFuture;
+}
+
+/// fetch urls with the browser's `fetch`, relative to the page
+pub struct HttpFetch;
+
+impl Fetch for HttpFetch {
+    fn fetch(&self, url: &str) -> FetchFuture {
+        let url = url.to_string();
+        Box::pin(async move {
+            fetch_bytes(&url).await.map_err(|err| {
+                let reason = err.as_string().unwrap_or_else(|| format!("{:?}", err));
+                format!("failed to fetch {}: {}", url, reason)
+            })
+        })
+    }
+}
+
+async fn fetch_bytes(url: &str) -> Result<Vec<u8>, JsValue> {
+    let window = web_sys::window().ok_or("there's no window to fetch with")?;
+    let response: Response = JsFuture::from(window.fetch_with_str(url))
+        .await?
+        .dyn_into()?;
+    if !response.ok() {
+        return Err(format!("the server responded with {}", response.status()).into());
+    }
+    let buffer = JsFuture::from(response.array_buffer()?).await?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+/// read urls as paths under a directory, standing in for a server when loading natively, e.g.
+/// from tests. The futures are ready immediately
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileFetch {
+    root: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileFetch {
+    pub fn new<P: Into<std::path::PathBuf>>(root: P) -> FileFetch {
+        FileFetch { root: root.into() }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Fetch for FileFetch {
+    fn fetch(&self, url: &str) -> FetchFuture {
+        let relative = super::percent_decode(url.trim_start_matches('/'));
+        let path = self.root.join(String::from_utf8_lossy(&relative).as_ref());
+        let result = std::fs::read(&path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err));
+        Box::pin(std::future::ready(result))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::task::{Context, Poll, Waker};
+
+    use super::*;
+    use crate::loader::{fetch_gltf_assets, ImageAsset, LoadProgress};
+
+    const GLTF: &str = r#"{
+        "asset": { "version": "2.0" },
+        "buffers": [{ "uri": "triangle.bin", "byteLength": 36 }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+        "accessors": [{
+            "bufferView": 0,
+            "componentType": 5126,
+            "count": 3,
+            "type": "VEC3",
+            "min": [0, 0, 0],
+            "max": [1, 1, 0]
+        }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+        "images": [{ "uri": "textures/my%20texture.png" }]
+    }"#;
+    /// stands in for an encoded image, which isn't decoded
+    const IMAGE: &[u8] = b"\x89PNG not really";
+
+    /// a directory under the system's temporary one, removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        fn write(&self, path: &str, data: &[u8]) {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// run a future that never waits, which `FileFetch`'s don't
+    fn ready<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        match future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the future had to wait"),
+        }
+    }
+
+    fn triangle() -> Vec<u8> {
+        [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn reads_files_under_the_root() {
+        let dir = TempDir::new("tetra-fetch-files");
+        dir.write("models/my model.gltf", GLTF.as_bytes());
+        let fetch = FileFetch::new(dir.path());
+        let data = ready(fetch.fetch("/models/my%20model.gltf")).unwrap();
+        assert_eq!(data, GLTF.as_bytes());
+
+        let err = ready(fetch.fetch("models/missing.gltf")).unwrap_err();
+        assert!(err.starts_with("failed to read"), "{}", err);
+    }
+
+    #[test]
+    fn fetches_a_model_and_its_files() {
+        let dir = TempDir::new("tetra-fetch-model");
+        dir.write("models/triangle.gltf", GLTF.as_bytes());
+        dir.write("models/triangle.bin", &triangle());
+        dir.write("models/textures/my texture.png", IMAGE);
+
+        let mut progress = Vec::new();
+        let assets = ready(fetch_gltf_assets(
+            &FileFetch::new(dir.path()),
+            "models/triangle.gltf",
+            &mut |state| progress.push(state),
+            false,
+            None,
+        ))
+        .unwrap();
+
+        let gltf = GLTF.len() as u32;
+        let bin = gltf + 36;
+        let image = bin + IMAGE.len() as u32;
+        assert_eq!(
+            progress,
+            [(1, gltf), (2, bin), (3, image)]
+                .iter()
+                .map(|&(loaded, bytes)| LoadProgress {
+                    loaded,
+                    total: 3,
+                    bytes,
+                })
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(assets.buffers.len(), 1);
+        assert_eq!(assets.buffers[0].0, triangle());
+        match &assets.images[..] {
+            [ImageAsset::Encoded { data, .. }] => assert_eq!(data, IMAGE),
+            _ => panic!("expected one encoded image"),
+        }
+    }
+
+    #[test]
+    fn fails_when_a_file_is_missing() {
+        let dir = TempDir::new("tetra-fetch-missing");
+        dir.write("triangle.gltf", GLTF.as_bytes());
+        dir.write("triangle.bin", &triangle());
+
+        let mut progress = Vec::new();
+        let result = ready(fetch_gltf_assets(
+            &FileFetch::new(dir.path()),
+            "triangle.gltf",
+            &mut |state| progress.push(state.loaded),
+            false,
+            None,
+        ));
+        let err = result.err().unwrap();
+        assert!(err.contains("my texture.png"), "{}", err);
+        assert_eq!(progress, [1, 2]);
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+
+use gltf::buffer::Source as BufferSource;
+use gltf::image::{Format, Source as ImageSource};
+use wasm_bindgen::prelude::*;
+use web_sys::{HtmlImageElement, ImageBitmap, ImageData};
+
+use super::{
+    decode_data_uri, decode_image_bitmaps, is_data_uri, parse_gltf, percent_decode, resolve_uri,
+    DecodeDraco, DracoDecoder, Fetch, HttpFetch, ImageAsset, ImageDecoding, ParsedGltf,
+};
+use crate::cubemap::decode_rgba8;
+use crate::ktx2::Ktx2;
+
+/// a glTF file with the contents of every buffer and image it references
+pub struct GltfAssets {
+    pub document: gltf::Document,
+    pub buffers: Vec<gltf::buffer::Data>,
+    pub images: Vec<ImageAsset>,
+    /// for each texture, the KTX2 image `KHR_texture_basisu` gives it, used in place of its
+    /// source when it can be uploaded
+    pub basisu_images: Vec<Option<usize>>,
+}
+
+/// how far along fetching a model is, counted in files
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub loaded: u32,
+    /// every file the model needs, known once the first has been parsed
+    pub total: u32,
+    pub bytes: u32,
+}
+
+/// where the external files a glTF file references come from, looked up by the uri they're
+/// referenced with. Data uris are decoded without asking
+pub trait Resolve {
+    fn resolve(&self, uri: &str) -> Result<Vec<u8>, String>;
+}
+
+/// files by uri, also found by the percent decoded uri, e.g. `"my texture.png"` for
+/// `my%20texture.png`
+impl Resolve for HashMap<String, Vec<u8>> {
+    fn resolve(&self, uri: &str) -> Result<Vec<u8>, String> {
+        self.get(uri)
+            .or_else(|| self.get(String::from_utf8_lossy(&percent_decode(uri)).as_ref()))
+            .cloned()
+            .ok_or_else(|| format!("no file was given for {}", uri))
+    }
+}
+
+/// parse a `.gltf` or `.glb` that's already in memory, getting the files it references from
+/// `resolver`. Images are decoded to 8 bit rgba if `decode_images` is set, and left encoded
+/// otherwise. Meshopt compressed buffers are always decoded, while draco compressed meshes need
+/// `draco`
+pub fn import_gltf_assets(
+    data: &[u8],
+    resolver: &dyn Resolve,
+    decode_images: bool,
+    draco: Option<&dyn DecodeDraco>,
+) -> Result<GltfAssets, String> {
+    assemble(parse_gltf(data)?, resolver, decode_images, draco)
+}
+
+/// fetch a `.gltf` or `.glb` from `url`, then every external buffer and image it references
+/// relative to it, calling `progress` after each file. Images are decoded like
+/// `import_gltf_assets` does, as is compressed geometry
+pub async fn fetch_gltf_assets(
+    fetch: &dyn Fetch,
+    url: &str,
+    progress: &mut dyn FnMut(LoadProgress),
+    decode_images: bool,
+    draco: Option<&dyn DecodeDraco>,
+) -> Result<GltfAssets, String> {
+    let data = fetch.fetch(url).await?;
+    let gltf = parse_gltf(&data).map_err(|err| format!("{}: {}", url, err))?;
+    let uris = external_uris(&gltf.document);
+    let mut state = LoadProgress {
+        loaded: 1,
+        total: 1 + uris.len() as u32,
+        bytes: data.len() as u32,
+    };
+    progress(state);
+    let mut files = HashMap::new();
+    for uri in uris {
+        let data = fetch.fetch(&resolve_uri(url, &uri)).await?;
+        state.loaded += 1;
+        state.bytes += data.len() as u32;
+        progress(state);
+        files.insert(uri, data);
+    }
+    assemble(gltf, &files, decode_images, draco).map_err(|err| format!("{}: {}", url, err))
+}
+
+/// the uris of every external buffer and image, each once
+pub fn external_uris(document: &gltf::Document) -> Vec<String> {
+    let buffers = document
+        .buffers()
+        .filter_map(|buffer| match buffer.source() {
+            BufferSource::Uri(uri) => Some(uri),
+            BufferSource::Bin => None,
+        });
+    let images = document.images().filter_map(|image| match image.source() {
+        ImageSource::Uri { uri, .. } => Some(uri),
+        ImageSource::View { .. } => None,
+    });
+    let mut uris: Vec<String> = Vec::new();
+    for uri in buffers.chain(images).filter(|uri| !is_data_uri(uri)) {
+        if !uris.iter().any(|known| known == uri) {
+            uris.push(uri.to_string());
+        }
+    }
+    uris
+}
+
+/// read every buffer and image of a parsed glTF file, decoding compressed geometry once the
+/// buffers are read
+fn assemble(
+    mut gltf: ParsedGltf,
+    resolver: &dyn Resolve,
+    decode_images: bool,
+    draco: Option<&dyn DecodeDraco>,
+) -> Result<GltfAssets, String> {
+    let read_uri = |uri: &str| {
+        if is_data_uri(uri) {
+            decode_data_uri(uri).map(|data_uri| data_uri.data)
+        } else {
+            resolver.resolve(uri)
+        }
+    };
+
+    let mut buffers = Vec::new();
+    for buffer in gltf.document.buffers() {
+        let data = match buffer.source() {
+            // filled in by decoding the views compressed into other buffers
+            _ if gltf.is_meshopt_fallback(buffer.index()) => vec![0; buffer.length()],
+            BufferSource::Bin => gltf.blob.take().ok_or("the glb file has no binary chunk")?,
+            BufferSource::Uri(uri) => read_uri(uri)?,
+        };
+        buffers.push(buffer_data(&buffer, data)?);
+    }
+    gltf.decode_meshopt_views(&mut buffers)?;
+    gltf.decode_draco_primitives(&mut buffers, draco)?;
+    let ParsedGltf {
+        document,
+        basisu_images,
+        ..
+    } = gltf;
+
+    let mut images = Vec::new();
+    for image in document.images() {
+        let (data, mime_type) = match image.source() {
+            ImageSource::View { view, mime_type } => (
+                buffers[view.buffer().index()]
+                    .get(view.offset()..view.offset() + view.length())
+                    .ok_or_else(|| format!("image {} is outside its buffer", image.index()))?
+                    .to_vec(),
+                Some(mime_type.to_string()),
+            ),
+            ImageSource::Uri { uri, mime_type } if is_data_uri(uri) => {
+                let data_uri = decode_data_uri(uri)?;
+                let mime_type = mime_type.map(str::to_string).or(data_uri.mime_type);
+                (data_uri.data, mime_type)
+            }
+            ImageSource::Uri { uri, mime_type } => {
+                (resolver.resolve(uri)?, mime_type.map(str::to_string))
+            }
+        };
+        // KTX2 images are parsed whatever the decoding, as they're uploaded without it
+        images.push(if Ktx2::is_ktx2(&data) {
+            ImageAsset::Ktx2(Ktx2::parse(data)?)
+        } else if decode_images {
+            ImageAsset::Pixels(image_data(&data)?)
+        } else {
+            ImageAsset::Encoded { data, mime_type }
+        });
+    }
+
+    Ok(GltfAssets {
+        document,
+        buffers,
+        images,
+        basisu_images,
+    })
+}
+
+/// check that `data` holds all of `buffer`, padding it to 4 bytes like the glTF importer does
+pub fn buffer_data(buffer: &gltf::Buffer, mut data: Vec<u8>) -> Result<gltf::buffer::Data, String> {
+    if data.len() < buffer.length() {
+        return Err(format!(
+            "buffer {} should be {} bytes but is {}",
+            buffer.index(),
+            buffer.length(),
+            data.len()
+        ));
+    }
+    while !data.len().is_multiple_of(4) {
+        data.push(0);
+    }
+    Ok(gltf::buffer::Data(data))
+}
+
+/// decode a png or jpeg into the rgba image data the glTF importer would produce
+pub fn image_data(encoded: &[u8]) -> Result<gltf::image::Data, String> {
+    let (width, height, pixels) = decode_rgba8(encoded)?;
+    Ok(gltf::image::Data {
+        pixels,
+        format: Format::R8G8B8A8,
+        width,
+        height,
+    })
+}
+
+/// a fetched or imported model, ready to be added to a scene with `Tetra.add_model`
+#[wasm_bindgen]
+pub struct GltfModel {
+    url: Option<String>,
+    assets: GltfAssets,
+}
+
+#[wasm_bindgen]
+impl GltfModel {
+    /// where the model was fetched from, if it was
+    #[wasm_bindgen(getter)]
+    pub fn url(&self) -> Option<String> {
+        self.url.clone()
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.assets.images.len()
+    }
+
+    pub fn is_image_decoded(&self, index: usize) -> bool {
+        self.assets
+            .images
+            .get(index)
+            .is_some_and(ImageAsset::is_decoded)
+    }
+
+    /// the png or jpeg bytes of an image that hasn't been decoded yet, e.g. to post to a web
+    /// worker
+    pub fn encoded_image(&self, index: usize) -> Option<Vec<u8>> {
+        match self.assets.images.get(index) {
+            Some(ImageAsset::Encoded { data, .. }) => Some(data.clone()),
+            _ => None,
+        }
+    }
+
+    /// set an image to 8 bit rgba image data, e.g. from `decode_image` in a web worker
+    pub fn set_decoded_image(&mut self, index: usize, image: &ImageData) -> Result<(), JsValue> {
+        let pixels = gltf::image::Data {
+            pixels: image.data().0,
+            format: Format::R8G8B8A8,
+            width: image.width(),
+            height: image.height(),
+        };
+        self.set_image(index, ImageAsset::Pixels(pixels))
+    }
+
+    /// set an image to a bitmap decoded by the browser
+    pub fn set_image_bitmap(&mut self, index: usize, bitmap: ImageBitmap) -> Result<(), JsValue> {
+        self.set_image(index, ImageAsset::Bitmap(bitmap))
+    }
+
+    /// set an image to a loaded `<img>` element
+    pub fn set_image_element(
+        &mut self,
+        index: usize,
+        element: HtmlImageElement,
+    ) -> Result<(), JsValue> {
+        self.set_image(index, ImageAsset::Element(element))
+    }
+}
+
+impl GltfModel {
+    pub fn assets(&self) -> &GltfAssets {
+        &self.assets
+    }
+
+    fn set_image(&mut self, index: usize, image: ImageAsset) -> Result<(), JsValue> {
+        let count = self.assets.images.len();
+        let slot = self
+            .assets
+            .images
+            .get_mut(index)
+            .ok_or_else(|| format!("image {} is out of range of {} images", index, count))?;
+        *slot = image;
+        Ok(())
+    }
+}
+
+/// fetch a `.gltf` or `.glb` and the files it references relative to its url. `on_progress` is
+/// called with a `LoadProgress` after each file, and images are decoded as `decoding` says,
+/// blocking the thread by default. Draco compressed meshes need `draco`, the `DecoderModule`
+/// from draco's `draco_decoder.js` once it has loaded
+#[wasm_bindgen]
+pub async fn fetch_gltf(
+    url: String,
+    on_progress: Option<js_sys::Function>,
+    decoding: Option<ImageDecoding>,
+    draco: Option<js_sys::Object>,
+) -> Result<GltfModel, JsValue> {
+    let decoding = decoding.unwrap_or(ImageDecoding::Blocking);
+    let mut progress = |state: LoadProgress| {
+        if let Some(ref on_progress) = on_progress {
+            // errors thrown by the callback shouldn't stop the load
+            let _ = on_progress.call1(&JsValue::NULL, &state.into());
+        }
+    };
+    let decode_images = decoding == ImageDecoding::Blocking;
+    let draco = draco.map(|module| DracoDecoder::new(module.into()));
+    let draco = draco.as_ref().map(|draco| draco as &dyn DecodeDraco);
+    let mut assets =
+        fetch_gltf_assets(&HttpFetch, &url, &mut progress, decode_images, draco).await?;
+    if decoding == ImageDecoding::ImageBitmap {
+        decode_image_bitmaps(&mut assets.images).await?;
+    }
+    Ok(GltfModel {
+        url: Some(url),
+        assets,
+    })
+}
+
+/// import a `.gltf` or `.glb` that's already in memory. `files` maps the uris of the external
+/// buffers and images it references to their contents as `Uint8Array`s or `ArrayBuffer`s, e.g.
+/// `{"scene.bin": bytes}`, while data uris are decoded. Images are decoded unless
+/// `decode_images` is false, leaving them for `decode_model_images` or a web worker. Draco
+/// compressed meshes need `draco`, as they do for `fetch_gltf`
+#[wasm_bindgen]
+pub fn import_gltf(
+    data: &[u8],
+    files: Option<js_sys::Object>,
+    decode_images: Option<bool>,
+    draco: Option<js_sys::Object>,
+) -> Result<GltfModel, JsValue> {
+    let mut resolver = HashMap::new();
+    if let Some(files) = files {
+        for entry in js_sys::Object::entries(&files).iter() {
+            let entry = js_sys::Array::from(&entry);
+            let uri = entry
+                .get(0)
+                .as_string()
+                .ok_or("file names have to be strings")?;
+            let data = js_sys::Uint8Array::new(&entry.get(1)).to_vec();
+            resolver.insert(uri, data);
+        }
+    }
+    let draco = draco.map(|module| DracoDecoder::new(module.into()));
+    let draco = draco.as_ref().map(|draco| draco as &dyn DecodeDraco);
+    Ok(GltfModel {
+        url: None,
+        assets: import_gltf_assets(data, &resolver, decode_images.unwrap_or(true), draco)?,
+    })
+}
+
+/// decode every image of `model` that hasn't been with `createImageBitmap`, off the main thread
+#[wasm_bindgen]
+pub async fn decode_model_images(mut model: GltfModel) -> Result<GltfModel, JsValue> {
+    decode_image_bitmaps(&mut model.assets.images).await?;
+    Ok(model)
+}
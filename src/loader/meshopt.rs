@@ -0,0 +1,585 @@
+//! decoders for the vertex and index codecs of meshoptimizer, which `EXT_meshopt_compression`
+//! compresses buffer views with
+
+const VERTEX_HEADER: u8 = 0xA0;
+const INDEX_HEADER: u8 = 0xE0;
+const SEQUENCE_HEADER: u8 = 0xD0;
+
+/// vertices are encoded in blocks of at most this many bytes, and 256 vertices
+const VERTEX_BLOCK_SIZE_BYTES: usize = 8192;
+const VERTEX_BLOCK_MAX_SIZE: usize = 256;
+/// the bytes of a vertex attribute are encoded in groups of 16
+const BYTE_GROUP_SIZE: usize = 16;
+/// vertex data is padded to at least this many bytes at the end, holding the first vertex
+const TAIL_MAX_SIZE: usize = 32;
+
+/// how a compressed buffer view was encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshoptMode {
+    Attributes,
+    Triangles,
+    Indices,
+}
+
+/// the transform applied to attributes after encoding them, undone after decoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshoptFilter {
+    None,
+    Octahedral,
+    Quaternion,
+    Exponential,
+}
+
+/// decode `count` elements of `stride` bytes from a buffer view compressed with `mode`,
+/// undoing `filter`
+pub fn decode_meshopt(
+    data: &[u8],
+    count: usize,
+    stride: usize,
+    mode: MeshoptMode,
+    filter: MeshoptFilter,
+) -> Result<Vec<u8>, String> {
+    let mut decoded = match mode {
+        MeshoptMode::Attributes => decode_vertex_buffer(data, count, stride)?,
+        MeshoptMode::Triangles => decode_index_buffer(data, count, stride)?,
+        MeshoptMode::Indices => decode_index_sequence(data, count, stride)?,
+    };
+    match filter {
+        MeshoptFilter::None => {}
+        _ if mode != MeshoptMode::Attributes => {
+            return Err("only attributes can be filtered".into());
+        }
+        MeshoptFilter::Octahedral => octahedral_filter(&mut decoded, stride)?,
+        MeshoptFilter::Quaternion => quaternion_filter(&mut decoded, stride)?,
+        MeshoptFilter::Exponential => exponential_filter(&mut decoded, stride)?,
+    }
+    Ok(decoded)
+}
+
+/// decode `count` vertices of `size` bytes, each byte of which was delta encoded against the
+/// previous vertex and packed into groups
+pub fn decode_vertex_buffer(data: &[u8], count: usize, size: usize) -> Result<Vec<u8>, String> {
+    if size == 0 || size > 256 || !size.is_multiple_of(4) {
+        return Err(format!("can't decode vertices of {} bytes", size));
+    }
+    let tail_size = size.max(TAIL_MAX_SIZE);
+    if data.len() < 1 + tail_size {
+        return Err("the vertex data is cut off".into());
+    }
+    if data[0] & 0xF0 != VERTEX_HEADER || data[0] & 0x0F > 0 {
+        return Err(format!("unknown vertex encoding {:#x}", data[0]));
+    }
+    // deltas start from the first vertex, stored at the end
+    let mut last_vertex = data[data.len() - size..].to_vec();
+    let body = &data[..data.len() - tail_size];
+    let block_size =
+        ((VERTEX_BLOCK_SIZE_BYTES / size) & !(BYTE_GROUP_SIZE - 1)).min(VERTEX_BLOCK_MAX_SIZE);
+
+    let mut decoded = vec![0; count * size];
+    let mut position = 1;
+    let mut bytes = vec![0; block_size];
+    for block_start in (0..count).step_by(block_size) {
+        let block_count = block_size.min(count - block_start);
+        let aligned_count = (block_count + BYTE_GROUP_SIZE - 1) & !(BYTE_GROUP_SIZE - 1);
+        let block = &mut decoded[block_start * size..(block_start + block_count) * size];
+        for byte in 0..size {
+            position = decode_bytes(body, position, &mut bytes[..aligned_count])?;
+            let mut previous = last_vertex[byte];
+            for (vertex, &delta) in bytes[..block_count].iter().enumerate() {
+                previous = previous.wrapping_add(unzigzag8(delta));
+                block[vertex * size + byte] = previous;
+            }
+        }
+        last_vertex.copy_from_slice(&block[(block_count - 1) * size..]);
+    }
+    if position != body.len() {
+        return Err("the vertex data has bytes left over".into());
+    }
+    Ok(decoded)
+}
+
+/// decode the byte groups filling `bytes` from `data` at `position`, returning where they end
+fn decode_bytes(data: &[u8], position: usize, bytes: &mut [u8]) -> Result<usize, String> {
+    let cut_off = || "the vertex data is cut off".to_string();
+    let groups = bytes.len() / BYTE_GROUP_SIZE;
+    // two bits per group for how many bits its bytes take
+    let header_size = groups.div_ceil(4);
+    let header = data
+        .get(position..position + header_size)
+        .ok_or_else(cut_off)?;
+    let mut position = position + header_size;
+    for (group, output) in bytes.chunks_mut(BYTE_GROUP_SIZE).enumerate() {
+        let bits_log2 = (header[group / 4] >> ((group % 4) * 2)) & 3;
+        position = match bits_log2 {
+            0 => {
+                output.fill(0);
+                position
+            }
+            3 => {
+                let raw = data.get(position..position + 16).ok_or_else(cut_off)?;
+                output.copy_from_slice(raw);
+                position + 16
+            }
+            _ => {
+                let bits = 1 << bits_log2;
+                let packed = data
+                    .get(position..position + bits * 2)
+                    .ok_or_else(cut_off)?;
+                // values with every bit set are escapes, stored as whole bytes after the group
+                let escape = (1u8 << bits) - 1;
+                let mut extra = position + bits * 2;
+                for (index, value) in output.iter_mut().enumerate() {
+                    let bit = index * bits;
+                    let packed_value = (packed[bit / 8] >> (8 - bits - bit % 8)) & escape;
+                    *value = if packed_value == escape {
+                        extra += 1;
+                        *data.get(extra - 1).ok_or_else(cut_off)?
+                    } else {
+                        packed_value
+                    };
+                }
+                extra
+            }
+        };
+    }
+    Ok(position)
+}
+
+fn unzigzag8(value: u8) -> u8 {
+    (value & 1).wrapping_neg() ^ (value >> 1)
+}
+
+/// decode `count` triangle list indices of `size` bytes, encoded as edges and vertices shared
+/// with recent triangles
+pub fn decode_index_buffer(data: &[u8], count: usize, size: usize) -> Result<Vec<u8>, String> {
+    if !count.is_multiple_of(3) {
+        return Err("triangle indices have to come in threes".into());
+    }
+    if data.len() < 1 + count / 3 + 16 {
+        return Err("the index data is cut off".into());
+    }
+    let version = data[0] & 0x0F;
+    if data[0] & 0xF0 != INDEX_HEADER || version > 1 {
+        return Err(format!("unknown index encoding {:#x}", data[0]));
+    }
+    // one code per triangle, then the data they read, then a table of common vertex reuses
+    let codes = &data[1..1 + count / 3];
+    let aux_table = &data[data.len() - 16..];
+    let mut reader = Reader {
+        data,
+        position: 1 + count / 3,
+        end: data.len() - 16,
+    };
+    // the most recent edges and vertices, which triangles reuse by their distance back
+    let mut edges = [(u32::MAX, u32::MAX); 16];
+    let mut vertices = [u32::MAX; 16];
+    let (mut edge_offset, mut vertex_offset) = (0, 0);
+    // the next new vertex, and the last vertex that was encoded as a delta
+    let (mut next, mut last) = (0u32, 0u32);
+    let max_vertex_reuse = if version >= 1 { 13 } else { 15 };
+
+    let mut indices = Vec::with_capacity(count);
+    for &code in codes {
+        let [a, b, c] = if code < 0xF0 {
+            // a triangle on a recent edge
+            let (a, b) = edges[(edge_offset + 15 - (code >> 4) as usize) & 15];
+            let reuse = (code & 15) as usize;
+            let c = if reuse == 0 {
+                next += 1;
+                next - 1
+            } else if reuse < max_vertex_reuse {
+                vertices[(vertex_offset + 15 - reuse) & 15]
+            } else {
+                // 13 and 14 step back or forward from the last delta encoded vertex
+                last = match reuse {
+                    13 => last.wrapping_sub(1),
+                    14 => last.wrapping_add(1),
+                    _ => reader.index(last)?,
+                };
+                last
+            };
+            let new_vertex = reuse == 0 || reuse >= max_vertex_reuse;
+            push_vertex(&mut vertices, &mut vertex_offset, c, new_vertex);
+            push_edge(&mut edges, &mut edge_offset, (c, b));
+            push_edge(&mut edges, &mut edge_offset, (a, c));
+            [a, b, c]
+        } else {
+            // a triangle away from recent edges, the codes above 0xFD reading their vertex
+            // reuses from the data rather than the table
+            let table = code < 0xFE;
+            let aux = if table {
+                aux_table[(code & 15) as usize]
+            } else {
+                reader.byte()?
+            };
+            let (reuse_b, reuse_c) = ((aux >> 4) as usize, (aux & 15) as usize);
+            if !table && aux == 0 {
+                next = 0;
+            }
+            let delta_a = code == 0xFF;
+            let mut vertex = |reuse: usize| {
+                if reuse == 0 {
+                    next += 1;
+                    next - 1
+                } else {
+                    vertices[(vertex_offset + 16 - reuse) & 15]
+                }
+            };
+            let mut a = if delta_a { 0 } else { vertex(0) };
+            let mut b = vertex(reuse_b);
+            let mut c = vertex(reuse_c);
+            if delta_a {
+                a = reader.index(last)?;
+                last = a;
+            }
+            if !table && reuse_b == 15 {
+                b = reader.index(last)?;
+                last = b;
+            }
+            if !table && reuse_c == 15 {
+                c = reader.index(last)?;
+                last = c;
+            }
+            push_vertex(&mut vertices, &mut vertex_offset, a, true);
+            push_vertex(
+                &mut vertices,
+                &mut vertex_offset,
+                b,
+                reuse_b == 0 || reuse_b == 15,
+            );
+            push_vertex(
+                &mut vertices,
+                &mut vertex_offset,
+                c,
+                reuse_c == 0 || reuse_c == 15,
+            );
+            push_edge(&mut edges, &mut edge_offset, (b, a));
+            push_edge(&mut edges, &mut edge_offset, (c, b));
+            push_edge(&mut edges, &mut edge_offset, (a, c));
+            [a, b, c]
+        };
+        indices.extend_from_slice(&[a, b, c]);
+    }
+    if reader.position != reader.end {
+        return Err("the index data has bytes left over".into());
+    }
+    write_indices(&indices, size)
+}
+
+/// add `vertex` to the history, only taking up a place if `push` is set
+fn push_vertex(vertices: &mut [u32; 16], offset: &mut usize, vertex: u32, push: bool) {
+    vertices[*offset] = vertex;
+    *offset = (*offset + push as usize) & 15;
+}
+
+fn push_edge(edges: &mut [(u32, u32); 16], offset: &mut usize, edge: (u32, u32)) {
+    edges[*offset] = edge;
+    *offset = (*offset + 1) & 15;
+}
+
+/// decode `count` indices of `size` bytes, each delta encoded against one of two previous
+/// indices
+pub fn decode_index_sequence(data: &[u8], count: usize, size: usize) -> Result<Vec<u8>, String> {
+    if data.len() < 1 + count + 4 {
+        return Err("the index data is cut off".into());
+    }
+    if data[0] & 0xF0 != SEQUENCE_HEADER || data[0] & 0x0F > 1 {
+        return Err(format!("unknown index sequence encoding {:#x}", data[0]));
+    }
+    let mut reader = Reader {
+        data,
+        position: 1,
+        end: data.len() - 4,
+    };
+    let mut last = [0u32; 2];
+    let mut indices = Vec::with_capacity(count);
+    for _ in 0..count {
+        let value = reader.vbyte()?;
+        // the low bit picks the index the rest is a delta from
+        let baseline = (value & 1) as usize;
+        last[baseline] = unzigzag32(value >> 1).wrapping_add(last[baseline]);
+        indices.push(last[baseline]);
+    }
+    if reader.position != reader.end {
+        return Err("the index data has bytes left over".into());
+    }
+    write_indices(&indices, size)
+}
+
+/// reads the variable length parts of encoded indices, up to `end`
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    end: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        if self.position >= self.end {
+            return Err("the index data is cut off".into());
+        }
+        self.position += 1;
+        Ok(self.data[self.position - 1])
+    }
+
+    /// a little endian base 128 number of up to 5 bytes
+    fn vbyte(&mut self) -> Result<u32, String> {
+        let mut value = 0;
+        for shift in (0..35).step_by(7) {
+            let group = self.byte()?;
+            value |= ((group & 127) as u32) << shift;
+            if group < 128 {
+                break;
+            }
+        }
+        Ok(value)
+    }
+
+    /// an index stored as a delta from `last`
+    fn index(&mut self, last: u32) -> Result<u32, String> {
+        Ok(last.wrapping_add(unzigzag32(self.vbyte()?)))
+    }
+}
+
+fn unzigzag32(value: u32) -> u32 {
+    (value & 1).wrapping_neg() ^ (value >> 1)
+}
+
+fn write_indices(indices: &[u32], size: usize) -> Result<Vec<u8>, String> {
+    match size {
+        2 => Ok(indices
+            .iter()
+            .flat_map(|&index| (index as u16).to_le_bytes())
+            .collect()),
+        4 => Ok(indices
+            .iter()
+            .flat_map(|&index| index.to_le_bytes())
+            .collect()),
+        _ => Err(format!("indices can't be {} bytes", size)),
+    }
+}
+
+/// rebuild unit vectors from the x and y of their octahedral mapping, as 8 or 16 bit
+/// normalized integers with the fourth component left alone
+fn octahedral_filter(data: &mut [u8], stride: usize) -> Result<(), String> {
+    let bytes = match stride {
+        4 => 1,
+        8 => 2,
+        _ => return Err("octahedral filtering needs a stride of 4 or 8".into()),
+    };
+    let max = ((1 << (bytes * 8 - 1)) - 1) as f32;
+    for element in data.chunks_exact_mut(stride) {
+        let [mut x, mut y, z] = [0, 1, 2].map(|component| read_signed(element, component, bytes));
+        let z = z - x.abs() - y.abs();
+        let t = z.min(0.0);
+        x += if x >= 0.0 { t } else { -t };
+        y += if y >= 0.0 { t } else { -t };
+        let scale = max / (x * x + y * y + z * z).sqrt();
+        for (component, &value) in [x, y, z].iter().enumerate() {
+            write_signed(element, component, bytes, round(value * scale));
+        }
+    }
+    Ok(())
+}
+
+/// rebuild unit quaternions from the three smallest components, with the largest one's index
+/// in the low bits of the fourth
+fn quaternion_filter(data: &mut [u8], stride: usize) -> Result<(), String> {
+    if stride != 8 {
+        return Err("quaternion filtering needs a stride of 8".into());
+    }
+    let scale = 1.0 / 2f32.sqrt();
+    for element in data.chunks_exact_mut(8) {
+        let packed = read_signed(element, 3, 2) as i32;
+        let component_scale = scale / (packed | 3) as f32;
+        let [x, y, z] =
+            [0, 1, 2].map(|component| read_signed(element, component, 2) * component_scale);
+        let w = (1.0 - x * x - y * y - z * z).max(0.0).sqrt();
+        let largest = (packed & 3) as usize;
+        for (offset, &value) in [w, x, y, z].iter().enumerate() {
+            write_signed(element, (largest + offset) & 3, 2, round(value * 32767.0));
+        }
+    }
+    Ok(())
+}
+
+/// turn 32 bit values holding an 8 bit exponent and 24 bit mantissa into floats
+fn exponential_filter(data: &mut [u8], stride: usize) -> Result<(), String> {
+    if !stride.is_multiple_of(4) {
+        return Err("exponential filtering needs a stride that's a multiple of 4".into());
+    }
+    for value in data.chunks_exact_mut(4) {
+        let bits = u32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+        let mantissa = ((bits << 8) as i32) >> 8;
+        let exponent = (bits as i32) >> 24;
+        let float = 2f32.powi(exponent) * mantissa as f32;
+        value.copy_from_slice(&float.to_le_bytes());
+    }
+    Ok(())
+}
+
+fn read_signed(element: &[u8], component: usize, bytes: usize) -> f32 {
+    match bytes {
+        1 => element[component] as i8 as f32,
+        _ => i16::from_le_bytes([element[component * 2], element[component * 2 + 1]]) as f32,
+    }
+}
+
+fn write_signed(element: &mut [u8], component: usize, bytes: usize, value: i32) {
+    match bytes {
+        1 => element[component] = value as i8 as u8,
+        _ => {
+            element[component * 2..component * 2 + 2].copy_from_slice(&(value as i16).to_le_bytes())
+        }
+    }
+}
+
+/// round half away from zero, like the reference decoder
+fn round(value: f32) -> i32 {
+    (value + if value >= 0.0 { 0.5 } else { -0.5 }) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // fixtures encoded with the reference meshoptimizer encoder
+
+    /// the corners of a unit quad as three floats each
+    const QUAD_POSITIONS: [f32; 12] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0];
+    const ENCODED_QUAD_POSITIONS: [u8; 69] = [
+        0xA0, 0x00, 0x00, 0x01, 0x3F, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0x01, 0x3F, 0x00, 0x00,
+        0x00, 0x7E, 0x7D, 0x7E, 0x00, 0x00, 0x01, 0x0C, 0x00, 0x00, 0x00, 0xFF, 0x01, 0x0C, 0x00,
+        0x00, 0x00, 0x7E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    /// the two triangles of the quad
+    const QUAD_INDICES: [u32; 6] = [0, 1, 2, 2, 1, 3];
+    const ENCODED_QUAD_TRIANGLES: [u8; 19] = [
+        0xE0, 0xF0, 0x10, 0x00, 0x76, 0x87, 0x56, 0x67, 0x78, 0xA9, 0x86, 0x65, 0x89, 0x68, 0x98,
+        0x01, 0x69, 0x00, 0x00,
+    ];
+
+    fn bytes_of(values: &[f32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    fn index_bytes(indices: &[u32], size: usize) -> Vec<u8> {
+        indices
+            .iter()
+            .flat_map(|&index| index.to_le_bytes()[..size].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_vertex_buffers() {
+        let decoded = decode_vertex_buffer(&ENCODED_QUAD_POSITIONS, 4, 12).unwrap();
+        assert_eq!(decoded, bytes_of(&QUAD_POSITIONS));
+    }
+
+    #[test]
+    fn rejects_truncated_vertex_buffers() {
+        assert!(decode_vertex_buffer(&ENCODED_QUAD_POSITIONS[..40], 4, 12).is_err());
+        assert!(decode_vertex_buffer(&ENCODED_QUAD_POSITIONS, 4, 6).is_err());
+    }
+
+    #[test]
+    fn decodes_index_buffers() {
+        for &size in &[2, 4] {
+            let decoded = decode_index_buffer(&ENCODED_QUAD_TRIANGLES, 6, size).unwrap();
+            assert_eq!(decoded, index_bytes(&QUAD_INDICES, size));
+        }
+        assert!(decode_index_buffer(&ENCODED_QUAD_TRIANGLES, 5, 2).is_err());
+    }
+
+    #[test]
+    fn decodes_index_sequences() {
+        let indices = [0, 1, 2, 2, 1, 3, 300, 4];
+        // zigzagged deltas shifted over a baseline bit, with 300 as a delta from the second
+        // baseline and the rest from the first
+        let encoded = [
+            0xD1, 0x00, 0x04, 0x04, 0x00, 0x02, 0x08, 0xB1, 0x09, 0x04, 0x00, 0x00, 0x00, 0x00,
+        ];
+        for &size in &[2, 4] {
+            let decoded = decode_index_sequence(&encoded, indices.len(), size).unwrap();
+            assert_eq!(decoded, index_bytes(&indices, size));
+        }
+        assert!(decode_index_sequence(&encoded, indices.len() + 1, 4).is_err());
+    }
+
+    #[test]
+    fn undoes_the_octahedral_filter() {
+        let encoded = [
+            0xA0, 0x01, 0x3C, 0x00, 0x00, 0x00, 0xFE, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7F,
+            0x09,
+        ];
+        let decoded = decode_meshopt(
+            &encoded,
+            3,
+            4,
+            MeshoptMode::Attributes,
+            MeshoptFilter::Octahedral,
+        )
+        .unwrap();
+        // +Z, +X and -X as 8 bit normals
+        assert_eq!(decoded, [0, 0, 127, 9, 127, 0, 0, 9, 0x81, 0, 0, 9]);
+    }
+
+    #[test]
+    fn undoes_the_quaternion_filter() {
+        let mut encoded = [0; 41];
+        encoded[0] = 0xA0;
+        encoded[39..].copy_from_slice(&[0xFE, 0x3F]);
+        let decoded = decode_meshopt(
+            &encoded,
+            1,
+            8,
+            MeshoptMode::Attributes,
+            MeshoptFilter::Quaternion,
+        )
+        .unwrap();
+        // the identity rotation, with w as the largest component
+        let mut identity = [0; 8];
+        identity[4..6].copy_from_slice(&32767i16.to_le_bytes());
+        assert_eq!(decoded, identity);
+    }
+
+    #[test]
+    fn undoes_the_exponential_filter() {
+        let encoded = [
+            0xA0, 0x01, 0x3C, 0x00, 0x00, 0x00, 0x11, 0x08, 0x01, 0x18, 0x00, 0x00, 0x00, 0x01,
+            0x18, 0x00, 0x00, 0x00, 0x01, 0x3C, 0x00, 0x00, 0x00, 0x04, 0x06, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00,
+            0xFE,
+        ];
+        let decoded = decode_meshopt(
+            &encoded,
+            3,
+            4,
+            MeshoptMode::Attributes,
+            MeshoptFilter::Exponential,
+        )
+        .unwrap();
+        // 6 * 2^-2, -3 * 2^0 and 1 * 2^3
+        assert_eq!(decoded, bytes_of(&[1.5, -3.0, 8.0]));
+    }
+
+    #[test]
+    fn only_filters_attributes() {
+        let result = decode_meshopt(
+            &ENCODED_QUAD_TRIANGLES,
+            6,
+            2,
+            MeshoptMode::Triangles,
+            MeshoptFilter::Octahedral,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,30 @@
+mod decode;
+pub use decode::{
+    decode_image, decode_image_bitmap, decode_image_bitmaps, ImageAsset, ImageDecoding,
+};
+
+mod draco;
+pub use draco::{decode_draco_primitives, DecodeDraco, DracoAttribute, DracoDecoder, DracoMesh};
+
+mod extensions;
+pub use extensions::{parse_gltf, ParsedGltf};
+
+mod fetch;
+#[cfg(not(target_arch = "wasm32"))]
+pub use fetch::FileFetch;
+pub use fetch::{Fetch, FetchFuture, HttpFetch};
+
+mod import;
+pub use import::{
+    buffer_data, decode_model_images, external_uris, fetch_gltf, fetch_gltf_assets, image_data,
+    import_gltf, import_gltf_assets, GltfAssets, GltfModel, LoadProgress, Resolve,
+};
+
+mod meshopt;
+pub use meshopt::{
+    decode_index_buffer, decode_index_sequence, decode_meshopt, decode_vertex_buffer,
+    MeshoptFilter, MeshoptMode,
+};
+
+mod uri;
+pub use uri::{decode_data_uri, is_data_uri, percent_decode, resolve_uri, DataUri};
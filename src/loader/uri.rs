@@ -0,0 +1,293 @@
+/// the decoded contents of a `data:` uri
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataUri {
+    pub mime_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// resolve `uri` relative to the url or path `base` it was referenced from, the way a browser
+/// resolves links. Uris with a scheme, like `data:` or `https:`, are already absolute
+pub fn resolve_uri(base: &str, uri: &str) -> String {
+    if scheme(uri).is_some() {
+        return uri.to_string();
+    }
+    let base = strip_query(base);
+    let (origin, base_path) = split_origin(base);
+    if uri.starts_with("//") {
+        return match scheme(base) {
+            Some(scheme) => format!("{}:{}", scheme, uri),
+            None => uri.to_string(),
+        };
+    }
+    let (uri_path, uri_query) = match uri.find(['?', '#']) {
+        Some(index) => uri.split_at(index),
+        None => (uri, ""),
+    };
+    let path = if uri_path.starts_with('/') {
+        uri_path.to_string()
+    } else {
+        let directory = match base_path.rfind('/') {
+            Some(index) => &base_path[..=index],
+            // a url without a path is at the root of its host
+            None if origin.ends_with(|c| c != ':') => "/",
+            None => "",
+        };
+        format!("{}{}", directory, uri_path)
+    };
+    format!("{}{}{}", origin, remove_dot_segments(&path), uri_query)
+}
+
+pub fn is_data_uri(uri: &str) -> bool {
+    uri.starts_with("data:")
+}
+
+/// decode a `data:[<mime type>][;base64],<data>` uri, with the data percent encoded if it isn't
+/// base64
+pub fn decode_data_uri(uri: &str) -> Result<DataUri, String> {
+    let contents = uri.strip_prefix("data:").ok_or("not a data uri")?;
+    let comma = contents.find(',').ok_or("data uri has no data")?;
+    let (header, data) = (&contents[..comma], &contents[comma + 1..]);
+    let (mime_type, base64) = match header.strip_suffix(";base64") {
+        Some(mime_type) => (mime_type, true),
+        None => (header, false),
+    };
+    let data = if base64 {
+        base64::decode(data).map_err(|err| format!("invalid base64 in data uri: {}", err))?
+    } else {
+        percent_decode(data)
+    };
+    let mime_type = mime_type.split(';').next().filter(|mime| !mime.is_empty());
+    Ok(DataUri {
+        mime_type: mime_type.map(str::to_string),
+        data,
+    })
+}
+
+/// replace `%XX` escapes with the bytes they encode, leaving malformed ones as they are
+pub fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i + 1..i + 3) {
+            Some(hex) if bytes[i] == b'%' => std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// the scheme of an absolute uri, e.g. `https`
+fn scheme(uri: &str) -> Option<&str> {
+    let colon = uri.find(':')?;
+    let scheme = &uri[..colon];
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+    // a single letter is more likely a windows drive than a scheme
+    if valid && scheme.len() > 1 {
+        Some(scheme)
+    } else {
+        None
+    }
+}
+
+fn strip_query(uri: &str) -> &str {
+    match uri.find(['?', '#']) {
+        Some(index) => &uri[..index],
+        None => uri,
+    }
+}
+
+/// split a url into its `scheme://authority` and path, the first empty for plain paths
+fn split_origin(uri: &str) -> (&str, &str) {
+    let after_scheme = match scheme(uri) {
+        Some(scheme) => scheme.len() + 1,
+        None => return ("", uri),
+    };
+    if !uri[after_scheme..].starts_with("//") {
+        return uri.split_at(after_scheme);
+    }
+    let authority = after_scheme + 2;
+    match uri[authority..].find('/') {
+        Some(index) => uri.split_at(authority + index),
+        None => (uri, ""),
+    }
+}
+
+/// collapse `.` and `..` segments, never going above the start of the path
+fn remove_dot_segments(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').peekable();
+    while let Some(part) = parts.next() {
+        let last = parts.peek().is_none();
+        match part {
+            "." | ".." => {
+                if part == ".." && segments.last().is_some_and(|segment| *segment != "..") {
+                    segments.pop();
+                } else if part == ".." && !absolute {
+                    segments.push("..");
+                }
+                // a trailing dot segment still names a directory
+                if last {
+                    segments.push("");
+                }
+            }
+            "" if !last => {}
+            _ => segments.push(part),
+        }
+    }
+    let joined = segments.join("/");
+    if absolute {
+        format!("/{}", joined)
+    } else {
+        joined
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_relative_paths() {
+        let base = "https://example.com/models/duck/duck.gltf";
+        assert_eq!(
+            resolve_uri(base, "duck.bin"),
+            "https://example.com/models/duck/duck.bin"
+        );
+        assert_eq!(
+            resolve_uri(base, "textures/duck.png"),
+            "https://example.com/models/duck/textures/duck.png"
+        );
+        assert_eq!(
+            resolve_uri("models/duck.gltf", "duck.bin"),
+            "models/duck.bin"
+        );
+        assert_eq!(resolve_uri("duck.gltf", "duck.bin"), "duck.bin");
+        assert_eq!(
+            resolve_uri("https://example.com", "duck.bin"),
+            "https://example.com/duck.bin"
+        );
+    }
+
+    #[test]
+    fn resolves_dot_segments() {
+        let base = "https://example.com/models/duck/duck.gltf";
+        assert_eq!(
+            resolve_uri(base, "../shared/duck.bin"),
+            "https://example.com/models/shared/duck.bin"
+        );
+        assert_eq!(
+            resolve_uri(base, "./textures/./duck.png"),
+            "https://example.com/models/duck/textures/duck.png"
+        );
+        // urls can't go above their host
+        assert_eq!(
+            resolve_uri(base, "../../../../duck.bin"),
+            "https://example.com/duck.bin"
+        );
+        // while relative paths keep the segments they can't remove
+        assert_eq!(
+            resolve_uri("models/duck.gltf", "../../duck.bin"),
+            "../duck.bin"
+        );
+    }
+
+    #[test]
+    fn resolves_absolute_uris() {
+        let base = "https://example.com/models/duck.gltf";
+        assert_eq!(
+            resolve_uri(base, "/assets/duck.bin"),
+            "https://example.com/assets/duck.bin"
+        );
+        assert_eq!(
+            resolve_uri(base, "//cdn.example.com/duck.bin"),
+            "https://cdn.example.com/duck.bin"
+        );
+        assert_eq!(
+            resolve_uri(base, "http://other.example.com/duck.bin"),
+            "http://other.example.com/duck.bin"
+        );
+        assert_eq!(resolve_uri("models/duck.gltf", "/duck.bin"), "/duck.bin");
+    }
+
+    #[test]
+    fn keeps_the_query_and_fragment_of_the_uri_only() {
+        let base = "https://example.com/models/duck.gltf?version=2#mesh";
+        assert_eq!(
+            resolve_uri(base, "duck.bin"),
+            "https://example.com/models/duck.bin"
+        );
+        assert_eq!(
+            resolve_uri(base, "../duck.bin?version=3#buffer"),
+            "https://example.com/duck.bin?version=3#buffer"
+        );
+        assert_eq!(
+            resolve_uri(base, "duck.bin#a/../b"),
+            "https://example.com/models/duck.bin#a/../b"
+        );
+    }
+
+    #[test]
+    fn leaves_data_uris_alone() {
+        let uri = "data:application/octet-stream;base64,AAEC";
+        assert_eq!(
+            resolve_uri("https://example.com/models/duck.gltf", uri),
+            uri
+        );
+        assert!(is_data_uri(uri));
+        assert!(!is_data_uri("duck.bin"));
+    }
+
+    #[test]
+    fn removes_dot_segments() {
+        assert_eq!(remove_dot_segments("/a/b/c/./../../g"), "/a/g");
+        assert_eq!(remove_dot_segments("mid/content=5/../6"), "mid/6");
+        assert_eq!(remove_dot_segments("/a/b/."), "/a/b/");
+        assert_eq!(remove_dot_segments("/a/b/.."), "/a/");
+        assert_eq!(remove_dot_segments("/.."), "/");
+        assert_eq!(remove_dot_segments("../a"), "../a");
+        assert_eq!(remove_dot_segments("a//b"), "a/b");
+    }
+
+    #[test]
+    fn decodes_data_uris() {
+        let base64 = decode_data_uri("data:application/octet-stream;base64,AAEC").unwrap();
+        assert_eq!(
+            base64.mime_type.as_deref(),
+            Some("application/octet-stream")
+        );
+        assert_eq!(base64.data, [0, 1, 2]);
+
+        let text = decode_data_uri("data:text/plain;charset=utf-8,a%20b%zz").unwrap();
+        assert_eq!(text.mime_type.as_deref(), Some("text/plain"));
+        assert_eq!(text.data, b"a b%zz");
+
+        let untyped = decode_data_uri("data:,").unwrap();
+        assert_eq!(
+            untyped,
+            DataUri {
+                mime_type: None,
+                data: Vec::new()
+            }
+        );
+
+        assert!(decode_data_uri("data:;base64,!!").is_err());
+        assert!(decode_data_uri("data:text/plain").is_err());
+        assert!(decode_data_uri("duck.bin").is_err());
+    }
+}
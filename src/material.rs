@@ -0,0 +1,66 @@
+/// how a material's alpha is interpreted, uploaded as an int so the values must match the
+/// `ALPHA_` constants in `tetra.frag`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// alpha is ignored and the surface is fully opaque
+    Opaque = 0,
+    /// fragments with alpha below the cutoff are discarded and the rest are opaque
+    Mask = 1,
+    /// the surface is blended over whatever is behind it
+    Blend = 2,
+}
+
+use crate::resources::TextureHandle;
+
+/// the surface parameters of a mesh, following gltf's metallic roughness model
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub base_color: [f32; 4],
+    /// the texture multiplied into the base color
+    pub base_color_texture: Option<TextureHandle>,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// whether back faces are drawn too
+    pub double_sided: bool,
+    pub alpha_mode: AlphaMode,
+    /// the alpha below which masked fragments are discarded
+    pub alpha_cutoff: f32,
+}
+
+impl Material {
+    /// the material's parameters, with `textures` holding the texture loaded for each of the
+    /// glTF file's textures, if one was
+    pub fn from_gltf(material: &gltf::Material, textures: &[Option<TextureHandle>]) -> Material {
+        let pbr = material.pbr_metallic_roughness();
+        Material {
+            base_color: pbr.base_color_factor(),
+            base_color_texture: pbr
+                .base_color_texture()
+                .and_then(|info| textures.get(info.texture().index()).copied().flatten()),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            double_sided: material.double_sided(),
+            alpha_mode: match material.alpha_mode() {
+                gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+                gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+            },
+            alpha_cutoff: material.alpha_cutoff(),
+        }
+    }
+}
+
+impl Default for Material {
+    /// the gltf defaults: white, fully metallic and fully rough
+    fn default() -> Material {
+        Material {
+            base_color: [1.0; 4],
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            double_sided: false,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use gltf::mesh::util::ReadTexCoords;
+use log::info;
+use nalgebra as na;
+use wasm_bindgen::JsValue;
+use web_sys::WebGlRenderingContext as WebGlCtx;
+
+use crate::animation::MorphTargets;
+use crate::gl_abstraction::{GlBuffer, VertexArray, WebGl};
+use crate::material::Material;
+use crate::resources::TextureHandle;
+
+/// a primitive's indices, which are only 32 bit when they don't fit in 16 bits and the context
+/// supports them
+pub enum ElementBuffer {
+    U16(GlBuffer<u16>),
+    U32(GlBuffer<u32>),
+}
+
+impl ElementBuffer {
+    /// upload `indices` in the smallest type they fit in, failing if they need 32 bits and the
+    /// context only has 16 bit indices
+    pub fn new(gl: &WebGl, indices: Vec<u32>) -> Result<ElementBuffer, JsValue> {
+        let max_index = indices.iter().copied().max().unwrap_or(0);
+        if max_index <= u32::from(u16::MAX) {
+            let indices = indices.into_iter().map(|index| index as u16).collect();
+            Ok(ElementBuffer::U16(GlBuffer::new(
+                gl,
+                WebGlCtx::ELEMENT_ARRAY_BUFFER,
+                indices,
+            )?))
+        } else if gl.capabilities().uint_indices {
+            Ok(ElementBuffer::U32(GlBuffer::new(
+                gl,
+                WebGlCtx::ELEMENT_ARRAY_BUFFER,
+                indices,
+            )?))
+        } else {
+            Err(format!(
+                "index {} needs 32 bit indices, which this context doesn't support",
+                max_index
+            )
+            .into())
+        }
+    }
+
+    pub fn bind(&self) {
+        match self {
+            ElementBuffer::U16(buffer) => buffer.bind(),
+            ElementBuffer::U32(buffer) => buffer.bind(),
+        }
+    }
+
+    /// upload the indices again after the context was lost and restored
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        match self {
+            ElementBuffer::U16(buffer) => buffer.restore(),
+            ElementBuffer::U32(buffer) => buffer.restore(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            ElementBuffer::U16(buffer) => buffer.array().len(),
+            ElementBuffer::U32(buffer) => buffer.array().len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the type to draw the indices with, UNSIGNED_SHORT or UNSIGNED_INT
+    pub fn index_type(&self) -> u32 {
+        match self {
+            ElementBuffer::U16(_) => WebGlCtx::UNSIGNED_SHORT,
+            ElementBuffer::U32(_) => WebGlCtx::UNSIGNED_INT,
+        }
+    }
+}
+
+/// the attribute locations of the program primitives are drawn with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexLayout {
+    pub position: u32,
+    pub normal: u32,
+    pub tex_coord: u32,
+    /// whether the draws stream instance attributes into the primitives' vertex arrays
+    pub instanced: bool,
+}
+
+/// the primitives of a glTF mesh, loaded and unloaded together
+pub struct Mesh {
+    pub primitives: Vec<Primitive>,
+}
+
+impl Mesh {
+    /// upload every primitive of a glTF mesh that has positions, with the same arguments as
+    /// `Primitive::from_gltf`
+    pub fn from_gltf(
+        gl: &WebGl,
+        mesh: &gltf::Mesh,
+        buffers: &[gltf::buffer::Data],
+        textures: &[Option<TextureHandle>],
+        first_material_id: u32,
+    ) -> Result<Mesh, JsValue> {
+        let mut primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            if let Some(primitive) =
+                Primitive::from_gltf(gl, mesh, &primitive, buffers, textures, first_material_id)?
+            {
+                primitives.push(primitive);
+            }
+        }
+        Ok(Mesh { primitives })
+    }
+
+    /// the textures the primitives' materials use, each once
+    pub fn textures(&self) -> Vec<TextureHandle> {
+        let mut textures = Vec::new();
+        for texture in self
+            .primitives
+            .iter()
+            .filter_map(|primitive| primitive.material.base_color_texture)
+        {
+            if !textures.contains(&texture) {
+                textures.push(texture);
+            }
+        }
+        textures
+    }
+
+    /// the distance from the model origin to the furthest vertex of any primitive
+    pub fn bounding_radius(&self) -> f32 {
+        self.primitives
+            .iter()
+            .map(|primitive| primitive.bounding_radius)
+            .fold(0.0, f32::max)
+    }
+
+    /// upload every primitive again after the context was lost and restored
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        for primitive in &mut self.primitives {
+            primitive.restore()?;
+        }
+        Ok(())
+    }
+}
+
+/// a single drawable part of a glTF mesh with its own vertex buffers and material
+pub struct Primitive {
+    pub vert_buffer: GlBuffer<f32>,
+    pub normal_buffer: Option<GlBuffer<f32>>,
+    pub tex_coord_buffer: Option<GlBuffer<f32>>,
+    pub element_buffer: Option<ElementBuffer>,
+    pub morph_targets: Option<MorphTargets>,
+    pub material: Material,
+    /// the attributes recorded for the layout they were last prepared for, where vertex array
+    /// objects are supported
+    vertex_array: Option<(VertexLayout, VertexArray)>,
+    /// identifies the material among every loaded one, so draws sharing it can skip setting it
+    /// up again. `u32::MAX` for the default material
+    pub material_id: u32,
+    /// the average vertex position in model space, used to sort primitives by depth
+    pub centroid: na::Point3<f32>,
+    /// the distance from the model origin to the furthest vertex
+    pub bounding_radius: f32,
+}
+
+impl Primitive {
+    /// upload the attributes of a glTF primitive, or return `None` if it has no positions
+    /// # Arguments
+    /// * `textures` - the texture loaded for each of the glTF file's textures, if one was
+    /// * `first_material_id` - the id of the file's first material, the rest following in order
+    pub fn from_gltf(
+        gl: &WebGl,
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+        textures: &[Option<TextureHandle>],
+        first_material_id: u32,
+    ) -> Result<Option<Primitive>, JsValue> {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let vertices: Vec<f32> = match reader.read_positions() {
+            Some(iter) => {
+                info!("loading vertices...");
+                iter.flatten().collect()
+            }
+            None => return Ok(None),
+        };
+        let vertex_count = (vertices.len() / 3).max(1) as f32;
+        let centroid = vertices
+            .chunks_exact(3)
+            .fold(na::Point3::origin(), |sum, p| {
+                sum + na::Vector3::new(p[0], p[1], p[2]) / vertex_count
+            });
+        let bounding_radius = vertices
+            .chunks_exact(3)
+            .map(|p| (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt())
+            .fold(0.0, f32::max);
+
+        let tex_coords: Option<Vec<f32>> = match reader.read_tex_coords(0) {
+            Some(ReadTexCoords::F32(iter)) => {
+                info!("loading texture coords for texture 0...");
+                Some(iter.flatten().collect())
+            }
+            _ => None,
+        };
+        let normals: Option<Vec<f32>> = reader.read_normals().map(|iter| {
+            info!("loading normals...");
+            iter.flatten().collect()
+        });
+        let indices: Option<Vec<u32>> = reader.read_indices().map(|indices| {
+            info!("loading indices...");
+            indices.into_u32().collect()
+        });
+
+        let morph_targets = if primitive.morph_targets().len() > 0 {
+            info!("loading morph targets...");
+            let mut position_deltas = Vec::new();
+            let mut normal_deltas = Vec::new();
+            for (positions, normals, _) in reader.read_morph_targets() {
+                position_deltas.push(
+                    positions
+                        .map(|iter| iter.flatten().collect())
+                        .unwrap_or_default(),
+                );
+                normal_deltas.push(
+                    normals
+                        .map(|iter| iter.flatten().collect())
+                        .unwrap_or_default(),
+                );
+            }
+            let morph_targets = MorphTargets::new(
+                vertices.clone(),
+                normals.clone().unwrap_or_default(),
+                position_deltas,
+                normal_deltas,
+                mesh.weights().map(<[f32]>::to_vec).unwrap_or_default(),
+            )?;
+            info!("loaded {} morph targets!", morph_targets.len());
+            Some(morph_targets)
+        } else {
+            None
+        };
+
+        // morphed attributes get re-uploaded whenever the weights change
+        let (vert_buffer, normal_buffer) = match morph_targets {
+            Some(ref morph_targets) => {
+                let weights = morph_targets.default_weights();
+                let normal_buffer = match normals {
+                    Some(_) => Some(GlBuffer::new_with_usage(
+                        gl,
+                        WebGlCtx::ARRAY_BUFFER,
+                        morph_targets.blend_normals(weights),
+                        WebGlCtx::DYNAMIC_DRAW,
+                    )?),
+                    None => None,
+                };
+                let vert_buffer = GlBuffer::new_with_usage(
+                    gl,
+                    WebGlCtx::ARRAY_BUFFER,
+                    morph_targets.blend_positions(weights),
+                    WebGlCtx::DYNAMIC_DRAW,
+                )?;
+                (vert_buffer, normal_buffer)
+            }
+            None => (
+                GlBuffer::new(gl, WebGlCtx::ARRAY_BUFFER, vertices)?,
+                match normals {
+                    Some(normals) => Some(GlBuffer::new(gl, WebGlCtx::ARRAY_BUFFER, normals)?),
+                    None => None,
+                },
+            ),
+        };
+        let tex_coord_buffer = match tex_coords {
+            Some(tex_coords) => Some(GlBuffer::new(gl, WebGlCtx::ARRAY_BUFFER, tex_coords)?),
+            None => None,
+        };
+        let element_buffer = match indices {
+            Some(indices) => Some(ElementBuffer::new(gl, indices)?),
+            None => None,
+        };
+
+        Ok(Some(Primitive {
+            vert_buffer,
+            normal_buffer,
+            tex_coord_buffer,
+            element_buffer,
+            morph_targets,
+            material: Material::from_gltf(&primitive.material(), textures),
+            vertex_array: None,
+            material_id: primitive
+                .material()
+                .index()
+                .map_or(u32::MAX, |index| first_material_id + index as u32),
+            centroid,
+            bounding_radius,
+        }))
+    }
+
+    /// upload the vertex and index buffers again after the context was lost and restored,
+    /// including the current morph
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        // the vertex array went with the context, and is recorded again when next prepared
+        self.vertex_array = None;
+        self.vert_buffer.restore()?;
+        if let Some(ref mut normal_buffer) = self.normal_buffer {
+            normal_buffer.restore()?;
+        }
+        if let Some(ref mut tex_coord_buffer) = self.tex_coord_buffer {
+            tex_coord_buffer.restore()?;
+        }
+        match self.element_buffer {
+            Some(ref mut element_buffer) => element_buffer.restore(),
+            None => Ok(()),
+        }
+    }
+
+    /// re-upload the morphed positions and normals for `weights`
+    pub fn set_morph_weights(&mut self, weights: &[f32]) {
+        let morph_targets = match self.morph_targets {
+            Some(ref morph_targets) => morph_targets,
+            None => return,
+        };
+        self.vert_buffer
+            .set_array(morph_targets.blend_positions(weights));
+        if let Some(ref mut normal_buffer) = self.normal_buffer {
+            normal_buffer.set_array(morph_targets.blend_normals(weights));
+        }
+    }
+
+    /// record the attributes at the locations in `layout` in a vertex array object, unless they
+    /// already are or the context has no vertex array objects
+    pub fn prepare_vertex_array(&mut self, gl: &WebGl, layout: VertexLayout) {
+        let prepared = self
+            .vertex_array
+            .as_ref()
+            .is_some_and(|&(recorded, _)| recorded == layout);
+        if prepared || !gl.capabilities().vertex_arrays {
+            return;
+        }
+        // a new vertex array rather than the old one, which may still stream instances
+        self.vertex_array = VertexArray::new(gl).ok().map(|vertex_array| {
+            vertex_array.bind();
+            for (location, size, buffer, default) in self.attributes(layout) {
+                bind_attribute(gl, location, size, buffer, default);
+            }
+            vertex_array.unbind();
+            (layout, vertex_array)
+        });
+    }
+
+    /// bind the attributes at the locations in `layout`, from the primitive's vertex array if
+    /// it was prepared for them
+    pub fn bind_attributes(&self, gl: &WebGl, layout: VertexLayout) {
+        match self.vertex_array {
+            Some((recorded, ref vertex_array)) if recorded == layout => {
+                vertex_array.bind();
+                // constant attribute values aren't part of the vertex array
+                for (location, _, buffer, [x, y, z, w]) in self.attributes(layout) {
+                    if buffer.is_none() {
+                        gl.vertex_attrib4f(location, x, y, z, w);
+                    }
+                }
+            }
+            _ => {
+                gl.bind_vertex_array(None);
+                for (location, size, buffer, default) in self.attributes(layout) {
+                    bind_attribute(gl, location, size, buffer, default);
+                }
+            }
+        }
+    }
+
+    fn attributes(&self, layout: VertexLayout) -> [Attribute<'_>; 3] {
+        [
+            (
+                layout.position,
+                3,
+                Some(&self.vert_buffer),
+                [0.0, 0.0, 0.0, 1.0],
+            ),
+            (
+                layout.normal,
+                3,
+                self.normal_buffer.as_ref(),
+                [0.0, 0.0, 1.0, 0.0],
+            ),
+            (
+                layout.tex_coord,
+                2,
+                self.tex_coord_buffer.as_ref(),
+                [0.0, 0.0, 0.0, 0.0],
+            ),
+        ]
+    }
+
+    /// draw `instances` copies of the primitive's triangles at once, returning false without
+    /// drawing if instancing isn't supported
+    pub fn draw_instanced(&self, gl: &WebGl, instances: usize) -> bool {
+        if let Some(ref element_buffer) = self.element_buffer {
+            element_buffer.bind();
+            gl.draw_elements_instanced(
+                WebGlCtx::TRIANGLES,
+                element_buffer.len() as i32,
+                element_buffer.index_type(),
+                0,
+                instances as i32,
+            )
+        } else {
+            gl.draw_arrays_instanced(
+                WebGlCtx::TRIANGLES,
+                0,
+                (self.vert_buffer.array().len() / 3) as i32,
+                instances as i32,
+            )
+        }
+    }
+
+    /// draw the primitive's triangles with whatever program and attributes are bound
+    pub fn draw(&self, gl: &WebGl) {
+        if let Some(ref element_buffer) = self.element_buffer {
+            element_buffer.bind();
+            gl.draw_elements_with_i32(
+                WebGlCtx::TRIANGLES,
+                element_buffer.len() as i32,
+                element_buffer.index_type(),
+                0,
+            );
+        } else {
+            gl.draw_arrays(
+                WebGlCtx::TRIANGLES,
+                0,
+                (self.vert_buffer.array().len() / 3) as i32,
+            );
+        }
+    }
+}
+
+/// the location, floats per vertex, buffer and constant fallback of an attribute
+type Attribute<'a> = (u32, i32, Option<&'a GlBuffer<f32>>, [f32; 4]);
+
+/// point `location` at `buffer` with `size` floats per vertex, or use the constant `default`
+/// for every vertex if there is no buffer
+fn bind_attribute(
+    gl: &WebGl,
+    location: u32,
+    size: i32,
+    buffer: Option<&GlBuffer<f32>>,
+    default: [f32; 4],
+) {
+    match buffer {
+        Some(buffer) => {
+            buffer.bind();
+            gl.vertex_attrib_pointer_with_i32(location, size, WebGlCtx::FLOAT, false, 0, 0);
+            gl.enable_vertex_attrib_array(location);
+        }
+        None => {
+            gl.disable_vertex_attrib_array(location);
+            let [x, y, z, w] = default;
+            gl.vertex_attrib4f(location, x, y, z, w);
+        }
+    }
+}
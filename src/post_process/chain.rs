@@ -0,0 +1,140 @@
+use wasm_bindgen::prelude::*;
+
+/// a full screen effect applied after the scene has been drawn
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostEffect {
+    Fxaa,
+    ToneMapping,
+    Gamma,
+    Vignette,
+    Bloom,
+}
+
+impl PostEffect {
+    /// the parameters the effect understands along with their default values
+    pub fn default_params(self) -> &'static [(&'static str, f32)] {
+        match self {
+            PostEffect::Fxaa => &[],
+            PostEffect::ToneMapping => &[("exposure", 1.0)],
+            PostEffect::Gamma => &[("gamma", 2.2)],
+            PostEffect::Vignette => &[("strength", 0.5), ("radius", 0.75)],
+            PostEffect::Bloom => &[("threshold", 0.8), ("intensity", 0.6), ("radius", 1.0)],
+        }
+    }
+}
+
+/// an effect in the chain together with its parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectSettings {
+    effect: PostEffect,
+    enabled: bool,
+    params: Vec<(&'static str, f32)>,
+}
+
+impl EffectSettings {
+    pub fn new(effect: PostEffect) -> EffectSettings {
+        EffectSettings {
+            effect,
+            enabled: true,
+            params: effect.default_params().to_vec(),
+        }
+    }
+
+    pub fn effect(&self) -> PostEffect {
+        self.effect
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// the value of a parameter, panicking if the effect doesn't have it since the renderer only
+    /// asks for parameters it knows exist
+    pub fn param(&self, name: &str) -> f32 {
+        self.params
+            .iter()
+            .find(|(param, _)| *param == name)
+            .map(|&(_, value)| value)
+            .unwrap_or_else(|| panic!("{:?} has no parameter {}", self.effect, name))
+    }
+
+    pub fn set_param(&mut self, name: &str, value: f32) -> Result<(), String> {
+        let effect = self.effect;
+        let param = self
+            .params
+            .iter_mut()
+            .find(|(param, _)| *param == name)
+            .ok_or_else(|| format!("{:?} has no parameter {}", effect, name))?;
+        param.1 = value;
+        Ok(())
+    }
+}
+
+/// the ordered list of effects, kept apart from the renderer so it can be edited without gl
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PostChain {
+    effects: Vec<EffectSettings>,
+}
+
+impl PostChain {
+    pub fn new() -> PostChain {
+        PostChain::default()
+    }
+
+    /// append an effect with default parameters, returning its index
+    pub fn add(&mut self, effect: PostEffect) -> usize {
+        self.effects.push(EffectSettings::new(effect));
+        self.effects.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<EffectSettings, String> {
+        self.check_index(index)?;
+        Ok(self.effects.remove(index))
+    }
+
+    /// move the effect at `from` so that it ends up at index `to`
+    pub fn move_effect(&mut self, from: usize, to: usize) -> Result<(), String> {
+        self.check_index(from)?;
+        self.check_index(to)?;
+        let effect = self.effects.remove(from);
+        self.effects.insert(to, effect);
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> Result<(), String> {
+        self.check_index(index)?;
+        self.effects[index].enabled = enabled;
+        Ok(())
+    }
+
+    pub fn set_param(&mut self, index: usize, name: &str, value: f32) -> Result<(), String> {
+        self.check_index(index)?;
+        self.effects[index].set_param(name, value)
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn effects(&self) -> &[EffectSettings] {
+        &self.effects
+    }
+
+    /// the effects that will actually run, in order
+    pub fn enabled_effects(&self) -> impl Iterator<Item = &EffectSettings> {
+        self.effects.iter().filter(|effect| effect.enabled)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.effects.iter().any(|effect| effect.enabled)
+    }
+
+    fn check_index(&self, index: usize) -> Result<(), String> {
+        if index < self.effects.len() {
+            Ok(())
+        } else {
+            Err(format!("no post effect with index {}", index))
+        }
+    }
+}
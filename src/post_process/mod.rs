@@ -0,0 +1,5 @@
+mod chain;
+pub use chain::{EffectSettings, PostChain, PostEffect};
+
+mod processor;
+pub use processor::PostProcessor;
@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::JsValue;
+use web_sys::{WebGlRenderingContext as WebGlCtx, WebGlUniformLocation};
+
+use super::{EffectSettings, PostChain, PostEffect};
+use crate::gl_abstraction::{
+    Attachment, Framebuffer, GlBuffer, Program, Renderbuffer, Shader, Texture2D, WebGl,
+};
+use crate::render_state::{CullMode, RenderState, RenderStateTracker};
+
+const FULLSCREEN_VERT: &str = include_str!("../../assets/shaders/post/fullscreen.vert");
+const FXAA_FRAG: &str = include_str!("../../assets/shaders/post/fxaa.frag");
+const TONE_MAPPING_FRAG: &str = include_str!("../../assets/shaders/post/tone_mapping.frag");
+const GAMMA_FRAG: &str = include_str!("../../assets/shaders/post/gamma.frag");
+const VIGNETTE_FRAG: &str = include_str!("../../assets/shaders/post/vignette.frag");
+const BLOOM_EXTRACT_FRAG: &str = include_str!("../../assets/shaders/post/bloom_extract.frag");
+const BLUR_FRAG: &str = include_str!("../../assets/shaders/post/blur.frag");
+const BLOOM_COMPOSITE_FRAG: &str = include_str!("../../assets/shaders/post/bloom_composite.frag");
+
+/// a full screen shader program along with the locations of its uniforms
+struct Pass {
+    program: Program,
+    position_attrib: u32,
+    uniforms: HashMap<&'static str, WebGlUniformLocation>,
+}
+
+impl Pass {
+    fn new(gl: &WebGl, frag_source: &str, uniforms: &[&'static str]) -> Result<Pass, JsValue> {
+        let shaders = [
+            Shader::new(gl, WebGlCtx::VERTEX_SHADER, FULLSCREEN_VERT)?,
+            Shader::new(gl, WebGlCtx::FRAGMENT_SHADER, frag_source)?,
+        ];
+        let program = Program::new(gl, &shaders)?;
+        let position_attrib = gl.get_attrib_location(&program, "a_position");
+        if position_attrib == -1 {
+            return Err("position attribute doesn't exist".into());
+        }
+        // uniforms the compiler optimized away are left out rather than treated as errors
+        let uniforms = uniforms
+            .iter()
+            .filter_map(|&name| {
+                gl.get_uniform_location(&program, name)
+                    .map(|location| (name, location))
+            })
+            .collect();
+        Ok(Pass {
+            program,
+            position_attrib: position_attrib as u32,
+            uniforms,
+        })
+    }
+
+    fn uniform(&self, name: &str) -> Option<&WebGlUniformLocation> {
+        self.uniforms.get(name)
+    }
+}
+
+/// the shader programs for every effect
+struct Passes {
+    fxaa: Pass,
+    tone_mapping: Pass,
+    gamma: Pass,
+    vignette: Pass,
+    bloom_extract: Pass,
+    blur: Pass,
+    bloom_composite: Pass,
+}
+
+impl Passes {
+    fn new(gl: &WebGl) -> Result<Passes, JsValue> {
+        Ok(Passes {
+            fxaa: Pass::new(gl, FXAA_FRAG, &["u_source", "u_resolution"])?,
+            tone_mapping: Pass::new(gl, TONE_MAPPING_FRAG, &["u_source", "u_exposure"])?,
+            gamma: Pass::new(gl, GAMMA_FRAG, &["u_source", "u_gamma"])?,
+            vignette: Pass::new(gl, VIGNETTE_FRAG, &["u_source", "u_strength", "u_radius"])?,
+            bloom_extract: Pass::new(gl, BLOOM_EXTRACT_FRAG, &["u_source", "u_threshold"])?,
+            blur: Pass::new(gl, BLUR_FRAG, &["u_source", "u_resolution", "u_direction"])?,
+            bloom_composite: Pass::new(
+                gl,
+                BLOOM_COMPOSITE_FRAG,
+                &["u_source", "u_bloom", "u_intensity"],
+            )?,
+        })
+    }
+}
+
+/// the offscreen targets the scene and the effects render into, sized to the viewport
+struct Targets {
+    scene: Framebuffer,
+    ping_pong: [Framebuffer; 2],
+    /// half resolution targets for bloom's bright pass and blur
+    bloom: [Framebuffer; 2],
+}
+
+/// runs the post process chain. While active the scene is drawn into an offscreen target
+/// between `begin` and `end`, then every enabled effect is applied in order, ping-ponging
+/// between two targets, with the last one drawing to the canvas. Targets use half float
+/// textures when supported so tone mapping has values above 1 to work with
+pub struct PostProcessor {
+    gl: WebGl,
+    chain: PostChain,
+    passes: Passes,
+    triangle: GlBuffer<f32>,
+    targets: Option<Targets>,
+    size: (u32, u32),
+    texel_type: u32,
+    texel_filter: u32,
+}
+
+impl PostProcessor {
+    pub fn new(gl: &WebGl) -> Result<PostProcessor, JsValue> {
+        let capabilities = gl.capabilities();
+        let (texel_type, texel_filter) = if capabilities.half_float_targets {
+            let filter = if capabilities.half_float_linear {
+                WebGlCtx::LINEAR
+            } else {
+                WebGlCtx::NEAREST
+            };
+            (capabilities.half_float_type(), filter)
+        } else {
+            (WebGlCtx::UNSIGNED_BYTE, WebGlCtx::LINEAR)
+        };
+        // a single triangle covering the whole screen, so there's no seam down a diagonal
+        let triangle = GlBuffer::new(
+            gl,
+            WebGlCtx::ARRAY_BUFFER,
+            vec![-1.0, -1.0, 3.0, -1.0, -1.0, 3.0],
+        )?;
+        Ok(PostProcessor {
+            gl: gl.clone(),
+            chain: PostChain::new(),
+            passes: Passes::new(gl)?,
+            triangle,
+            targets: None,
+            size: (0, 0),
+            texel_type,
+            texel_filter,
+        })
+    }
+
+    pub fn chain(&self) -> &PostChain {
+        &self.chain
+    }
+
+    pub fn chain_mut(&mut self) -> &mut PostChain {
+        &mut self.chain
+    }
+
+    /// whether any effect is enabled, so the scene needs to go through `begin` and `end`
+    pub fn is_active(&self) -> bool {
+        self.chain.is_active()
+    }
+
+    /// recreate the effect programs and targets after the context was lost and restored,
+    /// keeping the chain's settings
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        self.passes = Passes::new(&self.gl)?;
+        self.triangle.restore()?;
+        if self.targets.take().is_some() {
+            let (width, height) = self.size;
+            self.resize(width, height)?;
+        }
+        Ok(())
+    }
+
+    /// make sure the targets match the viewport size, recreating them if it changed
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), JsValue> {
+        if self.targets.is_some() && self.size == (width, height) {
+            return Ok(());
+        }
+        let (width, height) = (width.max(1), height.max(1));
+        let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+        let targets = match self.create_targets(width, height, half_width, half_height) {
+            Ok(targets) => targets,
+            Err(_) if self.texel_type != WebGlCtx::UNSIGNED_BYTE => {
+                // half float color attachments aren't renderable everywhere
+                self.texel_type = WebGlCtx::UNSIGNED_BYTE;
+                self.texel_filter = WebGlCtx::LINEAR;
+                self.create_targets(width, height, half_width, half_height)?
+            }
+            Err(err) => return Err(err),
+        };
+        self.targets = Some(targets);
+        self.size = (width, height);
+        Ok(())
+    }
+
+    fn create_targets(
+        &self,
+        width: u32,
+        height: u32,
+        half_width: u32,
+        half_height: u32,
+    ) -> Result<Targets, JsValue> {
+        Ok(Targets {
+            scene: self.color_target(width, height, true)?,
+            ping_pong: [
+                self.color_target(width, height, false)?,
+                self.color_target(width, height, false)?,
+            ],
+            bloom: [
+                self.color_target(half_width, half_height, false)?,
+                self.color_target(half_width, half_height, false)?,
+            ],
+        })
+    }
+
+    fn color_target(&self, width: u32, height: u32, depth: bool) -> Result<Framebuffer, JsValue> {
+        let texture = Texture2D::empty(&self.gl, width, height, WebGlCtx::RGBA, self.texel_type)?;
+        texture.set_filter(self.texel_filter, self.texel_filter);
+        let mut framebuffer = Framebuffer::new(&self.gl, width, height)?;
+        framebuffer.attach_color_texture(texture);
+        if depth {
+            framebuffer.attach_depth_renderbuffer(Renderbuffer::new(
+                &self.gl,
+                WebGlCtx::DEPTH_COMPONENT16,
+                width,
+                height,
+            )?);
+        }
+        framebuffer.check_status()?;
+        Ok(framebuffer)
+    }
+
+    /// redirect drawing into the scene target
+    pub fn begin(&self) {
+        if let Some(ref targets) = self.targets {
+            targets.scene.bind();
+        }
+    }
+
+    /// apply every enabled effect to the scene target, drawing the result to `output` or to the
+    /// canvas if there is none
+    pub fn end(&self, state: &mut RenderStateTracker, output: Option<&Framebuffer>) {
+        let targets = match self.targets {
+            Some(ref targets) => targets,
+            None => return,
+        };
+        targets.scene.unbind();
+        state.apply(
+            &self.gl,
+            &RenderState::default()
+                .with_depth_test(false)
+                .with_cull_mode(CullMode::None),
+        );
+
+        let effects: Vec<&EffectSettings> = self.chain.enabled_effects().collect();
+        let mut source = color_texture(&targets.scene);
+        for (i, settings) in effects.iter().enumerate() {
+            if i + 1 == effects.len() {
+                self.apply(settings, source, output, targets);
+            } else {
+                let pass_output = &targets.ping_pong[i % 2];
+                self.apply(settings, source, Some(pass_output), targets);
+                source = color_texture(pass_output);
+            }
+        }
+
+        self.gl.active_texture(WebGlCtx::TEXTURE0);
+    }
+
+    fn apply(
+        &self,
+        settings: &EffectSettings,
+        source: &Texture2D,
+        output: Option<&Framebuffer>,
+        targets: &Targets,
+    ) {
+        let passes = &self.passes;
+        let (width, height) = self.size;
+        match settings.effect() {
+            PostEffect::Fxaa => self.run(&passes.fxaa, source, output, |gl, pass| {
+                gl.uniform2f(pass.uniform("u_resolution"), width as f32, height as f32);
+            }),
+            PostEffect::ToneMapping => {
+                self.run(&passes.tone_mapping, source, output, |gl, pass| {
+                    gl.uniform1f(pass.uniform("u_exposure"), settings.param("exposure"));
+                })
+            }
+            PostEffect::Gamma => self.run(&passes.gamma, source, output, |gl, pass| {
+                gl.uniform1f(pass.uniform("u_gamma"), settings.param("gamma"));
+            }),
+            PostEffect::Vignette => self.run(&passes.vignette, source, output, |gl, pass| {
+                gl.uniform1f(pass.uniform("u_strength"), settings.param("strength"));
+                gl.uniform1f(pass.uniform("u_radius"), settings.param("radius"));
+            }),
+            PostEffect::Bloom => {
+                let [ref bright, ref blurred] = targets.bloom;
+                let (half_width, half_height) = bright.size();
+                let radius = settings.param("radius");
+                self.run(&passes.bloom_extract, source, Some(bright), |gl, pass| {
+                    gl.uniform1f(pass.uniform("u_threshold"), settings.param("threshold"));
+                });
+                for &(blur_source, blur_output, direction) in &[
+                    (bright, blurred, (radius, 0.0)),
+                    (blurred, bright, (0.0, radius)),
+                ] {
+                    self.run(
+                        &passes.blur,
+                        color_texture(blur_source),
+                        Some(blur_output),
+                        |gl, pass| {
+                            gl.uniform2f(
+                                pass.uniform("u_resolution"),
+                                half_width as f32,
+                                half_height as f32,
+                            );
+                            gl.uniform2f(pass.uniform("u_direction"), direction.0, direction.1);
+                        },
+                    );
+                }
+                self.run(&passes.bloom_composite, source, output, |gl, pass| {
+                    gl.active_texture(WebGlCtx::TEXTURE1);
+                    color_texture(bright).bind();
+                    gl.uniform1i(pass.uniform("u_bloom"), 1);
+                    gl.uniform1f(pass.uniform("u_intensity"), settings.param("intensity"));
+                });
+            }
+        }
+    }
+
+    /// draw the full screen triangle with `pass`, sampling `source` into `output` or the canvas
+    fn run<F>(&self, pass: &Pass, source: &Texture2D, output: Option<&Framebuffer>, set_uniforms: F)
+    where
+        F: FnOnce(&WebGl, &Pass),
+    {
+        let (width, height) = match output {
+            Some(framebuffer) => {
+                framebuffer.bind();
+                framebuffer.size()
+            }
+            None => {
+                self.gl.bind_framebuffer(WebGlCtx::FRAMEBUFFER, None);
+                self.size
+            }
+        };
+        self.gl.viewport(0, 0, width as i32, height as i32);
+        pass.program.set_used();
+
+        self.gl.active_texture(WebGlCtx::TEXTURE0);
+        source.bind();
+        self.gl.uniform1i(pass.uniform("u_source"), 0);
+        set_uniforms(&self.gl, pass);
+
+        self.triangle.bind();
+        self.gl.vertex_attrib_pointer_with_i32(
+            pass.position_attrib,
+            2,
+            WebGlCtx::FLOAT,
+            false,
+            0,
+            0,
+        );
+        self.gl.enable_vertex_attrib_array(pass.position_attrib);
+        self.gl.draw_arrays(WebGlCtx::TRIANGLES, 0, 3);
+        self.gl.disable_vertex_attrib_array(pass.position_attrib);
+    }
+}
+
+fn color_texture(framebuffer: &Framebuffer) -> &Texture2D {
+    framebuffer
+        .color()
+        .and_then(Attachment::texture)
+        .expect("post process targets always have a color texture")
+}
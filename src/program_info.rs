@@ -1,5 +1,5 @@
-use web_sys::WebGlUniformLocation;
 use wasm_bindgen::prelude::*;
+use web_sys::WebGlUniformLocation;
 
 use crate::gl_abstraction::{Program, WebGl};
 
@@ -8,6 +8,9 @@ pub struct AttribLocs {
     pub position: u32,
     pub normal: u32,
     pub tex_coord: u32,
+    /// the first of the four locations of the instance transform's columns
+    pub instance_model: u32,
+    pub instance_color: u32,
 }
 
 impl AttribLocs {
@@ -24,32 +27,219 @@ impl AttribLocs {
         if tex_coord == -1 {
             return Err("tex_coord attribute doesn't exist".into());
         }
+        let instance_model = gl.get_attrib_location(program, "a_instance_model");
+        if instance_model == -1 {
+            return Err("instance_model attribute doesn't exist".into());
+        }
+        let instance_color = gl.get_attrib_location(program, "a_instance_color");
+        if instance_color == -1 {
+            return Err("instance_color attribute doesn't exist".into());
+        }
         Ok(AttribLocs {
             position: position as u32,
             normal: normal as u32,
             tex_coord: tex_coord as u32,
+            instance_model: instance_model as u32,
+            instance_color: instance_color as u32,
+        })
+    }
+}
+
+pub struct LightUniformLocs {
+    pub ambient: WebGlUniformLocation,
+    pub count: WebGlUniformLocation,
+    pub kind: WebGlUniformLocation,
+    pub color: WebGlUniformLocation,
+    pub position: WebGlUniformLocation,
+    pub direction: WebGlUniformLocation,
+    pub range: WebGlUniformLocation,
+    pub cone: WebGlUniformLocation,
+}
+
+impl LightUniformLocs {
+    pub fn new(gl: &WebGl, program: &Program) -> Result<LightUniformLocs, JsValue> {
+        Ok(LightUniformLocs {
+            ambient: gl
+                .get_uniform_location(program, "u_ambient_light")
+                .ok_or("ambient_light uniform doesn't exist")?,
+            count: gl
+                .get_uniform_location(program, "u_light_count")
+                .ok_or("light_count uniform doesn't exist")?,
+            kind: gl
+                .get_uniform_location(program, "u_light_kind")
+                .ok_or("light_kind uniform doesn't exist")?,
+            color: gl
+                .get_uniform_location(program, "u_light_color")
+                .ok_or("light_color uniform doesn't exist")?,
+            position: gl
+                .get_uniform_location(program, "u_light_position")
+                .ok_or("light_position uniform doesn't exist")?,
+            direction: gl
+                .get_uniform_location(program, "u_light_direction")
+                .ok_or("light_direction uniform doesn't exist")?,
+            range: gl
+                .get_uniform_location(program, "u_light_range")
+                .ok_or("light_range uniform doesn't exist")?,
+            cone: gl
+                .get_uniform_location(program, "u_light_cone")
+                .ok_or("light_cone uniform doesn't exist")?,
+        })
+    }
+}
+
+pub struct ShadowUniformLocs {
+    pub count: WebGlUniformLocation,
+    pub light: WebGlUniformLocation,
+    pub matrix: WebGlUniformLocation,
+    pub bias: WebGlUniformLocation,
+    pub map: WebGlUniformLocation,
+    pub map_size: WebGlUniformLocation,
+    pub pcf_radius: WebGlUniformLocation,
+    pub packed: WebGlUniformLocation,
+}
+
+impl ShadowUniformLocs {
+    pub fn new(gl: &WebGl, program: &Program) -> Result<ShadowUniformLocs, JsValue> {
+        Ok(ShadowUniformLocs {
+            count: gl
+                .get_uniform_location(program, "u_shadow_count")
+                .ok_or("shadow_count uniform doesn't exist")?,
+            light: gl
+                .get_uniform_location(program, "u_shadow_light")
+                .ok_or("shadow_light uniform doesn't exist")?,
+            matrix: gl
+                .get_uniform_location(program, "u_shadow_matrix")
+                .ok_or("shadow_matrix uniform doesn't exist")?,
+            bias: gl
+                .get_uniform_location(program, "u_shadow_bias")
+                .ok_or("shadow_bias uniform doesn't exist")?,
+            map: gl
+                .get_uniform_location(program, "u_shadow_map")
+                .ok_or("shadow_map uniform doesn't exist")?,
+            map_size: gl
+                .get_uniform_location(program, "u_shadow_map_size")
+                .ok_or("shadow_map_size uniform doesn't exist")?,
+            pcf_radius: gl
+                .get_uniform_location(program, "u_shadow_pcf_radius")
+                .ok_or("shadow_pcf_radius uniform doesn't exist")?,
+            packed: gl
+                .get_uniform_location(program, "u_shadow_packed")
+                .ok_or("shadow_packed uniform doesn't exist")?,
+        })
+    }
+}
+
+pub struct MaterialUniformLocs {
+    pub base_color: WebGlUniformLocation,
+    pub metallic: WebGlUniformLocation,
+    pub roughness: WebGlUniformLocation,
+    pub alpha_mode: WebGlUniformLocation,
+    pub alpha_cutoff: WebGlUniformLocation,
+}
+
+impl MaterialUniformLocs {
+    pub fn new(gl: &WebGl, program: &Program) -> Result<MaterialUniformLocs, JsValue> {
+        Ok(MaterialUniformLocs {
+            base_color: gl
+                .get_uniform_location(program, "u_base_color")
+                .ok_or("base_color uniform doesn't exist")?,
+            metallic: gl
+                .get_uniform_location(program, "u_metallic")
+                .ok_or("metallic uniform doesn't exist")?,
+            roughness: gl
+                .get_uniform_location(program, "u_roughness")
+                .ok_or("roughness uniform doesn't exist")?,
+            alpha_mode: gl
+                .get_uniform_location(program, "u_alpha_mode")
+                .ok_or("alpha_mode uniform doesn't exist")?,
+            alpha_cutoff: gl
+                .get_uniform_location(program, "u_alpha_cutoff")
+                .ok_or("alpha_cutoff uniform doesn't exist")?,
+        })
+    }
+}
+
+pub struct IblUniformLocs {
+    pub enabled: WebGlUniformLocation,
+    pub intensity: WebGlUniformLocation,
+    pub irradiance_sh: WebGlUniformLocation,
+    pub specular: WebGlUniformLocation,
+    pub max_lod: WebGlUniformLocation,
+    pub rgbm: WebGlUniformLocation,
+    pub brdf_lut: WebGlUniformLocation,
+    pub view_to_world: WebGlUniformLocation,
+}
+
+impl IblUniformLocs {
+    pub fn new(gl: &WebGl, program: &Program) -> Result<IblUniformLocs, JsValue> {
+        Ok(IblUniformLocs {
+            enabled: gl
+                .get_uniform_location(program, "u_ibl_enabled")
+                .ok_or("ibl_enabled uniform doesn't exist")?,
+            intensity: gl
+                .get_uniform_location(program, "u_ibl_intensity")
+                .ok_or("ibl_intensity uniform doesn't exist")?,
+            irradiance_sh: gl
+                .get_uniform_location(program, "u_irradiance_sh")
+                .ok_or("irradiance_sh uniform doesn't exist")?,
+            specular: gl
+                .get_uniform_location(program, "u_specular_env")
+                .ok_or("specular_env uniform doesn't exist")?,
+            max_lod: gl
+                .get_uniform_location(program, "u_specular_max_lod")
+                .ok_or("specular_max_lod uniform doesn't exist")?,
+            rgbm: gl
+                .get_uniform_location(program, "u_specular_rgbm")
+                .ok_or("specular_rgbm uniform doesn't exist")?,
+            brdf_lut: gl
+                .get_uniform_location(program, "u_brdf_lut")
+                .ok_or("brdf_lut uniform doesn't exist")?,
+            view_to_world: gl
+                .get_uniform_location(program, "u_view_to_world")
+                .ok_or("view_to_world uniform doesn't exist")?,
         })
     }
 }
 
 pub struct UniformLocs {
+    pub model: WebGlUniformLocation,
+    pub model_view: WebGlUniformLocation,
     pub model_view_projection: WebGlUniformLocation,
     pub normal_matrix: WebGlUniformLocation,
     pub sampler: WebGlUniformLocation,
+    /// whether the shader has to decode the sampler's sRGB colors itself
+    pub decode_srgb: WebGlUniformLocation,
+    pub lights: LightUniformLocs,
+    pub shadows: ShadowUniformLocs,
+    pub material: MaterialUniformLocs,
+    pub ibl: IblUniformLocs,
 }
 
 impl UniformLocs {
     pub fn new(gl: &WebGl, program: &Program) -> Result<UniformLocs, JsValue> {
         Ok(UniformLocs {
+            model: gl
+                .get_uniform_location(program, "u_model")
+                .ok_or("model uniform doesn't exist")?,
+            model_view: gl
+                .get_uniform_location(program, "u_model_view")
+                .ok_or("model_view uniform doesn't exist")?,
             model_view_projection: gl
                 .get_uniform_location(program, "u_model_view_projection")
-                .ok_or_else(|| "model_view_projection uniform doesn't exist")?,
+                .ok_or("model_view_projection uniform doesn't exist")?,
             normal_matrix: gl
                 .get_uniform_location(program, "u_normal_matrix")
-                .ok_or_else(|| "normal_matrix uniform doesn't exist")?,
+                .ok_or("normal_matrix uniform doesn't exist")?,
             sampler: gl
                 .get_uniform_location(program, "u_sampler")
-                .ok_or_else(|| "sampler uniform doesn't exist")?,
+                .ok_or("sampler uniform doesn't exist")?,
+            decode_srgb: gl
+                .get_uniform_location(program, "u_decode_srgb")
+                .ok_or("decode_srgb uniform doesn't exist")?,
+            lights: LightUniformLocs::new(gl, program)?,
+            shadows: ShadowUniformLocs::new(gl, program)?,
+            material: MaterialUniformLocs::new(gl, program)?,
+            ibl: IblUniformLocs::new(gl, program)?,
         })
     }
 }
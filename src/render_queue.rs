@@ -0,0 +1,244 @@
+use std::cmp::Ordering;
+
+use wasm_bindgen::prelude::*;
+
+use crate::material::AlphaMode;
+use crate::resources::MeshHandle;
+
+/// the passes a frame's primitives are drawn in, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RenderPass {
+    Opaque,
+    Mask,
+    Blend,
+}
+
+impl From<AlphaMode> for RenderPass {
+    fn from(alpha_mode: AlphaMode) -> RenderPass {
+        match alpha_mode {
+            AlphaMode::Opaque => RenderPass::Opaque,
+            AlphaMode::Mask => RenderPass::Mask,
+            AlphaMode::Blend => RenderPass::Blend,
+        }
+    }
+}
+
+/// what a draw is sorted by. Opaque and masked draws are grouped by program, material and
+/// texture to minimize switches and then go front to back, while blended draws only go back to
+/// front so each blends over everything behind it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SortKey {
+    pub pass: RenderPass,
+    pub program: u32,
+    pub material: u32,
+    /// the bits of the texture's handle
+    pub texture: u64,
+    /// the distance in front of the camera
+    pub depth: f32,
+}
+
+impl SortKey {
+    pub fn compare(&self, other: &SortKey) -> Ordering {
+        let depth = self
+            .depth
+            .partial_cmp(&other.depth)
+            .unwrap_or(Ordering::Equal);
+        let state = (self.program, self.material, self.texture).cmp(&(
+            other.program,
+            other.material,
+            other.texture,
+        ));
+        self.pass.cmp(&other.pass).then(match self.pass {
+            RenderPass::Blend => depth.reverse().then(state),
+            _ => state.then(depth),
+        })
+    }
+}
+
+/// a primitive waiting to be drawn this frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawItem {
+    pub key: SortKey,
+    pub mesh: MeshHandle,
+    /// the index of the primitive in its mesh
+    pub primitive: usize,
+}
+
+/// how much state changes the sorted queue needs, readable from js after a frame
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub opaque_draws: u32,
+    pub mask_draws: u32,
+    pub blend_draws: u32,
+    pub program_switches: u32,
+    pub material_switches: u32,
+    pub texture_switches: u32,
+}
+
+/// the draws submitted for a frame, executed in sort key order in a single pass
+#[derive(Debug, Default)]
+pub struct RenderQueue {
+    items: Vec<DrawItem>,
+}
+
+impl RenderQueue {
+    pub fn new() -> RenderQueue {
+        RenderQueue::default()
+    }
+
+    /// empty the queue, keeping its allocation for the next frame
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    pub fn push(&mut self, item: DrawItem) {
+        self.items.push(item);
+    }
+
+    pub fn sort(&mut self) {
+        self.items.sort_by(|a, b| a.key.compare(&b.key));
+    }
+
+    pub fn items(&self) -> &[DrawItem] {
+        &self.items
+    }
+
+    /// the items of a single pass
+    pub fn pass(&self, pass: RenderPass) -> impl Iterator<Item = &DrawItem> {
+        self.items.iter().filter(move |item| item.key.pass == pass)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// count the draws and state switches needed to execute the queue in its current order,
+    /// where each item takes `draws_per_item` draw calls, more than one when instances are
+    /// drawn one at a time
+    pub fn stats(&self, draws_per_item: u32) -> RenderStats {
+        let mut stats = RenderStats::default();
+        let mut previous: Option<&SortKey> = None;
+        for DrawItem { key, .. } in &self.items {
+            stats.draw_calls += draws_per_item;
+            match key.pass {
+                RenderPass::Opaque => stats.opaque_draws += draws_per_item,
+                RenderPass::Mask => stats.mask_draws += draws_per_item,
+                RenderPass::Blend => stats.blend_draws += draws_per_item,
+            }
+            let switched = |field: fn(&SortKey) -> u64| match previous {
+                Some(previous) => field(previous) != field(key),
+                None => true,
+            };
+            stats.program_switches += switched(|key| key.program.into()) as u32;
+            stats.material_switches += switched(|key| key.material.into()) as u32;
+            stats.texture_switches += switched(|key| key.texture) as u32;
+            previous = Some(key);
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::Handle;
+
+    fn key(pass: RenderPass, state: (u32, u32, u64), depth: f32) -> SortKey {
+        let (program, material, texture) = state;
+        SortKey {
+            pass,
+            program,
+            material,
+            texture,
+            depth,
+        }
+    }
+
+    fn queue(keys: &[SortKey]) -> RenderQueue {
+        let mut queue = RenderQueue::new();
+        for (primitive, &key) in keys.iter().enumerate() {
+            queue.push(DrawItem {
+                key,
+                mesh: MeshHandle::new(0, 0),
+                primitive,
+            });
+        }
+        queue
+    }
+
+    /// the order the queue draws its items in, by the order they were pushed
+    fn sorted(keys: &[SortKey]) -> Vec<usize> {
+        let mut queue = queue(keys);
+        queue.sort();
+        queue.items().iter().map(|item| item.primitive).collect()
+    }
+
+    #[test]
+    fn sorts_by_pass_first() {
+        let keys = [
+            key(RenderPass::Blend, (0, 0, 0), 1.0),
+            key(RenderPass::Mask, (0, 0, 0), 1.0),
+            key(RenderPass::Opaque, (9, 9, 9), 9.0),
+        ];
+        assert_eq!(sorted(&keys), [2, 1, 0]);
+    }
+
+    #[test]
+    fn groups_opaque_draws_by_state_then_front_to_back() {
+        let keys = [
+            key(RenderPass::Opaque, (1, 0, 0), 1.0),
+            key(RenderPass::Opaque, (0, 1, 0), 2.0),
+            key(RenderPass::Opaque, (0, 0, 1), 5.0),
+            key(RenderPass::Opaque, (0, 0, 1), 3.0),
+            key(RenderPass::Opaque, (0, 0, 0), 4.0),
+        ];
+        assert_eq!(sorted(&keys), [4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn draws_blended_items_back_to_front() {
+        let keys = [
+            key(RenderPass::Blend, (0, 0, 0), 1.0),
+            key(RenderPass::Blend, (1, 1, 1), 3.0),
+            key(RenderPass::Blend, (0, 0, 0), 2.0),
+            key(RenderPass::Blend, (1, 0, 0), 2.0),
+        ];
+        assert_eq!(sorted(&keys), [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn counts_draws_and_switches() {
+        let mut queue = queue(&[
+            key(RenderPass::Blend, (0, 1, 1), 1.0),
+            key(RenderPass::Opaque, (0, 0, 0), 1.0),
+            key(RenderPass::Opaque, (0, 0, 1), 2.0),
+            key(RenderPass::Mask, (1, 0, 1), 1.0),
+            key(RenderPass::Opaque, (0, 0, 0), 3.0),
+        ]);
+        queue.sort();
+        let stats = queue.stats(2);
+        assert_eq!(
+            stats,
+            RenderStats {
+                draw_calls: 10,
+                opaque_draws: 6,
+                mask_draws: 2,
+                blend_draws: 2,
+                program_switches: 3,
+                material_switches: 2,
+                texture_switches: 2,
+            }
+        );
+        assert_eq!(queue.pass(RenderPass::Opaque).count(), 3);
+
+        queue.clear();
+        assert!(queue.is_empty());
+        assert_eq!(queue.stats(1), RenderStats::default());
+    }
+}
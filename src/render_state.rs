@@ -0,0 +1,433 @@
+use wasm_bindgen::prelude::*;
+use web_sys::WebGlRenderingContext as WebGlCtx;
+
+use crate::gl_abstraction::WebGl;
+use crate::material::{AlphaMode, Material};
+
+/// the comparison a fragment's depth has to pass against the depth buffer to be drawn
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthFunc {
+    Never,
+    Less,
+    Equal,
+    LessOrEqual,
+    Greater,
+    NotEqual,
+    GreaterOrEqual,
+    Always,
+}
+
+impl DepthFunc {
+    pub fn gl_enum(self) -> u32 {
+        match self {
+            DepthFunc::Never => WebGlCtx::NEVER,
+            DepthFunc::Less => WebGlCtx::LESS,
+            DepthFunc::Equal => WebGlCtx::EQUAL,
+            DepthFunc::LessOrEqual => WebGlCtx::LEQUAL,
+            DepthFunc::Greater => WebGlCtx::GREATER,
+            DepthFunc::NotEqual => WebGlCtx::NOTEQUAL,
+            DepthFunc::GreaterOrEqual => WebGlCtx::GEQUAL,
+            DepthFunc::Always => WebGlCtx::ALWAYS,
+        }
+    }
+}
+
+/// which faces, by counter-clockwise winding, are skipped
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+    FrontAndBack,
+}
+
+/// how fragments are combined with what is already in the color buffer
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// overwrite the destination
+    Opaque,
+    /// mix by the source alpha
+    Alpha,
+    /// mix by the source alpha, for colors that have already been multiplied by it
+    PremultipliedAlpha,
+    /// add the source weighted by its alpha
+    Additive,
+    /// multiply the destination by the source
+    Multiply,
+}
+
+impl BlendMode {
+    /// the source and destination blend factors, or `None` if blending is disabled
+    pub fn factors(self) -> Option<(u32, u32)> {
+        match self {
+            BlendMode::Opaque => None,
+            BlendMode::Alpha => Some((WebGlCtx::SRC_ALPHA, WebGlCtx::ONE_MINUS_SRC_ALPHA)),
+            BlendMode::PremultipliedAlpha => Some((WebGlCtx::ONE, WebGlCtx::ONE_MINUS_SRC_ALPHA)),
+            BlendMode::Additive => Some((WebGlCtx::SRC_ALPHA, WebGlCtx::ONE)),
+            BlendMode::Multiply => Some((WebGlCtx::DST_COLOR, WebGlCtx::ZERO)),
+        }
+    }
+}
+
+/// the fixed function state used to clear and draw: clear values, depth testing, face culling,
+/// blending, polygon offset and color masks
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderState {
+    clear_color: [f32; 4],
+    clear_depth: f32,
+    depth_test: bool,
+    depth_write: bool,
+    depth_func: DepthFunc,
+    cull_mode: CullMode,
+    blend_mode: BlendMode,
+    polygon_offset: Option<(f32, f32)>,
+    color_mask: [bool; 4],
+}
+
+#[wasm_bindgen]
+impl RenderState {
+    /// opaque black clears, depth tested against LEQUAL with writes and back faces culled
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> RenderState {
+        RenderState::default()
+    }
+
+    pub fn with_clear_color(mut self, r: f32, g: f32, b: f32, a: f32) -> RenderState {
+        self.clear_color = [r, g, b, a];
+        self
+    }
+
+    pub fn with_clear_depth(mut self, depth: f32) -> RenderState {
+        self.clear_depth = depth;
+        self
+    }
+
+    pub fn with_depth_test(mut self, enabled: bool) -> RenderState {
+        self.depth_test = enabled;
+        self
+    }
+
+    /// whether drawing writes depth. Clearing the depth buffer needs this too
+    pub fn with_depth_write(mut self, enabled: bool) -> RenderState {
+        self.depth_write = enabled;
+        self
+    }
+
+    pub fn with_depth_func(mut self, depth_func: DepthFunc) -> RenderState {
+        self.depth_func = depth_func;
+        self
+    }
+
+    pub fn with_cull_mode(mut self, cull_mode: CullMode) -> RenderState {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> RenderState {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    /// offset depth by `factor` times the polygon's depth slope plus `units` times the smallest
+    /// resolvable depth difference
+    pub fn with_polygon_offset(mut self, factor: f32, units: f32) -> RenderState {
+        self.polygon_offset = Some((factor, units));
+        self
+    }
+
+    pub fn without_polygon_offset(mut self) -> RenderState {
+        self.polygon_offset = None;
+        self
+    }
+
+    pub fn with_color_mask(mut self, r: bool, g: bool, b: bool, a: bool) -> RenderState {
+        self.color_mask = [r, g, b, a];
+        self
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn clear_depth(&self) -> f32 {
+        self.clear_depth
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn depth_test(&self) -> bool {
+        self.depth_test
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn depth_write(&self) -> bool {
+        self.depth_write
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn depth_func(&self) -> DepthFunc {
+        self.depth_func
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn cull_mode(&self) -> CullMode {
+        self.cull_mode
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+}
+
+impl RenderState {
+    pub fn clear_color(&self) -> [f32; 4] {
+        self.clear_color
+    }
+
+    pub fn polygon_offset(&self) -> Option<(f32, f32)> {
+        self.polygon_offset
+    }
+
+    pub fn color_mask(&self) -> [bool; 4] {
+        self.color_mask
+    }
+
+    /// this state with the overrides a material asks for, like drawing both sides of
+    /// double-sided materials, or blending without writing depth for transparent ones
+    pub fn for_material(&self, material: &Material) -> RenderState {
+        let mut state = self.clone();
+        if material.double_sided {
+            state.cull_mode = CullMode::None;
+        }
+        if material.alpha_mode == AlphaMode::Blend {
+            state.blend_mode = BlendMode::Alpha;
+            state.depth_write = false;
+        }
+        state
+    }
+}
+
+impl Default for RenderState {
+    fn default() -> RenderState {
+        RenderState {
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            clear_depth: 1.0,
+            depth_test: true,
+            depth_write: true,
+            depth_func: DepthFunc::LessOrEqual,
+            cull_mode: CullMode::Back,
+            blend_mode: BlendMode::Opaque,
+            polygon_offset: None,
+            color_mask: [true; 4],
+        }
+    }
+}
+
+/// remembers the last `RenderState` applied to a context so that applying another only makes
+/// the gl calls for what changed. Everything that changes this state has to go through the
+/// same tracker, or call `invalidate` afterwards
+#[derive(Debug, Default)]
+pub struct RenderStateTracker {
+    current: Option<RenderState>,
+}
+
+impl RenderStateTracker {
+    pub fn new() -> RenderStateTracker {
+        RenderStateTracker::default()
+    }
+
+    /// forget the current state, so the next `apply` sets everything
+    pub fn invalidate(&mut self) {
+        self.current = None;
+    }
+
+    pub fn current(&self) -> Option<&RenderState> {
+        self.current.as_ref()
+    }
+
+    /// the pieces of state that applying `state` has to set, everything if there's no
+    /// current state
+    fn changes<'a>(&'a self, state: &'a RenderState) -> impl Iterator<Item = StateChange> + 'a {
+        StateChange::ALL.iter().copied().filter(move |change| {
+            self.current
+                .as_ref()
+                .is_none_or(|current| change.differs(current, state))
+        })
+    }
+
+    pub fn apply(&mut self, gl: &WebGl, state: &RenderState) {
+        for change in self.changes(state) {
+            match change {
+                StateChange::ClearColor => {
+                    let [r, g, b, a] = state.clear_color;
+                    gl.clear_color(r, g, b, a);
+                }
+                StateChange::ClearDepth => gl.clear_depth(state.clear_depth),
+                StateChange::DepthTest => {
+                    set_enabled(gl, WebGlCtx::DEPTH_TEST, state.depth_test);
+                }
+                StateChange::DepthWrite => gl.depth_mask(state.depth_write),
+                StateChange::DepthFunc => gl.depth_func(state.depth_func.gl_enum()),
+                StateChange::CullMode => {
+                    let cull_face = match state.cull_mode {
+                        CullMode::None => None,
+                        CullMode::Back => Some(WebGlCtx::BACK),
+                        CullMode::Front => Some(WebGlCtx::FRONT),
+                        CullMode::FrontAndBack => Some(WebGlCtx::FRONT_AND_BACK),
+                    };
+                    set_enabled(gl, WebGlCtx::CULL_FACE, cull_face.is_some());
+                    if let Some(cull_face) = cull_face {
+                        gl.cull_face(cull_face);
+                    }
+                }
+                StateChange::BlendMode => {
+                    let factors = state.blend_mode.factors();
+                    set_enabled(gl, WebGlCtx::BLEND, factors.is_some());
+                    if let Some((source, destination)) = factors {
+                        gl.blend_func(source, destination);
+                    }
+                }
+                StateChange::PolygonOffset => {
+                    set_enabled(
+                        gl,
+                        WebGlCtx::POLYGON_OFFSET_FILL,
+                        state.polygon_offset.is_some(),
+                    );
+                    if let Some((factor, units)) = state.polygon_offset {
+                        gl.polygon_offset(factor, units);
+                    }
+                }
+                StateChange::ColorMask => {
+                    let [r, g, b, a] = state.color_mask;
+                    gl.color_mask(r, g, b, a);
+                }
+            }
+        }
+
+        self.current = Some(state.clone());
+    }
+}
+
+/// a piece of `RenderState` that the tracker sets on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StateChange {
+    ClearColor,
+    ClearDepth,
+    DepthTest,
+    DepthWrite,
+    DepthFunc,
+    CullMode,
+    BlendMode,
+    PolygonOffset,
+    ColorMask,
+}
+
+impl StateChange {
+    const ALL: [StateChange; 9] = [
+        StateChange::ClearColor,
+        StateChange::ClearDepth,
+        StateChange::DepthTest,
+        StateChange::DepthWrite,
+        StateChange::DepthFunc,
+        StateChange::CullMode,
+        StateChange::BlendMode,
+        StateChange::PolygonOffset,
+        StateChange::ColorMask,
+    ];
+
+    /// whether `a` and `b` differ in this piece
+    fn differs(self, a: &RenderState, b: &RenderState) -> bool {
+        match self {
+            StateChange::ClearColor => a.clear_color != b.clear_color,
+            StateChange::ClearDepth => a.clear_depth != b.clear_depth,
+            StateChange::DepthTest => a.depth_test != b.depth_test,
+            StateChange::DepthWrite => a.depth_write != b.depth_write,
+            StateChange::DepthFunc => a.depth_func != b.depth_func,
+            StateChange::CullMode => a.cull_mode != b.cull_mode,
+            StateChange::BlendMode => a.blend_mode != b.blend_mode,
+            StateChange::PolygonOffset => a.polygon_offset != b.polygon_offset,
+            StateChange::ColorMask => a.color_mask != b.color_mask,
+        }
+    }
+}
+
+fn set_enabled(gl: &WebGl, capability: u32, enabled: bool) {
+    if enabled {
+        gl.enable(capability);
+    } else {
+        gl.disable(capability);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a tracker that last applied `state`
+    fn tracking(state: RenderState) -> RenderStateTracker {
+        RenderStateTracker {
+            current: Some(state),
+        }
+    }
+
+    fn changes(tracker: &RenderStateTracker, state: &RenderState) -> Vec<StateChange> {
+        tracker.changes(state).collect()
+    }
+
+    #[test]
+    fn sets_everything_without_a_current_state() {
+        let mut tracker = RenderStateTracker::new();
+        let state = RenderState::new();
+        assert_eq!(changes(&tracker, &state), StateChange::ALL);
+        tracker = tracking(state.clone());
+        tracker.invalidate();
+        assert_eq!(changes(&tracker, &state), StateChange::ALL);
+    }
+
+    #[test]
+    fn skips_state_that_is_already_set() {
+        let state = RenderState::new().with_polygon_offset(1.0, 2.0);
+        let tracker = tracking(state.clone());
+        assert!(changes(&tracker, &state).is_empty());
+        assert!(changes(&tracker, &state.clone().with_polygon_offset(1.0, 2.0)).is_empty());
+    }
+
+    #[test]
+    fn sets_only_what_changed() {
+        let tracker = tracking(RenderState::new());
+        let blended = RenderState::new().with_blend_mode(BlendMode::Additive);
+        assert_eq!(changes(&tracker, &blended), [StateChange::BlendMode]);
+        let masked = RenderState::new()
+            .with_clear_color(1.0, 0.0, 0.0, 1.0)
+            .with_color_mask(true, true, true, false)
+            .without_polygon_offset();
+        assert_eq!(
+            changes(&tracker, &masked),
+            [StateChange::ClearColor, StateChange::ColorMask]
+        );
+    }
+
+    #[test]
+    fn materials_override_culling_and_blending() {
+        let base = RenderState::new();
+        let material = Material {
+            double_sided: true,
+            alpha_mode: AlphaMode::Blend,
+            ..Material::default()
+        };
+        let state = base.for_material(&material);
+        assert_eq!(state.cull_mode(), CullMode::None);
+        assert_eq!(state.blend_mode(), BlendMode::Alpha);
+        assert!(!state.depth_write());
+        assert_eq!(
+            changes(&tracking(base.clone()), &state),
+            [
+                StateChange::DepthWrite,
+                StateChange::CullMode,
+                StateChange::BlendMode
+            ]
+        );
+        assert_eq!(base.for_material(&Material::default()), base);
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsValue;
+
+use crate::gl_abstraction::{Program, Texture2D};
+use crate::mesh::Mesh;
+
+/// a generational index into a `Pool`. A handle stops resolving once its resource is unloaded,
+/// even if the slot gets reused by another resource
+pub trait Handle: Copy {
+    fn new(index: u32, generation: u32) -> Self;
+    fn index(&self) -> u32;
+    fn generation(&self) -> u32;
+
+    /// the index and generation in one number, which differs between resources that were in
+    /// the same slot at different times
+    fn to_bits(&self) -> u64 {
+        ((self.generation() as u64) << 32) | self.index() as u64
+    }
+}
+
+macro_rules! handle {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        #[wasm_bindgen]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct $name {
+            index: u32,
+            generation: u32,
+        }
+
+        impl Handle for $name {
+            fn new(index: u32, generation: u32) -> $name {
+                $name { index, generation }
+            }
+
+            fn index(&self) -> u32 {
+                self.index
+            }
+
+            fn generation(&self) -> u32 {
+                self.generation
+            }
+        }
+    };
+}
+
+handle!(
+    /// a mesh loaded from a gltf file
+    MeshHandle
+);
+handle!(
+    /// a texture used by loaded meshes
+    TextureHandle
+);
+handle!(
+    /// a linked shader program
+    ProgramHandle
+);
+
+struct Entry<T> {
+    value: T,
+    name: Option<String>,
+    ref_count: u32,
+}
+
+struct Slot<T> {
+    generation: u32,
+    entry: Option<Entry<T>>,
+}
+
+/// resources of one type, addressed by handles of type `H`. Every resource is reference
+/// counted, starting at one for whoever inserted it, and can be found by its name if it has
+/// one
+pub struct Pool<H, T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    names: HashMap<String, H>,
+}
+
+impl<H: Handle, T> Pool<H, T> {
+    pub fn new() -> Pool<H, T> {
+        Pool {
+            slots: Vec::new(),
+            free: Vec::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// add a resource with a reference count of one, failing if `name` is already taken
+    pub fn insert(&mut self, name: Option<String>, value: T) -> Result<H, String> {
+        if let Some(ref name) = name {
+            if self.names.contains_key(name) {
+                return Err(format!("a resource named \"{}\" is already loaded", name));
+            }
+        }
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    entry: None,
+                });
+                self.slots.len() as u32 - 1
+            }
+        };
+        let slot = &mut self.slots[index as usize];
+        let handle = H::new(index, slot.generation);
+        if let Some(ref name) = name {
+            self.names.insert(name.clone(), handle);
+        }
+        slot.entry = Some(Entry {
+            value,
+            name,
+            ref_count: 1,
+        });
+        Ok(handle)
+    }
+
+    fn entry(&self, handle: H) -> Option<&Entry<T>> {
+        self.slots
+            .get(handle.index() as usize)
+            .filter(|slot| slot.generation == handle.generation())
+            .and_then(|slot| slot.entry.as_ref())
+    }
+
+    fn entry_mut(&mut self, handle: H) -> Option<&mut Entry<T>> {
+        self.slots
+            .get_mut(handle.index() as usize)
+            .filter(|slot| slot.generation == handle.generation())
+            .and_then(|slot| slot.entry.as_mut())
+    }
+
+    pub fn get(&self, handle: H) -> Option<&T> {
+        self.entry(handle).map(|entry| &entry.value)
+    }
+
+    pub fn get_mut(&mut self, handle: H) -> Option<&mut T> {
+        self.entry_mut(handle).map(|entry| &mut entry.value)
+    }
+
+    pub fn contains(&self, handle: H) -> bool {
+        self.entry(handle).is_some()
+    }
+
+    pub fn find(&self, name: &str) -> Option<H> {
+        self.names.get(name).copied()
+    }
+
+    pub fn name(&self, handle: H) -> Option<&str> {
+        self.entry(handle).and_then(|entry| entry.name.as_deref())
+    }
+
+    /// how many holders the resource has, 0 if the handle is stale
+    pub fn ref_count(&self, handle: H) -> u32 {
+        self.entry(handle).map_or(0, |entry| entry.ref_count)
+    }
+
+    /// add a holder to the resource, returning the new reference count
+    pub fn retain(&mut self, handle: H) -> Result<u32, String> {
+        let entry = self
+            .entry_mut(handle)
+            .ok_or("the resource has been unloaded")?;
+        entry.ref_count += 1;
+        Ok(entry.ref_count)
+    }
+
+    /// remove a holder from the resource, unloading and returning it when that was the last
+    pub fn release(&mut self, handle: H) -> Result<Option<T>, String> {
+        let entry = self
+            .entry_mut(handle)
+            .ok_or("the resource has been unloaded")?;
+        entry.ref_count -= 1;
+        if entry.ref_count > 0 {
+            return Ok(None);
+        }
+        Ok(self.remove(handle))
+    }
+
+    /// unload the resource no matter how many holders it has, invalidating every handle to it
+    pub fn remove(&mut self, handle: H) -> Option<T> {
+        let slot = self
+            .slots
+            .get_mut(handle.index() as usize)
+            .filter(|slot| slot.generation == handle.generation())?;
+        let entry = slot.entry.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index());
+        if let Some(ref name) = entry.name {
+            self.names.remove(name);
+        }
+        Some(entry.value)
+    }
+
+    /// unload every resource
+    pub fn clear(&mut self) {
+        let handles: Vec<H> = self.handles().collect();
+        for handle in handles {
+            self.remove(handle);
+        }
+    }
+
+    pub fn handles(&self) -> impl Iterator<Item = H> + '_ {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.entry
+                .as_ref()
+                .map(|_| H::new(index as u32, slot.generation))
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (H, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.entry
+                .as_ref()
+                .map(|entry| (H::new(index as u32, slot.generation), &entry.value))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (H, &mut T)> {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let generation = slot.generation;
+                slot.entry
+                    .as_mut()
+                    .map(|entry| (H::new(index as u32, generation), &mut entry.value))
+            })
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots
+            .iter_mut()
+            .filter_map(|slot| slot.entry.as_mut().map(|entry| &mut entry.value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<H: Handle, T> Default for Pool<H, T> {
+    fn default() -> Pool<H, T> {
+        Pool::new()
+    }
+}
+
+/// something that holds a reference to the textures it uses while it's loaded, like a mesh
+pub trait UsesTextures {
+    /// the textures used, each once
+    fn textures(&self) -> Vec<TextureHandle>;
+}
+
+impl UsesTextures for Mesh {
+    fn textures(&self) -> Vec<TextureHandle> {
+        Mesh::textures(self)
+    }
+}
+
+/// every mesh, texture and program loaded into a scene. Meshes hold a reference to each
+/// texture their materials use, so unloading a mesh unloads the textures only it used
+pub struct Resources<M = Mesh, T = Texture2D> {
+    pub meshes: Pool<MeshHandle, M>,
+    pub textures: Pool<TextureHandle, T>,
+    pub programs: Pool<ProgramHandle, Program>,
+}
+
+impl<M, T> Default for Resources<M, T> {
+    fn default() -> Resources<M, T> {
+        Resources {
+            meshes: Pool::new(),
+            textures: Pool::new(),
+            programs: Pool::new(),
+        }
+    }
+}
+
+impl Resources {
+    pub fn new() -> Resources {
+        Resources::default()
+    }
+
+    /// recreate every resource after the context was lost and restored
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        for mesh in self.meshes.values_mut() {
+            mesh.restore()?;
+        }
+        for texture in self.textures.values_mut() {
+            texture.restore()?;
+        }
+        for program in self.programs.values_mut() {
+            program.restore()?;
+        }
+        Ok(())
+    }
+}
+
+impl<M: UsesTextures, T> Resources<M, T> {
+    /// add a mesh, retaining the textures it uses
+    pub fn insert_mesh(&mut self, name: Option<String>, mesh: M) -> Result<MeshHandle, String> {
+        let textures = mesh.textures();
+        if !textures
+            .iter()
+            .all(|&texture| self.textures.contains(texture))
+        {
+            return Err("the mesh uses a texture that has been unloaded".into());
+        }
+        let handle = self.meshes.insert(name, mesh)?;
+        for texture in textures {
+            self.textures.retain(texture)?;
+        }
+        Ok(handle)
+    }
+
+    /// remove a holder from the mesh, unloading it if that was the last. Returns whether it
+    /// was unloaded
+    pub fn release_mesh(&mut self, handle: MeshHandle) -> Result<bool, String> {
+        match self.meshes.release(handle)? {
+            Some(mesh) => {
+                self.release_textures(&mesh);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// unload the mesh no matter how many holders it has, returning false if it already was
+    pub fn unload_mesh(&mut self, handle: MeshHandle) -> bool {
+        match self.meshes.remove(handle) {
+            Some(mesh) => {
+                self.release_textures(&mesh);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn release_textures(&mut self, mesh: &M) {
+        for texture in mesh.textures() {
+            // textures unloaded explicitly while in use are already gone
+            let _ = self.textures.release(texture);
+        }
+    }
+
+    /// unload every mesh and the textures they used
+    pub fn clear_meshes(&mut self) {
+        let handles: Vec<MeshHandle> = self.meshes.handles().collect();
+        for handle in handles {
+            self.unload_mesh(handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a mesh that's only the textures it uses
+    struct Model(Vec<TextureHandle>);
+
+    impl UsesTextures for Model {
+        fn textures(&self) -> Vec<TextureHandle> {
+            self.0.clone()
+        }
+    }
+
+    type Textures = Pool<TextureHandle, &'static str>;
+
+    #[test]
+    fn handles_stop_resolving_when_slots_are_reused() {
+        let mut pool = Textures::new();
+        let first = pool.insert(None, "first").unwrap();
+        assert_eq!(pool.remove(first), Some("first"));
+        let second = pool.insert(None, "second").unwrap();
+        assert_eq!(first.index(), second.index());
+        assert_ne!(first.to_bits(), second.to_bits());
+        assert!(!pool.contains(first));
+        assert_eq!(pool.get(first), None);
+        assert_eq!(pool.get(second), Some(&"second"));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn rejects_stale_handles() {
+        let mut pool = Textures::new();
+        let handle = pool.insert(None, "texture").unwrap();
+        pool.remove(handle);
+        assert_eq!(pool.ref_count(handle), 0);
+        assert!(pool.retain(handle).is_err());
+        assert!(pool.release(handle).is_err());
+        assert_eq!(pool.remove(handle), None);
+        assert!(pool.get_mut(handle).is_none());
+        let never_loaded = TextureHandle::new(5, 0);
+        assert!(!pool.contains(never_loaded));
+    }
+
+    #[test]
+    fn names_are_unique_until_unloaded() {
+        let mut pool = Textures::new();
+        let handle = pool.insert(Some("brick".into()), "brick").unwrap();
+        assert!(pool.insert(Some("brick".into()), "other brick").is_err());
+        assert_eq!(pool.find("brick"), Some(handle));
+        assert_eq!(pool.name(handle), Some("brick"));
+        assert_eq!(pool.len(), 1);
+        pool.remove(handle);
+        assert_eq!(pool.find("brick"), None);
+        let again = pool.insert(Some("brick".into()), "new brick").unwrap();
+        assert_eq!(pool.find("brick"), Some(again));
+    }
+
+    #[test]
+    fn unloads_on_the_last_release() {
+        let mut pool = Textures::new();
+        let handle = pool.insert(None, "texture").unwrap();
+        assert_eq!(pool.retain(handle), Ok(2));
+        assert_eq!(pool.ref_count(handle), 2);
+        assert_eq!(pool.release(handle), Ok(None));
+        assert_eq!(pool.ref_count(handle), 1);
+        assert_eq!(pool.release(handle), Ok(Some("texture")));
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn meshes_hold_their_textures() {
+        let mut resources = Resources::<Model, &str>::default();
+        let shared = resources.textures.insert(None, "shared").unwrap();
+        let own = resources.textures.insert(None, "own").unwrap();
+        let first = resources
+            .insert_mesh(None, Model(vec![shared, own]))
+            .unwrap();
+        let second = resources.insert_mesh(None, Model(vec![shared])).unwrap();
+        assert_eq!(resources.textures.ref_count(shared), 3);
+        // the loader lets go of its own references once meshes hold them
+        resources.textures.release(shared).unwrap();
+        resources.textures.release(own).unwrap();
+
+        assert!(resources.unload_mesh(first));
+        assert!(!resources.unload_mesh(first));
+        assert!(!resources.textures.contains(own));
+        assert_eq!(resources.textures.ref_count(shared), 1);
+
+        resources.meshes.retain(second).unwrap();
+        assert_eq!(resources.release_mesh(second), Ok(false));
+        assert!(resources.textures.contains(shared));
+        assert_eq!(resources.release_mesh(second), Ok(true));
+        assert!(resources.textures.is_empty());
+    }
+
+    #[test]
+    fn rejects_meshes_using_unloaded_textures() {
+        let mut resources = Resources::<Model, &str>::default();
+        let texture = resources.textures.insert(None, "texture").unwrap();
+        resources.textures.remove(texture);
+        assert!(resources.insert_mesh(None, Model(vec![texture])).is_err());
+        assert!(resources.meshes.is_empty());
+    }
+}
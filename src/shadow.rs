@@ -0,0 +1,356 @@
+use nalgebra as na;
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlRenderingContext as WebGlCtx, WebGlUniformLocation};
+
+use crate::gl_abstraction::{
+    Attachment, Framebuffer, Program, Renderbuffer, Shader, Texture2D, WebGl,
+};
+use crate::light::{Light, LightKind};
+use crate::material::AlphaMode;
+use crate::mesh::Primitive;
+use crate::render_state::{CullMode, RenderState, RenderStateTracker};
+use crate::resources::{Pool, TextureHandle};
+
+/// the most lights that can cast shadows at once. Must match `MAX_SHADOW_MAPS` in `tetra.frag`
+pub const MAX_SHADOW_MAPS: usize = 2;
+
+/// the largest pcf kernel radius in texels. Must match `MAX_PCF_RADIUS` in `tetra.frag`
+pub const MAX_PCF_RADIUS: i32 = 2;
+
+/// the texture unit of the first shadow map, the rest follow it
+pub const SHADOW_TEXTURE_UNIT: u32 = 1;
+
+const DEPTH_VERT: &str = include_str!("../assets/shaders/shadow_depth.vert");
+const DEPTH_FRAG: &str = include_str!("../assets/shaders/shadow_depth.frag");
+
+/// a square offscreen target that a light's depth gets rendered into. With depth texture support
+/// the depth buffer is the texture, otherwise depth is packed into an rgba texture by the depth
+/// shader and a renderbuffer provides the depth test
+pub struct ShadowMap {
+    framebuffer: Framebuffer,
+}
+
+impl ShadowMap {
+    pub fn new(gl: &WebGl, size: u32, depth_texture: bool) -> Result<ShadowMap, JsValue> {
+        let mut framebuffer = Framebuffer::new(gl, size, size)?;
+        if depth_texture {
+            framebuffer.attach_depth_texture(Texture2D::empty(
+                gl,
+                size,
+                size,
+                WebGlCtx::DEPTH_COMPONENT,
+                WebGlCtx::UNSIGNED_INT,
+            )?);
+        } else {
+            framebuffer.attach_color_texture(Texture2D::empty(
+                gl,
+                size,
+                size,
+                WebGlCtx::RGBA,
+                WebGlCtx::UNSIGNED_BYTE,
+            )?);
+            framebuffer.attach_depth_renderbuffer(Renderbuffer::new(
+                gl,
+                WebGlCtx::DEPTH_COMPONENT16,
+                size,
+                size,
+            )?);
+        }
+        framebuffer.check_status()?;
+        Ok(ShadowMap { framebuffer })
+    }
+
+    pub fn bind(&self) {
+        self.framebuffer.bind();
+    }
+
+    pub fn unbind(&self) {
+        self.framebuffer.unbind();
+    }
+
+    /// the texture holding the depth, whichever way it was stored
+    pub fn texture(&self) -> &Texture2D {
+        self.framebuffer
+            .depth()
+            .and_then(Attachment::texture)
+            .or_else(|| self.framebuffer.color().and_then(Attachment::texture))
+            .expect("shadow maps always have a texture attached")
+    }
+
+    /// bind the depth texture to texture unit `unit` for sampling
+    pub fn bind_texture(&self, gl: &WebGl, unit: u32) {
+        gl.active_texture(WebGlCtx::TEXTURE0 + unit);
+        self.texture().bind();
+    }
+}
+
+/// a light that is casting a shadow this frame
+#[derive(Debug, Clone)]
+pub struct ShadowCaster {
+    pub light_index: usize,
+    pub view_projection: na::Matrix4<f32>,
+    pub bias: f32,
+}
+
+/// renders the depth of the scene from each shadow casting light before the main pass
+pub struct ShadowRenderer {
+    gl: WebGl,
+    program: Program,
+    position_attrib: u32,
+    tex_coord_attrib: u32,
+    light_mvp_loc: WebGlUniformLocation,
+    sampler_loc: WebGlUniformLocation,
+    base_color_loc: WebGlUniformLocation,
+    alpha_cutoff_loc: WebGlUniformLocation,
+    /// bound for masked materials without a base color texture
+    white_texture: Texture2D,
+    maps: Vec<ShadowMap>,
+    map_size: u32,
+    pcf_radius: i32,
+    depth_texture: bool,
+}
+
+impl ShadowRenderer {
+    /// create the depth program and shadow maps, using depth textures when the context
+    /// supports them and falling back to rgba packed depth
+    pub fn new(gl: &WebGl, map_size: u32) -> Result<ShadowRenderer, JsValue> {
+        let mut depth_texture = gl.capabilities().depth_textures;
+        let maps = match create_maps(gl, map_size, depth_texture) {
+            Ok(maps) => maps,
+            Err(_) if depth_texture => {
+                depth_texture = false;
+                create_maps(gl, map_size, depth_texture)?
+            }
+            Err(err) => return Err(err),
+        };
+        let frag_source = if depth_texture {
+            DEPTH_FRAG.to_string()
+        } else {
+            format!("#define PACK_DEPTH\n{}", DEPTH_FRAG)
+        };
+        let shaders = [
+            Shader::new(gl, WebGlCtx::VERTEX_SHADER, DEPTH_VERT)?,
+            Shader::new(gl, WebGlCtx::FRAGMENT_SHADER, &frag_source)?,
+        ];
+        let program = Program::new(gl, &shaders)?;
+        let position_attrib = gl.get_attrib_location(&program, "a_position");
+        if position_attrib == -1 {
+            return Err("position attribute doesn't exist".into());
+        }
+        let tex_coord_attrib = gl.get_attrib_location(&program, "a_tex_coord");
+        if tex_coord_attrib == -1 {
+            return Err("tex coord attribute doesn't exist".into());
+        }
+        let uniform = |name: &str| {
+            gl.get_uniform_location(&program, name)
+                .ok_or_else(|| format!("{} uniform doesn't exist", name))
+        };
+        let light_mvp_loc = uniform("u_light_model_view_projection")?;
+        let sampler_loc = uniform("u_sampler")?;
+        let base_color_loc = uniform("u_base_color")?;
+        let alpha_cutoff_loc = uniform("u_alpha_cutoff")?;
+        Ok(ShadowRenderer {
+            gl: gl.clone(),
+            program,
+            position_attrib: position_attrib as u32,
+            tex_coord_attrib: tex_coord_attrib as u32,
+            light_mvp_loc,
+            sampler_loc,
+            base_color_loc,
+            alpha_cutoff_loc,
+            white_texture: Texture2D::from_pixels(gl, 1, 1, &[255; 4])?,
+            maps,
+            map_size,
+            pcf_radius: 1,
+            depth_texture,
+        })
+    }
+
+    pub fn map_size(&self) -> u32 {
+        self.map_size
+    }
+
+    /// recreate the shadow maps at a new resolution
+    pub fn set_map_size(&mut self, map_size: u32) -> Result<(), JsValue> {
+        self.maps = create_maps(&self.gl, map_size, self.depth_texture)?;
+        self.map_size = map_size;
+        Ok(())
+    }
+
+    /// recreate the depth program and shadow maps after the context was lost and restored,
+    /// keeping the settings
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        let pcf_radius = self.pcf_radius;
+        *self = ShadowRenderer::new(&self.gl, self.map_size)?;
+        self.pcf_radius = pcf_radius;
+        Ok(())
+    }
+
+    pub fn pcf_radius(&self) -> i32 {
+        self.pcf_radius
+    }
+
+    /// set how many texels around each lookup are averaged, from 0 (hard shadows) up to
+    /// `MAX_PCF_RADIUS`
+    pub fn set_pcf_radius(&mut self, radius: i32) {
+        self.pcf_radius = radius.clamp(0, MAX_PCF_RADIUS);
+    }
+
+    /// whether depth is packed into rgba textures because depth textures aren't supported
+    pub fn packed_depth(&self) -> bool {
+        !self.depth_texture
+    }
+
+    pub fn maps(&self) -> &[ShadowMap] {
+        &self.maps
+    }
+
+    /// render the depth of every primitive that isn't blended into one shadow map per caster,
+    /// once per instance transform or once untransformed if there are none. Masked primitives
+    /// are cut out by their base color, with its texture looked up in `textures`. Leaves the
+    /// default framebuffer bound but doesn't restore the viewport
+    pub fn render(
+        &self,
+        state: &mut RenderStateTracker,
+        casters: &[ShadowCaster],
+        model: &na::Matrix4<f32>,
+        instances: &[na::Matrix4<f32>],
+        primitives: &[&Primitive],
+        textures: &Pool<TextureHandle, Texture2D>,
+    ) {
+        let identity = [na::Matrix4::identity()];
+        let instances = if instances.is_empty() {
+            &identity
+        } else {
+            instances
+        };
+        self.program.set_used();
+        self.gl
+            .viewport(0, 0, self.map_size as i32, self.map_size as i32);
+        state.apply(
+            &self.gl,
+            &RenderState::default()
+                .with_clear_color(1.0, 1.0, 1.0, 1.0)
+                .with_cull_mode(CullMode::None),
+        );
+        self.gl.enable_vertex_attrib_array(self.position_attrib);
+        self.gl.active_texture(WebGlCtx::TEXTURE0);
+        self.gl.uniform1i(Some(&self.sampler_loc), 0);
+
+        for (caster, map) in casters.iter().zip(&self.maps) {
+            map.bind();
+            self.gl
+                .clear(WebGlCtx::COLOR_BUFFER_BIT | WebGlCtx::DEPTH_BUFFER_BIT);
+            for primitive in primitives
+                .iter()
+                .filter(|primitive| primitive.material.alpha_mode != AlphaMode::Blend)
+            {
+                primitive.vert_buffer.bind();
+                self.gl.vertex_attrib_pointer_with_i32(
+                    self.position_attrib,
+                    3,
+                    WebGlCtx::FLOAT,
+                    false,
+                    0,
+                    0,
+                );
+                self.bind_cutout(primitive, textures);
+                // the depth pass is cheap enough that instances are just drawn one by one
+                for instance in instances {
+                    let light_mvp = caster.view_projection * model * instance;
+                    self.gl.uniform_matrix4fv_with_f32_array(
+                        Some(&self.light_mvp_loc),
+                        false,
+                        light_mvp.as_slice(),
+                    );
+                    primitive.draw(&self.gl);
+                }
+            }
+        }
+
+        self.gl.bind_framebuffer(WebGlCtx::FRAMEBUFFER, None);
+        self.gl.disable_vertex_attrib_array(self.position_attrib);
+        self.gl.disable_vertex_attrib_array(self.tex_coord_attrib);
+    }
+
+    /// set up the alpha test of a primitive, which only masked materials discard by
+    fn bind_cutout(&self, primitive: &Primitive, textures: &Pool<TextureHandle, Texture2D>) {
+        let material = &primitive.material;
+        let alpha_cutoff = match material.alpha_mode {
+            AlphaMode::Mask => material.alpha_cutoff,
+            _ => 0.0,
+        };
+        self.gl
+            .uniform1f(Some(&self.alpha_cutoff_loc), alpha_cutoff);
+        self.gl
+            .uniform4fv_with_f32_array(Some(&self.base_color_loc), &material.base_color);
+        let texture = material
+            .base_color_texture
+            .filter(|_| alpha_cutoff > 0.0)
+            .and_then(|texture| textures.get(texture))
+            .unwrap_or(&self.white_texture);
+        texture.bind();
+        match primitive.tex_coord_buffer {
+            Some(ref tex_coords) => {
+                tex_coords.bind();
+                self.gl.vertex_attrib_pointer_with_i32(
+                    self.tex_coord_attrib,
+                    2,
+                    WebGlCtx::FLOAT,
+                    false,
+                    0,
+                    0,
+                );
+                self.gl.enable_vertex_attrib_array(self.tex_coord_attrib);
+            }
+            None => {
+                self.gl.disable_vertex_attrib_array(self.tex_coord_attrib);
+                self.gl.vertex_attrib2f(self.tex_coord_attrib, 0.0, 0.0);
+            }
+        }
+    }
+}
+
+fn create_maps(gl: &WebGl, size: u32, depth_texture: bool) -> Result<Vec<ShadowMap>, JsValue> {
+    (0..MAX_SHADOW_MAPS)
+        .map(|_| ShadowMap::new(gl, size, depth_texture))
+        .collect()
+}
+
+/// the view projection matrix a light renders its shadow map with, framing a bounding sphere
+/// around the scene. Point lights would need a cube of maps so they don't cast shadows
+pub fn light_view_projection(
+    light: &Light,
+    center: &na::Point3<f32>,
+    radius: f32,
+) -> Option<na::Matrix4<f32>> {
+    let direction = light.direction().try_normalize(f32::EPSILON)?;
+    let up = if direction.y.abs() > 0.99 {
+        na::Vector3::z()
+    } else {
+        na::Vector3::y()
+    };
+    match light.kind() {
+        LightKind::Directional => {
+            let eye = center - direction * radius * 2.0;
+            let view = na::Isometry3::look_at_rh(&eye, center, &up);
+            let projection =
+                na::Orthographic3::new(-radius, radius, -radius, radius, radius, radius * 3.0);
+            Some(projection.as_matrix() * view.to_homogeneous())
+        }
+        LightKind::Spot => {
+            let eye = light.position();
+            let view = na::Isometry3::look_at_rh(&eye, &(eye + direction), &up);
+            let (_, outer_angle) = light.cone_angles();
+            let fovy = (outer_angle * 2.0).min(std::f32::consts::PI - 0.1);
+            let far = if light.range() > 0.0 {
+                light.range()
+            } else {
+                na::distance(&eye, center) + radius
+            };
+            let projection = na::Perspective3::new(1.0, fovy, 0.05, far.max(0.1));
+            Some(projection.as_matrix() * view.to_homogeneous())
+        }
+        LightKind::Point => None,
+    }
+}
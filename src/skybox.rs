@@ -0,0 +1,159 @@
+use nalgebra as na;
+use wasm_bindgen::JsValue;
+use web_sys::{WebGlRenderingContext as WebGlCtx, WebGlUniformLocation};
+
+use crate::gl_abstraction::{GlBuffer, Program, Shader, TextureCube, WebGl};
+use crate::render_state::{CullMode, DepthFunc, RenderState, RenderStateTracker};
+
+const SKYBOX_VERT: &str = include_str!("../assets/shaders/skybox.vert");
+const SKYBOX_FRAG: &str = include_str!("../assets/shaders/skybox.frag");
+
+/// a cube map drawn around the camera after the scene, filling in every pixel the scene
+/// didn't cover
+pub struct Skybox {
+    gl: WebGl,
+    program: Program,
+    position_attrib: u32,
+    view_projection_loc: WebGlUniformLocation,
+    sampler_loc: WebGlUniformLocation,
+    cube: GlBuffer<f32>,
+    texture: TextureCube,
+}
+
+impl Skybox {
+    pub fn new(gl: &WebGl, texture: TextureCube) -> Result<Skybox, JsValue> {
+        let shaders = [
+            Shader::new(gl, WebGlCtx::VERTEX_SHADER, SKYBOX_VERT)?,
+            Shader::new(gl, WebGlCtx::FRAGMENT_SHADER, SKYBOX_FRAG)?,
+        ];
+        let program = Program::new(gl, &shaders)?;
+        let (position_attrib, view_projection_loc, sampler_loc) = locations(gl, &program)?;
+        let cube = GlBuffer::new(gl, WebGlCtx::ARRAY_BUFFER, cube_vertices())?;
+        Ok(Skybox {
+            gl: gl.clone(),
+            program,
+            position_attrib,
+            view_projection_loc,
+            sampler_loc,
+            cube,
+            texture,
+        })
+    }
+
+    /// recreate the program, cube and texture after the context was lost and restored
+    pub fn restore(&mut self) -> Result<(), JsValue> {
+        self.program.restore()?;
+        let (position_attrib, view_projection_loc, sampler_loc) =
+            locations(&self.gl, &self.program)?;
+        self.position_attrib = position_attrib;
+        self.view_projection_loc = view_projection_loc;
+        self.sampler_loc = sampler_loc;
+        self.cube.restore()?;
+        self.texture.restore()
+    }
+
+    pub fn texture(&self) -> &TextureCube {
+        &self.texture
+    }
+
+    /// draw the sky in `base`, the state the scene is drawn in, without culling or depth writes.
+    /// It sits on the far plane, so a LESS depth test is relaxed to LEQUAL to pass where nothing
+    /// has been drawn
+    pub fn draw(
+        &self,
+        state: &mut RenderStateTracker,
+        base: &RenderState,
+        view: &na::Isometry3<f32>,
+        projection: &na::Matrix4<f32>,
+    ) {
+        self.program.set_used();
+        let rotation_only = na::Isometry3::from_parts(na::Translation3::identity(), view.rotation);
+        let view_projection = projection * rotation_only.to_homogeneous();
+        self.gl.uniform_matrix4fv_with_f32_array(
+            Some(&self.view_projection_loc),
+            false,
+            view_projection.as_slice(),
+        );
+
+        self.gl.active_texture(WebGlCtx::TEXTURE0);
+        self.texture.bind();
+        self.gl.uniform1i(Some(&self.sampler_loc), 0);
+
+        let mut sky_state = base
+            .clone()
+            .with_cull_mode(CullMode::None)
+            .with_depth_write(false);
+        if base.depth_func() == DepthFunc::Less {
+            sky_state = sky_state.with_depth_func(DepthFunc::LessOrEqual);
+        }
+        state.apply(&self.gl, &sky_state);
+
+        self.cube.bind();
+        self.gl.vertex_attrib_pointer_with_i32(
+            self.position_attrib,
+            3,
+            WebGlCtx::FLOAT,
+            false,
+            0,
+            0,
+        );
+        self.gl.enable_vertex_attrib_array(self.position_attrib);
+        self.gl
+            .draw_arrays(WebGlCtx::TRIANGLES, 0, (self.cube.array().len() / 3) as i32);
+        self.gl.disable_vertex_attrib_array(self.position_attrib);
+    }
+}
+
+/// the position attribute and the view projection and sampler uniforms of the skybox program
+fn locations(
+    gl: &WebGl,
+    program: &Program,
+) -> Result<(u32, WebGlUniformLocation, WebGlUniformLocation), JsValue> {
+    let position_attrib = gl.get_attrib_location(program, "a_position");
+    if position_attrib == -1 {
+        return Err("position attribute doesn't exist".into());
+    }
+    let view_projection_loc = gl
+        .get_uniform_location(program, "u_view_projection")
+        .ok_or("view_projection uniform doesn't exist")?;
+    let sampler_loc = gl
+        .get_uniform_location(program, "u_skybox")
+        .ok_or("skybox uniform doesn't exist")?;
+    Ok((position_attrib as u32, view_projection_loc, sampler_loc))
+}
+
+/// the 36 vertices of the triangles of a cube spanning -1 to 1
+fn cube_vertices() -> Vec<f32> {
+    // each face as its four corners, split into two triangles below
+    const FACES: [[[f32; 3]; 4]; 6] = [
+        [[1., -1., -1.], [1., -1., 1.], [1., 1., 1.], [1., 1., -1.]],
+        [
+            [-1., -1., 1.],
+            [-1., -1., -1.],
+            [-1., 1., -1.],
+            [-1., 1., 1.],
+        ],
+        [[-1., 1., -1.], [1., 1., -1.], [1., 1., 1.], [-1., 1., 1.]],
+        [
+            [-1., -1., 1.],
+            [1., -1., 1.],
+            [1., -1., -1.],
+            [-1., -1., -1.],
+        ],
+        [[1., -1., 1.], [-1., -1., 1.], [-1., 1., 1.], [1., 1., 1.]],
+        [
+            [-1., -1., -1.],
+            [1., -1., -1.],
+            [1., 1., -1.],
+            [-1., 1., -1.],
+        ],
+    ];
+    FACES
+        .iter()
+        .flat_map(|face| {
+            [0, 1, 2, 0, 2, 3]
+                .iter()
+                .flat_map(move |&corner| face[corner].to_vec())
+        })
+        .collect()
+}